@@ -0,0 +1,907 @@
+//! Command-line tool for operating multidistribute collections and distributions.
+//!
+//! Transactions are signed with a keypair file and sent to the cluster, unless
+//! `--sign-only` prints the signed transaction or `--serialize` prints the bare
+//! instructions, e.g. for multisig and governance proposals.
+
+use std::path::{Path, PathBuf};
+
+use anchor_lang::prelude::{borsh, AnchorSerialize, Pubkey};
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::Discriminator;
+use anchor_spl::associated_token::get_associated_token_address_with_program_id;
+use anyhow::{anyhow, Context, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use clap::{Args, Parser, Subcommand};
+use multidistribute_client::allowlist::Allowlist;
+use multidistribute_client::state::{
+    Collection, CollectionMint, CollectionUserState, CommitBonus, Distribution,
+    DistributionUserState, VestingSchedule,
+};
+use multidistribute_client::{instructions, math, pda, state};
+use serde_json::{json, Value};
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::rpc_client::RpcClient;
+use solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
+use solana_client::rpc_filter::{Memcmp, RpcFilterType};
+use solana_sdk::account::{from_account, Account};
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::hash::Hash;
+use solana_sdk::signature::{read_keypair_file, Keypair, Signer};
+use solana_sdk::sysvar::{self, clock::Clock};
+use solana_sdk::transaction::Transaction;
+use spl_associated_token_account::instruction::create_associated_token_account_idempotent;
+
+#[derive(Parser)]
+#[command(name = "multidistribute", version, about)]
+struct Cli {
+    #[command(flatten)]
+    config: Config,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Args)]
+struct Config {
+    /// RPC URL of the cluster
+    #[arg(short, long, global = true, default_value = "http://127.0.0.1:8899")]
+    url: String,
+
+    /// Keypair file signing and paying for transactions [default: ~/.config/solana/id.json]
+    #[arg(short, long, global = true)]
+    keypair: Option<PathBuf>,
+
+    /// Key acting as authority, user or funder, if it isn't the keypair, e.g. a
+    /// multisig or governance account
+    #[arg(long, global = true)]
+    authority: Option<Pubkey>,
+
+    /// Token program of the mint involved, instead of looking it up on the cluster
+    #[arg(long, global = true)]
+    token_program: Option<Pubkey>,
+
+    /// Print the signed transaction as base64 instead of sending it
+    #[arg(long, global = true, conflicts_with = "serialize")]
+    sign_only: bool,
+
+    /// Blockhash for --sign-only, instead of fetching the latest one
+    #[arg(long, global = true, requires = "sign_only")]
+    blockhash: Option<Hash>,
+
+    /// Print the instructions as base64 instead of sending them, in the format
+    /// governance proposals take
+    #[arg(long, global = true)]
+    serialize: bool,
+
+    /// Print output as JSON
+    #[arg(long, global = true)]
+    json: bool,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Create a collection of a mint
+    InitCollection {
+        #[arg(long)]
+        mint: Pubkey,
+        /// Counter telling apart collections of the same creator and mint
+        #[arg(long)]
+        counter: u64,
+        /// Maximum amount of tokens that can be committed, in base units
+        #[arg(long)]
+        max: u64,
+        /// Burn committed tokens instead of keeping them in the vault
+        #[arg(long)]
+        burn: bool,
+        /// Let the replacement tokens carry the entitlement to distributions
+        #[arg(long)]
+        transferable_claims: bool,
+        /// Unix timestamp until which users can undo their commits
+        #[arg(long)]
+        refund_deadline: Option<i64>,
+        /// CSV file of `<user>,<max amount>` lines allowed to commit
+        #[arg(long)]
+        allowlist: Option<PathBuf>,
+        /// Maximum amount a single user can commit in total, in base units
+        #[arg(long)]
+        max_per_user: Option<u64>,
+        /// Minimum amount of a single commit, in base units
+        #[arg(long, default_value_t = 0)]
+        min_commit: u64,
+        /// Unix timestamp from which commits are accepted
+        #[arg(long)]
+        commit_start: Option<i64>,
+        /// Unix timestamp from which commits are rejected
+        #[arg(long)]
+        commit_end: Option<i64>,
+        /// Unix timestamp until which commits get the full bonus multiplier
+        #[arg(long, requires_all = ["bonus_end", "bonus_multiplier_bps"])]
+        bonus_start: Option<i64>,
+        /// Unix timestamp at which the bonus multiplier has decayed to 1.0
+        #[arg(long, requires = "bonus_start")]
+        bonus_end: Option<i64>,
+        /// Bonus multiplier at the start in basis points, 10000 being 1.0
+        #[arg(long, requires = "bonus_start")]
+        bonus_multiplier_bps: Option<u64>,
+    },
+    /// Create a distribution of a mint for a collection
+    InitDistribution {
+        #[arg(long)]
+        collection: Pubkey,
+        #[arg(long)]
+        mint: Pubkey,
+        /// Unix timestamp after which claims are rejected
+        #[arg(long)]
+        expires_at: Option<i64>,
+        /// Unix timestamp from which the tokens vest linearly
+        #[arg(long, requires_all = ["vesting_cliff", "vesting_end"])]
+        vesting_start: Option<i64>,
+        /// Unix timestamp before which nothing is claimable
+        #[arg(long, requires = "vesting_start")]
+        vesting_cliff: Option<i64>,
+        /// Unix timestamp at which all tokens are vested
+        #[arg(long, requires = "vesting_start")]
+        vesting_end: Option<i64>,
+    },
+    /// Add tokens to a distribution
+    Fund {
+        #[arg(long)]
+        collection: Pubkey,
+        /// Id of the distribution within the collection
+        #[arg(long)]
+        id: u64,
+        /// Amount in base units
+        #[arg(long)]
+        amount: u64,
+        /// Token account to take the tokens from [default: associated token account]
+        #[arg(long)]
+        from: Option<Pubkey>,
+    },
+    /// Commit tokens to a collection
+    Commit {
+        #[arg(long)]
+        collection: Pubkey,
+        /// Mint of the collection, or one added with add-mint
+        #[arg(long)]
+        mint: Pubkey,
+        /// The mint was added with add-mint
+        #[arg(long)]
+        added_mint: bool,
+        /// Amount in base units
+        #[arg(long)]
+        amount: u64,
+        /// Token account to take the tokens from [default: associated token account]
+        #[arg(long)]
+        from: Option<Pubkey>,
+        /// Allowlist CSV file of the collection, to prove the user's entry
+        #[arg(long)]
+        allowlist: Option<PathBuf>,
+    },
+    /// Claim from one or more distributions of a collection
+    Claim {
+        #[arg(long)]
+        collection: Pubkey,
+        /// Ids of the distributions to claim from
+        #[arg(long = "id", required = true)]
+        ids: Vec<u64>,
+    },
+    /// Decrease the maximum amount of tokens a collection accepts
+    DecreaseMax {
+        #[arg(long)]
+        collection: Pubkey,
+        /// New maximum in base units
+        #[arg(long)]
+        max: u64,
+    },
+    /// Decrease the maximum amount of tokens a single user can commit
+    DecreaseMaxPerUser {
+        #[arg(long)]
+        collection: Pubkey,
+        /// New maximum per user in base units
+        #[arg(long)]
+        max: u64,
+    },
+    /// Decrease the minimum amount of a single commit
+    DecreaseMinCommit {
+        #[arg(long)]
+        collection: Pubkey,
+        /// New minimum in base units
+        #[arg(long)]
+        min: u64,
+    },
+    /// Extend or close early the commit window of a collection
+    SetCommitEnd {
+        #[arg(long)]
+        collection: Pubkey,
+        /// Unix timestamp from which commits are rejected, a past one such as 0 to
+        /// close the window now [default: no end]
+        #[arg(long)]
+        end: Option<i64>,
+    },
+    /// Set or remove the allowlist of a collection
+    SetAllowlist {
+        #[arg(long)]
+        collection: Pubkey,
+        /// CSV file of `<user>,<max amount>` lines allowed to commit [default: anyone]
+        #[arg(long)]
+        allowlist: Option<PathBuf>,
+    },
+    /// Accept another mint in a collection, at a weight relative to its own mint
+    AddMint {
+        #[arg(long)]
+        collection: Pubkey,
+        #[arg(long)]
+        mint: Pubkey,
+        /// Weighted units credited per --denominator tokens
+        #[arg(long)]
+        numerator: u64,
+        /// Number of tokens worth --numerator weighted units
+        #[arg(long)]
+        denominator: u64,
+    },
+    /// Stop accepting an added mint, whose vault must be empty
+    CloseMint {
+        #[arg(long)]
+        collection: Pubkey,
+        #[arg(long)]
+        mint: Pubkey,
+    },
+    /// Withdraw all tokens from a collection's vault
+    Withdraw {
+        #[arg(long)]
+        collection: Pubkey,
+        /// Mint of the collection, or one added with add-mint
+        #[arg(long)]
+        mint: Pubkey,
+        /// The mint was added with add-mint
+        #[arg(long)]
+        added_mint: bool,
+        /// Token account to receive the tokens [default: associated token account]
+        #[arg(long)]
+        to: Option<Pubkey>,
+    },
+    /// Show a collection and its distributions
+    Show {
+        #[arg(long)]
+        collection: Pubkey,
+        /// Also show the deposit and claimable amounts of this user
+        #[arg(long)]
+        user: Option<Pubkey>,
+    },
+}
+
+struct App {
+    rpc: RpcClient,
+    config: Config,
+    /// The keypair, or why it couldn't be read
+    keypair: Result<Keypair, String>,
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let keypair_path = match &cli.config.keypair {
+        Some(path) => path.clone(),
+        None => PathBuf::from(std::env::var("HOME").context("HOME is not set")?)
+            .join(".config/solana/id.json"),
+    };
+    // Only signing needs the keypair, so a missing one is reported when it's used
+    let keypair = read_keypair_file(&keypair_path)
+        .map_err(|err| format!("failed to read keypair {}: {}", keypair_path.display(), err));
+    let ctx = App {
+        rpc: RpcClient::new_with_commitment(cli.config.url.clone(), CommitmentConfig::confirmed()),
+        config: cli.config,
+        keypair,
+    };
+
+    let output = run(&ctx, cli.command)?;
+    if ctx.config.json {
+        println!("{}", serde_json::to_string_pretty(&output)?);
+    } else {
+        print_value(&output, 0);
+    }
+    Ok(())
+}
+
+fn run(ctx: &App, command: Command) -> Result<Value> {
+    // Showing accounts only reads, so it works without a keypair
+    if let Command::Show { collection, user } = command {
+        return show(ctx, &collection, user.as_ref());
+    }
+    let signer = ctx.signer()?;
+    let instructions = match command {
+        Command::InitCollection {
+            mint,
+            counter,
+            max,
+            burn,
+            transferable_claims,
+            refund_deadline,
+            allowlist,
+            max_per_user,
+            min_commit,
+            commit_start,
+            commit_end,
+            bonus_start,
+            bonus_end,
+            bonus_multiplier_bps,
+        } => {
+            let collection = pda::find_collection(&signer, &mint, counter).0;
+            let allowlist_root = allowlist
+                .map(|path| read_allowlist(&path))
+                .transpose()?
+                .and_then(|allowlist| allowlist.root());
+            let commit_bonus = match (bonus_start, bonus_end, bonus_multiplier_bps) {
+                (Some(start_ts), Some(end_ts), Some(start_multiplier_bps)) => Some(CommitBonus {
+                    start_ts,
+                    end_ts,
+                    start_multiplier_bps,
+                }),
+                _ => None,
+            };
+            let output = ctx.execute(vec![instructions::init_collection(
+                &signer,
+                &mint,
+                &ctx.token_program(&mint)?,
+                multidistribute::instruction::InitCollection {
+                    counter,
+                    max_collectable_tokens: max,
+                    burn_tokens: burn,
+                    transferable_claims,
+                    refund_deadline,
+                    allowlist_root,
+                    max_per_user,
+                    min_commit,
+                    commit_start,
+                    commit_end,
+                    commit_bonus,
+                },
+            )])?;
+            return Ok(with_field(output, "collection", collection.to_string()));
+        }
+        Command::InitDistribution {
+            collection,
+            mint,
+            expires_at,
+            vesting_start,
+            vesting_cliff,
+            vesting_end,
+        } => {
+            let vesting = match (vesting_start, vesting_cliff, vesting_end) {
+                (Some(start_ts), Some(cliff_ts), Some(end_ts)) => Some(VestingSchedule {
+                    start_ts,
+                    cliff_ts,
+                    end_ts,
+                }),
+                _ => None,
+            };
+            // The new distribution gets the next id of the collection
+            let id = ctx.collection(&collection)?.distribution_count;
+            let distribution = pda::find_distribution(&collection, id).0;
+            let output = ctx.execute(vec![instructions::init_distribution(
+                &collection,
+                id,
+                &signer,
+                &mint,
+                &ctx.token_program(&mint)?,
+                expires_at,
+                vesting,
+            )])?;
+            let output = with_field(output, "distribution", distribution.to_string());
+            return Ok(with_field(output, "id", id.to_string()));
+        }
+        Command::Fund {
+            collection,
+            id,
+            amount,
+            from,
+        } => {
+            let (distribution, mint) = ctx.distribution(&collection, id)?;
+            let token_program = ctx.token_program(&mint)?;
+            let from = from.unwrap_or_else(|| {
+                get_associated_token_address_with_program_id(&signer, &mint, &token_program)
+            });
+            vec![instructions::add_distribution_tokens(
+                &distribution,
+                &mint,
+                &signer,
+                &from,
+                &token_program,
+                amount,
+            )]
+        }
+        Command::Commit {
+            collection,
+            mint,
+            added_mint,
+            amount,
+            from,
+            allowlist,
+        } => {
+            let token_program = ctx.token_program(&mint)?;
+            let from = from.unwrap_or_else(|| {
+                get_associated_token_address_with_program_id(&signer, &mint, &token_program)
+            });
+            let allowlist_proof = match allowlist {
+                Some(path) => Some(
+                    read_allowlist(&path)?
+                        .proof(&signer)
+                        .ok_or_else(|| anyhow!("{signer} is not on the allowlist"))?,
+                ),
+                None => None,
+            };
+            let commit = if added_mint {
+                instructions::user_commit_added_mint_to_collection
+            } else {
+                instructions::user_commit_to_collection
+            };
+            vec![commit(
+                &collection,
+                &mint,
+                &signer,
+                &from,
+                &token_program,
+                amount,
+                allowlist_proof,
+            )]
+        }
+        Command::Claim { collection, ids } => {
+            let distributions = ids
+                .iter()
+                .map(|&id| {
+                    let mint = ctx.distribution(&collection, id)?.1;
+                    Ok((id, mint, ctx.token_program(&mint)?))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            // Claims pay out to the user's associated token accounts, which must exist
+            let mut instructions: Vec<_> = distributions
+                .iter()
+                .map(|(_, mint, token_program)| {
+                    create_associated_token_account_idempotent(
+                        &signer,
+                        &signer,
+                        mint,
+                        token_program,
+                    )
+                })
+                .collect();
+            // A batch claim takes a single token program, so claim once per program
+            let mut groups: Vec<(Pubkey, Vec<(u64, Pubkey)>)> = Vec::new();
+            for &(id, mint, token_program) in &distributions {
+                match groups
+                    .iter_mut()
+                    .find(|(program, _)| *program == token_program)
+                {
+                    Some((_, group)) => group.push((id, mint)),
+                    None => groups.push((token_program, vec![(id, mint)])),
+                }
+            }
+            for (token_program, group) in &groups {
+                instructions.push(if let [(id, mint)] = group.as_slice() {
+                    instructions::user_claim_from_distribution(
+                        &collection,
+                        *id,
+                        mint,
+                        &signer,
+                        token_program,
+                    )
+                } else {
+                    instructions::user_claim_from_distributions(
+                        &collection,
+                        group,
+                        &signer,
+                        token_program,
+                    )
+                });
+            }
+            instructions
+        }
+        Command::DecreaseMax { collection, max } => {
+            vec![instructions::decrease_collection_max_collectable_tokens(
+                &collection,
+                &signer,
+                max,
+            )]
+        }
+        Command::DecreaseMaxPerUser { collection, max } => {
+            vec![instructions::decrease_collection_max_per_user(
+                &collection,
+                &signer,
+                max,
+            )]
+        }
+        Command::DecreaseMinCommit { collection, min } => {
+            vec![instructions::decrease_collection_min_commit(
+                &collection,
+                &signer,
+                min,
+            )]
+        }
+        Command::SetCommitEnd { collection, end } => {
+            vec![instructions::set_collection_commit_end(
+                &collection,
+                &signer,
+                end,
+            )]
+        }
+        Command::SetAllowlist {
+            collection,
+            allowlist,
+        } => {
+            let allowlist_root = match allowlist {
+                Some(path) => Some(
+                    read_allowlist(&path)?
+                        .root()
+                        .ok_or_else(|| anyhow!("the allowlist is empty"))?,
+                ),
+                None => None,
+            };
+            vec![instructions::set_collection_allowlist(
+                &collection,
+                &signer,
+                allowlist_root,
+            )]
+        }
+        Command::AddMint {
+            collection,
+            mint,
+            numerator,
+            denominator,
+        } => {
+            vec![instructions::add_collection_mint(
+                &collection,
+                &mint,
+                &signer,
+                &ctx.token_program(&mint)?,
+                numerator,
+                denominator,
+            )]
+        }
+        Command::CloseMint { collection, mint } => {
+            vec![instructions::close_collection_mint(
+                &collection,
+                &mint,
+                &signer,
+                &ctx.token_program(&mint)?,
+            )]
+        }
+        Command::Withdraw {
+            collection,
+            mint,
+            added_mint,
+            to,
+        } => {
+            let token_program = ctx.token_program(&mint)?;
+            let to = to.unwrap_or_else(|| {
+                get_associated_token_address_with_program_id(&signer, &mint, &token_program)
+            });
+            let withdraw = if added_mint {
+                instructions::withdraw_added_mint_from_collection
+            } else {
+                instructions::withdraw_from_collection
+            };
+            vec![withdraw(&collection, &mint, &signer, &to, &token_program)]
+        }
+        Command::Show { .. } => unreachable!("show is handled above"),
+    };
+    ctx.execute(instructions)
+}
+
+impl App {
+    /// Key acting in the instructions: --authority, or the keypair
+    fn signer(&self) -> Result<Pubkey> {
+        match &self.config.authority {
+            Some(authority) => Ok(*authority),
+            None => Ok(self.keypair()?.pubkey()),
+        }
+    }
+
+    fn keypair(&self) -> Result<&Keypair> {
+        self.keypair.as_ref().map_err(|err| anyhow!("{err}"))
+    }
+
+    fn collection(&self, collection: &Pubkey) -> Result<Collection> {
+        let data = self
+            .rpc
+            .get_account_data(collection)
+            .with_context(|| format!("failed to fetch collection {collection}"))?;
+        Ok(state::collection(&data)?)
+    }
+
+    /// Data of the account at `key`, or None if it doesn't exist
+    fn account_data(&self, key: &Pubkey) -> Result<Option<Vec<u8>>> {
+        let account = self
+            .rpc
+            .get_account_with_commitment(key, self.rpc.commitment())?
+            .value;
+        Ok(account.map(|account| account.data))
+    }
+
+    /// Address and mint of distribution `id` of `collection`
+    fn distribution(&self, collection: &Pubkey, id: u64) -> Result<(Pubkey, Pubkey)> {
+        let distribution = pda::find_distribution(collection, id).0;
+        let data = self
+            .rpc
+            .get_account_data(&distribution)
+            .with_context(|| format!("failed to fetch distribution {id} of {collection}"))?;
+        Ok((distribution, state::distribution(&data)?.mint))
+    }
+
+    /// Program accounts of the type with `discriminator` belonging to `collection`,
+    /// which all store the collection key first
+    fn collection_accounts(
+        &self,
+        collection: &Pubkey,
+        discriminator: [u8; 8],
+    ) -> Result<Vec<(Pubkey, Account)>> {
+        Ok(self.rpc.get_program_accounts_with_config(
+            &multidistribute_client::ID,
+            RpcProgramAccountsConfig {
+                filters: Some(vec![
+                    RpcFilterType::Memcmp(Memcmp::new_raw_bytes(0, discriminator.to_vec())),
+                    RpcFilterType::Memcmp(Memcmp::new_raw_bytes(8, collection.to_bytes().to_vec())),
+                ]),
+                account_config: RpcAccountInfoConfig {
+                    encoding: Some(UiAccountEncoding::Base64),
+                    ..RpcAccountInfoConfig::default()
+                },
+                ..RpcProgramAccountsConfig::default()
+            },
+        )?)
+    }
+
+    /// Token program owning `mint`, from --token-program or the cluster
+    fn token_program(&self, mint: &Pubkey) -> Result<Pubkey> {
+        if let Some(token_program) = self.config.token_program {
+            return Ok(token_program);
+        }
+        let account = self
+            .rpc
+            .get_account(mint)
+            .with_context(|| format!("failed to fetch mint {mint}, pass --token-program"))?;
+        Ok(account.owner)
+    }
+
+    /// Sends the instructions, or prints them according to --sign-only and --serialize
+    fn execute(&self, instructions: Vec<Instruction>) -> Result<Value> {
+        if self.config.serialize {
+            let serialized = instructions
+                .iter()
+                .map(serialize_instruction)
+                .collect::<Result<Vec<_>>>()?;
+            return Ok(json!({ "instructions": serialized }));
+        }
+
+        let keypair = self.keypair()?;
+        let mut transaction = Transaction::new_with_payer(&instructions, Some(&keypair.pubkey()));
+        let blockhash = match self.config.blockhash {
+            Some(blockhash) => blockhash,
+            None => self.rpc.get_latest_blockhash()?,
+        };
+
+        if self.config.sign_only {
+            // Other signers, like a multisig authority, sign the printed transaction later
+            transaction.try_partial_sign(&[keypair], blockhash)?;
+            return Ok(json!({
+                "blockhash": blockhash.to_string(),
+                "transaction": BASE64.encode(bincode::serialize(&transaction)?),
+            }));
+        }
+
+        transaction.try_sign(&[keypair], blockhash)?;
+        let signature = self.rpc.send_and_confirm_transaction(&transaction)?;
+        Ok(json!({ "signature": signature.to_string() }))
+    }
+}
+
+/// Instruction in the layout of spl-governance's InstructionData, which governance
+/// proposals and their UIs take as base64
+#[derive(AnchorSerialize)]
+struct GovernanceInstruction {
+    program_id: Pubkey,
+    accounts: Vec<GovernanceAccountMeta>,
+    data: Vec<u8>,
+}
+
+#[derive(AnchorSerialize)]
+struct GovernanceAccountMeta {
+    pubkey: Pubkey,
+    is_signer: bool,
+    is_writable: bool,
+}
+
+fn serialize_instruction(instruction: &Instruction) -> Result<String> {
+    let instruction = GovernanceInstruction {
+        program_id: instruction.program_id,
+        accounts: instruction
+            .accounts
+            .iter()
+            .map(|meta| GovernanceAccountMeta {
+                pubkey: meta.pubkey,
+                is_signer: meta.is_signer,
+                is_writable: meta.is_writable,
+            })
+            .collect(),
+        data: instruction.data.clone(),
+    };
+    Ok(BASE64.encode(instruction.try_to_vec()?))
+}
+
+fn show(ctx: &App, collection_key: &Pubkey, user: Option<&Pubkey>) -> Result<Value> {
+    let collection = ctx.collection(collection_key)?;
+    let clock: Clock = from_account(&ctx.rpc.get_account(&sysvar::clock::ID)?)
+        .ok_or_else(|| anyhow!("failed to read the clock"))?;
+
+    let distributions = ctx.collection_accounts(collection_key, Distribution::DISCRIMINATOR)?;
+    let mut collection_mints = ctx
+        .collection_accounts(collection_key, CollectionMint::DISCRIMINATOR)?
+        .into_iter()
+        .map(|(key, account)| Ok((key, state::collection_mint(&account.data)?)))
+        .collect::<Result<Vec<_>>>()?;
+    collection_mints.sort_by_key(|(_, collection_mint)| collection_mint.mint);
+    let collection_mint_values: Vec<_> = collection_mints
+        .iter()
+        .map(|(key, collection_mint)| {
+            json!({
+                "address": key.to_string(),
+                "mint": collection_mint.mint.to_string(),
+                "vault": collection_mint.vault.to_string(),
+                "weight_numerator": collection_mint.weight_numerator,
+                "weight_denominator": collection_mint.weight_denominator,
+                "lifetime_tokens_collected": collection_mint.lifetime_tokens_collected,
+            })
+        })
+        .collect();
+
+    let user_state = match user {
+        Some(user) => {
+            let key = pda::find_collection_user_state(collection_key, user).0;
+            Some(match ctx.account_data(&key)? {
+                Some(data) => state::collection_user_state(&data)?,
+                None => CollectionUserState {
+                    deposited_amount: 0,
+                    received_distribution: false,
+                },
+            })
+        }
+        None => None,
+    };
+
+    let mut distributions = distributions
+        .into_iter()
+        .map(|(key, account)| Ok((key, state::distribution(&account.data)?)))
+        .collect::<Result<Vec<_>>>()?;
+    distributions.sort_by_key(|(_, distribution)| distribution.id);
+
+    let mut distribution_values = Vec::new();
+    for (key, distribution) in distributions {
+        let mut value = json!({
+            "address": key.to_string(),
+            "id": distribution.id,
+            "mint": distribution.mint.to_string(),
+            "vault": distribution.vault.to_string(),
+            "lifetime_deposited_tokens": distribution.lifetime_deposited_tokens,
+            "distributed_tokens": distribution.distributed_tokens,
+            "clawed_back_tokens": distribution.clawed_back_tokens,
+            "expires_at": distribution.expires_at,
+            "swept": distribution.swept,
+            "paused": distribution.paused,
+        });
+        if let (Some(user), Some(user_state)) = (user, &user_state) {
+            let distribution_user_state = ctx
+                .account_data(&pda::find_distribution_user_state(&key, user).0)?
+                .map(|data| state::distribution_user_state(&data))
+                .transpose()?;
+            value["received_amount"] = json!(distribution_user_state
+                .as_ref()
+                .map_or(0, |state: &DistributionUserState| state.received_amount));
+            value["claimable_amount"] = json!(math::claimable_amount(
+                &collection,
+                &distribution,
+                user_state,
+                distribution_user_state.as_ref(),
+                clock.unix_timestamp,
+            )?);
+        }
+        distribution_values.push(value);
+    }
+
+    let mut output = json!({
+        "address": collection_key.to_string(),
+        "authority": collection.authority.to_string(),
+        "pending_authority": collection.pending_authority.map(|key| key.to_string()),
+        "guardian": collection.guardian.map(|key| key.to_string()),
+        "creator": collection.creator.to_string(),
+        "mint": collection.mint.to_string(),
+        "vault": collection.vault.to_string(),
+        "replacement_mint": collection.replacement_mint.to_string(),
+        "counter": collection.counter,
+        "lifetime_tokens_collected": collection.lifetime_tokens_collected,
+        "max_collectable_tokens": collection.max_collectable_tokens,
+        "burn_tokens": collection.burn_tokens,
+        "transferable_claims": collection.transferable_claims,
+        "refund_deadline": collection.refund_deadline,
+        "allowlist_root": collection.allowlist_root.map(hex::encode),
+        "max_per_user": collection.max_per_user,
+        "min_commit": collection.min_commit,
+        "commit_start": collection.commit_start,
+        "commit_end": collection.commit_end,
+        "commit_bonus": collection.commit_bonus.map(|commit_bonus| json!({
+            "start_ts": commit_bonus.start_ts,
+            "end_ts": commit_bonus.end_ts,
+            "start_multiplier_bps": commit_bonus.start_multiplier_bps,
+        })),
+        "finalized": collection.finalized,
+        "commits_closed": collection.commits_closed,
+        "paused": collection.paused,
+        "added_mints": collection_mint_values,
+        "distribution_count": collection.distribution_count,
+        "open_distributions": collection.open_distributions,
+        "distributions": distribution_values,
+    });
+    if let Some(user_state) = &user_state {
+        output["deposited_amount"] = json!(user_state.deposited_amount);
+    }
+    Ok(output)
+}
+
+/// Reads an allowlist from a CSV file of `<user>,<max amount>` lines
+fn read_allowlist(path: &Path) -> Result<Allowlist> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read allowlist {}", path.display()))?;
+    let entries = contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let (user, max_amount) = line
+                .split_once(',')
+                .ok_or_else(|| anyhow!("invalid allowlist line {line:?}"))?;
+            Ok((user.trim().parse()?, max_amount.trim().parse()?))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Allowlist::new(entries))
+}
+
+fn with_field(mut output: Value, key: &str, value: String) -> Value {
+    output[key] = Value::String(value);
+    output
+}
+
+/// Prints a JSON value as indented `key: value` lines
+fn print_value(value: &Value, indent: usize) {
+    let padding = "  ".repeat(indent);
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                match value {
+                    Value::Object(_) | Value::Array(_) => {
+                        println!("{padding}{key}:");
+                        print_value(value, indent + 1);
+                    }
+                    _ => println!("{padding}{key}: {}", scalar(value)),
+                }
+            }
+        }
+        Value::Array(values) => {
+            for (index, value) in values.iter().enumerate() {
+                match value {
+                    Value::Object(_) | Value::Array(_) => {
+                        println!("{padding}- [{index}]");
+                        print_value(value, indent + 1);
+                    }
+                    _ => println!("{padding}- {}", scalar(value)),
+                }
+            }
+        }
+        _ => println!("{padding}{}", scalar(value)),
+    }
+}
+
+fn scalar(value: &Value) -> String {
+    match value {
+        Value::String(value) => value.clone(),
+        Value::Null => "-".to_string(),
+        value => value.to_string(),
+    }
+}
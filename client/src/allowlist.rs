@@ -0,0 +1,60 @@
+//! Merkle trees of commit allowlists.
+
+use anchor_lang::prelude::Pubkey;
+use multidistribute::AllowlistProof;
+
+/// Merkle tree over the (user, max_amount) entries of a collection's allowlist.
+///
+/// Leaves are hashed in the order given. A node without a sibling on its level is
+/// carried up unchanged, so its proof has no entry for that level.
+pub struct Allowlist {
+    entries: Vec<(Pubkey, u64)>,
+    /// Levels of the tree from the leaves up to the root
+    levels: Vec<Vec<[u8; 32]>>,
+}
+
+impl Allowlist {
+    pub fn new(entries: Vec<(Pubkey, u64)>) -> Self {
+        let leaves: Vec<_> = entries
+            .iter()
+            .map(|(user, max_amount)| AllowlistProof::leaf(user, *max_amount))
+            .collect();
+        let mut levels = vec![leaves];
+        while levels.last().unwrap().len() > 1 {
+            let level = levels
+                .last()
+                .unwrap()
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => AllowlistProof::parent(left, right),
+                    [node] => *node,
+                    _ => unreachable!(),
+                })
+                .collect();
+            levels.push(level);
+        }
+        Self { entries, levels }
+    }
+
+    /// Root to set on the collection, None for an empty allowlist
+    pub fn root(&self) -> Option<[u8; 32]> {
+        self.levels.last().unwrap().first().copied()
+    }
+
+    /// Proof of the first entry of `user`, None if the user isn't allowlisted
+    pub fn proof(&self, user: &Pubkey) -> Option<AllowlistProof> {
+        let position = self.entries.iter().position(|(key, _)| key == user)?;
+        let mut index = position;
+        let mut proof = Vec::new();
+        for level in &self.levels[..self.levels.len() - 1] {
+            if let Some(sibling) = level.get(index ^ 1) {
+                proof.push(*sibling);
+            }
+            index /= 2;
+        }
+        Some(AllowlistProof {
+            max_amount: self.entries[position].1,
+            proof,
+        })
+    }
+}
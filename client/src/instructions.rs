@@ -0,0 +1,809 @@
+//! Builders for the program's instructions.
+//!
+//! Each builder derives the PDAs, vaults and associated token accounts involved,
+//! so callers only pass the keys that can't be derived. `token_program` is the
+//! program owning the mint involved, SPL Token or Token-2022.
+
+use anchor_lang::prelude::{AccountMeta, Pubkey};
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::sysvar;
+use anchor_lang::{system_program, Id, InstructionData, ToAccountMetas};
+use anchor_spl::associated_token::{self, get_associated_token_address_with_program_id};
+use anchor_spl::metadata::Metadata;
+use multidistribute::{accounts, instruction, AllowlistProof, VestingSchedule};
+
+use crate::pda;
+
+fn build(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: crate::ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+fn event_authority() -> Pubkey {
+    pda::find_event_authority().0
+}
+
+/// Creates a collection of `mint` tokens, with `authority` as creator and authority
+pub fn init_collection(
+    authority: &Pubkey,
+    mint: &Pubkey,
+    token_program: &Pubkey,
+    args: instruction::InitCollection,
+) -> Instruction {
+    let collection = pda::find_collection(authority, mint, args.counter).0;
+    build(
+        accounts::InitCollection {
+            collection,
+            mint: *mint,
+            vault: pda::vault(&collection, mint, token_program),
+            replacement_mint: pda::find_replacement_mint(&collection).0,
+            authority: *authority,
+            system_program: system_program::ID,
+            token_program: *token_program,
+            associated_token_program: associated_token::ID,
+            rent: sysvar::rent::ID,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        args,
+    )
+}
+
+pub fn propose_authority(
+    collection: &Pubkey,
+    authority: &Pubkey,
+    new_authority: &Pubkey,
+) -> Instruction {
+    build(
+        accounts::ProposeAuthority {
+            collection: *collection,
+            authority: *authority,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::ProposeAuthority {
+            new_authority: *new_authority,
+        },
+    )
+}
+
+pub fn accept_authority(collection: &Pubkey, new_authority: &Pubkey) -> Instruction {
+    build(
+        accounts::AcceptAuthority {
+            collection: *collection,
+            new_authority: *new_authority,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::AcceptAuthority {},
+    )
+}
+
+pub fn set_collection_guardian(
+    collection: &Pubkey,
+    authority: &Pubkey,
+    guardian: Option<Pubkey>,
+) -> Instruction {
+    build(
+        accounts::SetCollectionGuardian {
+            collection: *collection,
+            authority: *authority,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::SetCollectionGuardian { guardian },
+    )
+}
+
+pub fn set_collection_allowlist(
+    collection: &Pubkey,
+    authority: &Pubkey,
+    allowlist_root: Option<[u8; 32]>,
+) -> Instruction {
+    build(
+        accounts::SetCollectionAllowlist {
+            collection: *collection,
+            authority: *authority,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::SetCollectionAllowlist { allowlist_root },
+    )
+}
+
+pub fn set_replacement_mint_metadata(
+    collection: &Pubkey,
+    authority: &Pubkey,
+    name: String,
+    symbol: String,
+    uri: String,
+) -> Instruction {
+    let replacement_mint = pda::find_replacement_mint(collection).0;
+    build(
+        accounts::SetReplacementMintMetadata {
+            collection: *collection,
+            replacement_mint,
+            metadata: pda::find_metadata(&replacement_mint).0,
+            authority: *authority,
+            token_metadata_program: Metadata::id(),
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::SetReplacementMintMetadata { name, symbol, uri },
+    )
+}
+
+/// `signer` is the collection's authority or guardian
+pub fn set_collection_paused(collection: &Pubkey, signer: &Pubkey, paused: bool) -> Instruction {
+    build(
+        accounts::SetCollectionPaused {
+            collection: *collection,
+            signer: *signer,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::SetCollectionPaused { paused },
+    )
+}
+
+/// `signer` is the collection's authority or guardian
+pub fn set_distribution_paused(
+    collection: &Pubkey,
+    distribution: &Pubkey,
+    signer: &Pubkey,
+    paused: bool,
+) -> Instruction {
+    build(
+        accounts::SetDistributionPaused {
+            collection: *collection,
+            distribution: *distribution,
+            signer: *signer,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::SetDistributionPaused { paused },
+    )
+}
+
+pub fn decrease_collection_max_collectable_tokens(
+    collection: &Pubkey,
+    authority: &Pubkey,
+    new_max_collectable_tokens: u64,
+) -> Instruction {
+    build(
+        accounts::DecreaseCollectionMaxTokens {
+            collection: *collection,
+            authority: *authority,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::DecreaseCollectionMaxCollectableTokens {
+            new_max_collectable_tokens,
+        },
+    )
+}
+
+pub fn decrease_collection_max_per_user(
+    collection: &Pubkey,
+    authority: &Pubkey,
+    new_max_per_user: u64,
+) -> Instruction {
+    build(
+        accounts::DecreaseCollectionMaxPerUser {
+            collection: *collection,
+            authority: *authority,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::DecreaseCollectionMaxPerUser { new_max_per_user },
+    )
+}
+
+pub fn decrease_collection_min_commit(
+    collection: &Pubkey,
+    authority: &Pubkey,
+    new_min_commit: u64,
+) -> Instruction {
+    build(
+        accounts::DecreaseCollectionMinCommit {
+            collection: *collection,
+            authority: *authority,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::DecreaseCollectionMinCommit { new_min_commit },
+    )
+}
+
+pub fn set_collection_commit_end(
+    collection: &Pubkey,
+    authority: &Pubkey,
+    commit_end: Option<i64>,
+) -> Instruction {
+    build(
+        accounts::SetCollectionCommitEnd {
+            collection: *collection,
+            authority: *authority,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::SetCollectionCommitEnd { commit_end },
+    )
+}
+
+/// Accepts `mint` in the collection, credited at `weight_numerator` weighted units
+/// per `weight_denominator` tokens. `token_program` is the program owning both
+/// `mint` and the collection's replacement mint.
+pub fn add_collection_mint(
+    collection: &Pubkey,
+    mint: &Pubkey,
+    authority: &Pubkey,
+    token_program: &Pubkey,
+    weight_numerator: u64,
+    weight_denominator: u64,
+) -> Instruction {
+    build(
+        accounts::AddCollectionMint {
+            collection: *collection,
+            collection_mint: pda::find_collection_mint(collection, mint).0,
+            mint: *mint,
+            vault: pda::vault(collection, mint, token_program),
+            replacement_mint: pda::find_replacement_mint(collection).0,
+            authority: *authority,
+            system_program: system_program::ID,
+            token_program: *token_program,
+            associated_token_program: associated_token::ID,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::AddCollectionMint {
+            weight_numerator,
+            weight_denominator,
+        },
+    )
+}
+
+/// `mint` is an added mint, whose vault must be empty
+pub fn close_collection_mint(
+    collection: &Pubkey,
+    mint: &Pubkey,
+    authority: &Pubkey,
+    token_program: &Pubkey,
+) -> Instruction {
+    build(
+        accounts::CloseCollectionMint {
+            collection: *collection,
+            collection_mint: pda::find_collection_mint(collection, mint).0,
+            vault: pda::vault(collection, mint, token_program),
+            mint: *mint,
+            authority: *authority,
+            token_program: *token_program,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::CloseCollectionMint {},
+    )
+}
+
+pub fn finalize_collection(collection: &Pubkey, authority: &Pubkey) -> Instruction {
+    build(
+        accounts::FinalizeCollection {
+            collection: *collection,
+            authority: *authority,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::FinalizeCollection {},
+    )
+}
+
+pub fn close_collection_commits(collection: &Pubkey, authority: &Pubkey) -> Instruction {
+    build(
+        accounts::CloseCollectionCommits {
+            collection: *collection,
+            authority: *authority,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::CloseCollectionCommits {},
+    )
+}
+
+/// `mint` is the collection's mint
+pub fn withdraw_from_collection(
+    collection: &Pubkey,
+    mint: &Pubkey,
+    authority: &Pubkey,
+    authority_token_account: &Pubkey,
+    token_program: &Pubkey,
+) -> Instruction {
+    withdraw(
+        collection,
+        None,
+        mint,
+        authority,
+        authority_token_account,
+        token_program,
+    )
+}
+
+/// `mint` is an added mint of the collection
+pub fn withdraw_added_mint_from_collection(
+    collection: &Pubkey,
+    mint: &Pubkey,
+    authority: &Pubkey,
+    authority_token_account: &Pubkey,
+    token_program: &Pubkey,
+) -> Instruction {
+    withdraw(
+        collection,
+        Some(pda::find_collection_mint(collection, mint).0),
+        mint,
+        authority,
+        authority_token_account,
+        token_program,
+    )
+}
+
+fn withdraw(
+    collection: &Pubkey,
+    collection_mint: Option<Pubkey>,
+    mint: &Pubkey,
+    authority: &Pubkey,
+    authority_token_account: &Pubkey,
+    token_program: &Pubkey,
+) -> Instruction {
+    build(
+        accounts::WithdrawFromCollection {
+            collection: *collection,
+            collection_mint,
+            vault: pda::vault(collection, mint, token_program),
+            mint: *mint,
+            authority_token_account: *authority_token_account,
+            authority: *authority,
+            token_program: *token_program,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::WithdrawFromCollection {},
+    )
+}
+
+/// `mint` is the collection's mint
+pub fn close_collection(
+    collection: &Pubkey,
+    mint: &Pubkey,
+    authority: &Pubkey,
+    token_program: &Pubkey,
+) -> Instruction {
+    build(
+        accounts::CloseCollection {
+            collection: *collection,
+            vault: pda::vault(collection, mint, token_program),
+            mint: *mint,
+            replacement_mint: pda::find_replacement_mint(collection).0,
+            authority: *authority,
+            token_program: *token_program,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::CloseCollection {},
+    )
+}
+
+/// Creates a distribution of `mint` tokens for `collection`. `id` must be the
+/// collection's current distribution_count, which the new distribution is numbered by.
+pub fn init_distribution(
+    collection: &Pubkey,
+    id: u64,
+    authority: &Pubkey,
+    mint: &Pubkey,
+    token_program: &Pubkey,
+    expires_at: Option<i64>,
+    vesting: Option<VestingSchedule>,
+) -> Instruction {
+    let distribution = pda::find_distribution(collection, id).0;
+    build(
+        accounts::InitDistribution {
+            distribution,
+            collection: *collection,
+            mint: *mint,
+            vault: pda::vault(&distribution, mint, token_program),
+            authority: *authority,
+            system_program: system_program::ID,
+            token_program: *token_program,
+            associated_token_program: associated_token::ID,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::InitDistribution {
+            expires_at,
+            vesting,
+        },
+    )
+}
+
+/// `authority` is whoever funds the distribution from `authority_token_account`
+pub fn add_distribution_tokens(
+    distribution: &Pubkey,
+    mint: &Pubkey,
+    authority: &Pubkey,
+    authority_token_account: &Pubkey,
+    token_program: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    build(
+        accounts::AddDistributionTokens {
+            distribution: *distribution,
+            vault: pda::vault(distribution, mint, token_program),
+            mint: *mint,
+            authority_token_account: *authority_token_account,
+            authority: *authority,
+            token_program: *token_program,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::AddDistributionTokens { amount },
+    )
+}
+
+/// `id` and `mint` identify the distribution to claw back from
+pub fn clawback_distribution_remainder(
+    collection: &Pubkey,
+    id: u64,
+    mint: &Pubkey,
+    authority: &Pubkey,
+    authority_token_account: &Pubkey,
+    token_program: &Pubkey,
+) -> Instruction {
+    let distribution = pda::find_distribution(collection, id).0;
+    build(
+        accounts::ClawbackDistributionRemainder {
+            collection: *collection,
+            distribution,
+            vault: pda::vault(&distribution, mint, token_program),
+            mint: *mint,
+            authority_token_account: *authority_token_account,
+            authority: *authority,
+            token_program: *token_program,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::ClawbackDistributionRemainder {},
+    )
+}
+
+/// `id` and `mint` identify the distribution to sweep
+pub fn sweep_expired_distribution(
+    collection: &Pubkey,
+    id: u64,
+    mint: &Pubkey,
+    authority: &Pubkey,
+    authority_token_account: &Pubkey,
+    token_program: &Pubkey,
+) -> Instruction {
+    let distribution = pda::find_distribution(collection, id).0;
+    build(
+        accounts::SweepExpiredDistribution {
+            collection: *collection,
+            distribution,
+            vault: pda::vault(&distribution, mint, token_program),
+            mint: *mint,
+            authority_token_account: *authority_token_account,
+            authority: *authority,
+            token_program: *token_program,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::SweepExpiredDistribution {},
+    )
+}
+
+/// `id` and `mint` identify the distribution to close
+pub fn close_distribution(
+    collection: &Pubkey,
+    id: u64,
+    mint: &Pubkey,
+    authority: &Pubkey,
+    token_program: &Pubkey,
+) -> Instruction {
+    let distribution = pda::find_distribution(collection, id).0;
+    build(
+        accounts::CloseDistribution {
+            collection: *collection,
+            distribution,
+            vault: pda::vault(&distribution, mint, token_program),
+            mint: *mint,
+            authority: *authority,
+            token_program: *token_program,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::CloseDistribution {},
+    )
+}
+
+/// `mint` is the collection's mint, committed from `user_token_account`.
+/// `allowlist_proof` is required if the collection has an allowlist, see
+/// [`crate::allowlist::Allowlist::proof`].
+pub fn user_commit_to_collection(
+    collection: &Pubkey,
+    mint: &Pubkey,
+    user: &Pubkey,
+    user_token_account: &Pubkey,
+    token_program: &Pubkey,
+    amount: u64,
+    allowlist_proof: Option<AllowlistProof>,
+) -> Instruction {
+    commit(
+        collection,
+        None,
+        mint,
+        user,
+        user_token_account,
+        token_program,
+        amount,
+        allowlist_proof,
+    )
+}
+
+/// Like [`user_commit_to_collection`], for an added mint of the collection
+pub fn user_commit_added_mint_to_collection(
+    collection: &Pubkey,
+    mint: &Pubkey,
+    user: &Pubkey,
+    user_token_account: &Pubkey,
+    token_program: &Pubkey,
+    amount: u64,
+    allowlist_proof: Option<AllowlistProof>,
+) -> Instruction {
+    commit(
+        collection,
+        Some(pda::find_collection_mint(collection, mint).0),
+        mint,
+        user,
+        user_token_account,
+        token_program,
+        amount,
+        allowlist_proof,
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+fn commit(
+    collection: &Pubkey,
+    collection_mint: Option<Pubkey>,
+    mint: &Pubkey,
+    user: &Pubkey,
+    user_token_account: &Pubkey,
+    token_program: &Pubkey,
+    amount: u64,
+    allowlist_proof: Option<AllowlistProof>,
+) -> Instruction {
+    let replacement_mint = pda::find_replacement_mint(collection).0;
+    build(
+        accounts::UserCommitToCollection {
+            collection: *collection,
+            collection_mint,
+            user_state: pda::find_collection_user_state(collection, user).0,
+            mint: *mint,
+            user_token_account: *user_token_account,
+            vault: pda::vault(collection, mint, token_program),
+            replacement_mint,
+            user_replacement_token_account: get_associated_token_address_with_program_id(
+                user,
+                &replacement_mint,
+                token_program,
+            ),
+            user: *user,
+            token_program: *token_program,
+            system_program: system_program::ID,
+            associated_token_program: associated_token::ID,
+            rent: sysvar::rent::ID,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::UserCommitToCollection {
+            amount,
+            allowlist_proof,
+        },
+    )
+}
+
+/// `mint` is the collection's mint, refunded to `user_token_account`
+pub fn user_refund_from_collection(
+    collection: &Pubkey,
+    mint: &Pubkey,
+    user: &Pubkey,
+    user_token_account: &Pubkey,
+    token_program: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    let replacement_mint = pda::find_replacement_mint(collection).0;
+    build(
+        accounts::UserRefundFromCollection {
+            collection: *collection,
+            user_state: pda::find_collection_user_state(collection, user).0,
+            mint: *mint,
+            user_token_account: *user_token_account,
+            vault: pda::vault(collection, mint, token_program),
+            replacement_mint,
+            user_replacement_token_account: get_associated_token_address_with_program_id(
+                user,
+                &replacement_mint,
+                token_program,
+            ),
+            user: *user,
+            token_program: *token_program,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::UserRefundFromCollection { amount },
+    )
+}
+
+/// Redeems replacement tokens from the associated token account of `user`
+pub fn redeem_replacement_tokens(
+    collection: &Pubkey,
+    user: &Pubkey,
+    token_program: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    let replacement_mint = pda::find_replacement_mint(collection).0;
+    build(
+        accounts::RedeemReplacementTokens {
+            collection: *collection,
+            user_state: pda::find_collection_user_state(collection, user).0,
+            replacement_mint,
+            user_replacement_token_account: get_associated_token_address_with_program_id(
+                user,
+                &replacement_mint,
+                token_program,
+            ),
+            user: *user,
+            token_program: *token_program,
+            system_program: system_program::ID,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::RedeemReplacementTokens { amount },
+    )
+}
+
+/// `id` and `mint` identify the distribution to claim from
+pub fn user_claim_from_distribution(
+    collection: &Pubkey,
+    id: u64,
+    mint: &Pubkey,
+    user: &Pubkey,
+    token_program: &Pubkey,
+) -> Instruction {
+    let distribution = pda::find_distribution(collection, id).0;
+    build(
+        accounts::UserClaimFromDistribution {
+            collection: *collection,
+            distribution,
+            collection_user_state: pda::find_collection_user_state(collection, user).0,
+            distribution_user_state: pda::find_distribution_user_state(&distribution, user).0,
+            distribution_vault: pda::vault(&distribution, mint, token_program),
+            distribution_mint: *mint,
+            user_token_account: get_associated_token_address_with_program_id(
+                user,
+                mint,
+                token_program,
+            ),
+            user: *user,
+            token_program: *token_program,
+            system_program: system_program::ID,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::UserClaimFromDistribution {},
+    )
+}
+
+/// `id` and `mint` identify the distribution to claim from, `payer` pays the rent
+/// of the user's new accounts
+pub fn claim_from_distribution_for_user(
+    collection: &Pubkey,
+    id: u64,
+    mint: &Pubkey,
+    user: &Pubkey,
+    payer: &Pubkey,
+    token_program: &Pubkey,
+) -> Instruction {
+    let distribution = pda::find_distribution(collection, id).0;
+    build(
+        accounts::ClaimFromDistributionForUser {
+            collection: *collection,
+            distribution,
+            collection_user_state: pda::find_collection_user_state(collection, user).0,
+            distribution_user_state: pda::find_distribution_user_state(&distribution, user).0,
+            distribution_vault: pda::vault(&distribution, mint, token_program),
+            user_token_account: get_associated_token_address_with_program_id(
+                user,
+                mint,
+                token_program,
+            ),
+            distribution_mint: *mint,
+            user: *user,
+            payer: *payer,
+            token_program: *token_program,
+            system_program: system_program::ID,
+            associated_token_program: associated_token::ID,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::ClaimFromDistributionForUser {},
+    )
+}
+
+/// `distributions` are the ids and mints of the distributions to claim from, all
+/// mints owned by `token_program`
+pub fn user_claim_from_distributions(
+    collection: &Pubkey,
+    distributions: &[(u64, Pubkey)],
+    user: &Pubkey,
+    token_program: &Pubkey,
+) -> Instruction {
+    let mut instruction = build(
+        accounts::UserClaimFromDistributions {
+            collection: *collection,
+            collection_user_state: pda::find_collection_user_state(collection, user).0,
+            user: *user,
+            token_program: *token_program,
+            system_program: system_program::ID,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::UserClaimFromDistributions {},
+    );
+    for (id, mint) in distributions {
+        let distribution = pda::find_distribution(collection, *id).0;
+        instruction.accounts.extend([
+            AccountMeta::new(distribution, false),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new(pda::vault(&distribution, mint, token_program), false),
+            AccountMeta::new(
+                pda::find_distribution_user_state(&distribution, user).0,
+                false,
+            ),
+            AccountMeta::new(
+                get_associated_token_address_with_program_id(user, mint, token_program),
+                false,
+            ),
+        ]);
+    }
+    instruction
+}
+
+pub fn close_collection_user_state(collection: &Pubkey, user: &Pubkey) -> Instruction {
+    build(
+        accounts::CloseCollectionUserState {
+            collection: *collection,
+            user_state: pda::find_collection_user_state(collection, user).0,
+            user: *user,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::CloseCollectionUserState {},
+    )
+}
+
+pub fn close_distribution_user_state(distribution: &Pubkey, user: &Pubkey) -> Instruction {
+    build(
+        accounts::CloseDistributionUserState {
+            distribution: *distribution,
+            distribution_user_state: pda::find_distribution_user_state(distribution, user).0,
+            user: *user,
+            event_authority: event_authority(),
+            program: crate::ID,
+        },
+        instruction::CloseDistributionUserState {},
+    )
+}
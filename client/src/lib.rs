@@ -0,0 +1,15 @@
+//! Rust client for the multidistribute program.
+//!
+//! Provides the program's PDA derivations, deserializers for its accounts,
+//! builders for all of its instructions, an off-chain mirror of the claim math and
+//! the merkle trees of commit allowlists.
+
+#![allow(clippy::result_large_err)]
+
+pub mod allowlist;
+pub mod instructions;
+pub mod math;
+pub mod pda;
+pub mod state;
+
+pub use multidistribute::ID;
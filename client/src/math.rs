@@ -0,0 +1,46 @@
+//! Off-chain mirror of the program's claim math.
+
+use anchor_lang::Result;
+use multidistribute::ErrorCode;
+
+use crate::state::{Collection, CollectionUserState, Distribution, DistributionUserState};
+
+/// Amount a claim from `distribution` would pay out to a user at unix timestamp `now`.
+///
+/// Mirrors the on-chain computation: the user's share is
+/// deposited_amount * vested tokens / share denominator, rounded down, minus what
+/// they already received. `distribution_user_state` is None if the user never
+/// claimed from the distribution. Returns zero when the claim would be rejected
+/// because the collection or distribution is paused or the distribution expired.
+pub fn claimable_amount(
+    collection: &Collection,
+    distribution: &Distribution,
+    collection_user_state: &CollectionUserState,
+    distribution_user_state: Option<&DistributionUserState>,
+    now: i64,
+) -> Result<u64> {
+    if collection.paused || distribution.paused || distribution.is_expired(now) {
+        return Ok(0);
+    }
+
+    let user_share = user_share(
+        collection_user_state.deposited_amount,
+        distribution.vested_tokens(now)?,
+        collection.share_denominator(),
+    )?;
+    let received_amount = distribution_user_state.map_or(0, |state| state.received_amount);
+
+    // A user's share never shrinks, so this only fails on inconsistent input
+    Ok(user_share
+        .checked_sub(received_amount)
+        .ok_or(ErrorCode::Overflow)?)
+}
+
+/// A depositor's total share of `distributable` tokens, rounded down
+pub fn user_share(deposited_amount: u64, distributable: u64, denominator: u64) -> Result<u64> {
+    Ok((deposited_amount as u128)
+        .checked_mul(distributable as u128)
+        .ok_or(ErrorCode::Overflow)?
+        .checked_div(denominator as u128)
+        .ok_or(ErrorCode::Overflow)? as u64)
+}
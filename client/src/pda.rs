@@ -0,0 +1,83 @@
+//! Addresses of the program's accounts.
+//!
+//! The `find_*` functions return the address together with its bump.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::Id;
+use anchor_spl::associated_token::get_associated_token_address_with_program_id;
+use anchor_spl::metadata::Metadata;
+
+/// Collection created by `creator` for `mint`, told apart by `counter`
+pub fn find_collection(creator: &Pubkey, mint: &Pubkey, counter: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            b"collection",
+            creator.as_ref(),
+            mint.as_ref(),
+            &counter.to_le_bytes(),
+        ],
+        &crate::ID,
+    )
+}
+
+/// Mint of the replacement tokens handed out for commits to `collection`
+pub fn find_replacement_mint(collection: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"replacement_mint", collection.as_ref()], &crate::ID)
+}
+
+/// Weight of `mint`, accepted by `collection` besides its own mint
+pub fn find_collection_mint(collection: &Pubkey, mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"collection_mint", collection.as_ref(), mint.as_ref()],
+        &crate::ID,
+    )
+}
+
+/// State tracking the deposits of `user` into `collection`
+pub fn find_collection_user_state(collection: &Pubkey, user: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"user_state", collection.as_ref(), user.as_ref()],
+        &crate::ID,
+    )
+}
+
+/// Distribution number `id` of `collection`, ids counting up from 0 to the
+/// collection's distribution_count
+pub fn find_distribution(collection: &Pubkey, id: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"distribution", collection.as_ref(), &id.to_le_bytes()],
+        &crate::ID,
+    )
+}
+
+/// State tracking how much `user` has claimed from `distribution`
+pub fn find_distribution_user_state(distribution: &Pubkey, user: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            b"distribution_user_state",
+            distribution.as_ref(),
+            user.as_ref(),
+        ],
+        &crate::ID,
+    )
+}
+
+/// Authority the program emits its events through
+pub fn find_event_authority() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"__event_authority"], &crate::ID)
+}
+
+/// Metaplex metadata account of `mint`
+pub fn find_metadata(mint: &Pubkey) -> (Pubkey, u8) {
+    let metadata_program = Metadata::id();
+    Pubkey::find_program_address(
+        &[b"metadata", metadata_program.as_ref(), mint.as_ref()],
+        &metadata_program,
+    )
+}
+
+/// Vault of a collection or distribution, the associated token account of `owner`
+/// for `mint` under `token_program`
+pub fn vault(owner: &Pubkey, mint: &Pubkey, token_program: &Pubkey) -> Pubkey {
+    get_associated_token_address_with_program_id(owner, mint, token_program)
+}
@@ -0,0 +1,33 @@
+//! Deserializers for the program's accounts.
+
+use anchor_lang::{AccountDeserialize, Result};
+
+pub use multidistribute::{
+    AllowlistProof, Collection, CollectionMint, CollectionUserState, CommitBonus, Distribution,
+    DistributionUserState, VestingSchedule,
+};
+
+/// Deserializes account data, checking the account discriminator
+pub fn deserialize<T: AccountDeserialize>(mut data: &[u8]) -> Result<T> {
+    T::try_deserialize(&mut data)
+}
+
+pub fn collection(data: &[u8]) -> Result<Collection> {
+    deserialize(data)
+}
+
+pub fn collection_mint(data: &[u8]) -> Result<CollectionMint> {
+    deserialize(data)
+}
+
+pub fn collection_user_state(data: &[u8]) -> Result<CollectionUserState> {
+    deserialize(data)
+}
+
+pub fn distribution(data: &[u8]) -> Result<Distribution> {
+    deserialize(data)
+}
+
+pub fn distribution_user_state(data: &[u8]) -> Result<DistributionUserState> {
+    deserialize(data)
+}
@@ -1,10 +1,33 @@
+#![allow(clippy::result_large_err)]
+
 use anchor_lang::prelude::*;
-use anchor_spl::associated_token::AssociatedToken;
-use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer};
+use anchor_lang::solana_program::keccak;
+use anchor_lang::solana_program::program::invoke;
+use anchor_lang::system_program;
+use anchor_spl::associated_token::{
+    self, get_associated_token_address_with_program_id, AssociatedToken,
+};
+use anchor_spl::metadata::{self, Metadata};
+use anchor_spl::token_interface::spl_token_2022::{
+    self,
+    extension::{
+        transfer_fee::{instruction::harvest_withheld_tokens_to_mint, TransferFeeAmount},
+        BaseStateWithExtensions, StateWithExtensions,
+    },
+    instruction::AuthorityType,
+};
+use anchor_spl::token_interface::{self, Mint, TokenAccount, TokenInterface, TransferChecked};
+use mpl_token_metadata::state::DataV2;
 use std::mem::size_of;
 
 declare_id!("3pXyP2BALvdpq8w9aoCXEhtxqG56JT8MEqeKRgqANnMt");
 
+/// Number of remaining accounts user_claim_from_distributions expects per distribution
+pub const CLAIM_ACCOUNTS_PER_DISTRIBUTION: usize = 5;
+
+/// Commit bonus multiplier of 1.0, in basis points
+pub const BONUS_MULTIPLIER_ONE_BPS: u64 = 10_000;
+
 #[program]
 pub mod multidistribute {
     use super::*;
@@ -19,17 +42,98 @@ pub mod multidistribute {
     /// * `counter` - Unique counter value to allow multiple collections for the same mint
     /// * `max_collectable_tokens` - Maximum number of tokens that can be deposited into this collection
     /// * `burn_tokens` - If true, committed tokens will be burned instead of stored in the vault
+    /// * `transferable_claims` - If true, distribution entitlement follows the replacement
+    ///   tokens, which holders turn into a claim position with redeem_replacement_tokens
+    /// * `refund_deadline` - Optional unix timestamp until which users can undo their
+    ///   commits with user_refund_from_collection. Requires a non-burning collection
+    ///   without transferable claims.
+    /// * `allowlist_root` - Optional merkle root of the (user, max_amount) pairs allowed
+    ///   to commit, see set_collection_allowlist. Requires a collection without
+    ///   transferable claims.
+    /// * `max_per_user` - Optional maximum deposited_amount a single user can reach
+    ///   through commits. Requires a collection without transferable claims.
+    /// * `min_commit` - Minimum amount of a single commit, 0 for no minimum
+    /// * `commit_start` - Optional unix timestamp from which commits are accepted
+    /// * `commit_end` - Optional unix timestamp from which commits are rejected, see
+    ///   set_collection_commit_end
+    /// * `commit_bonus` - Optional early-bird multiplier crediting early commits more
+    ///   weighted units, which max_collectable_tokens and the per-user limits are then
+    ///   counted in. Requires a collection without transferable claims or refunds,
+    ///   since those count deposits in replacement tokens.
+    #[allow(clippy::too_many_arguments)]
     pub fn init_collection(
         ctx: Context<InitCollection>,
         counter: u64,
         max_collectable_tokens: u64,
         burn_tokens: bool,
+        transferable_claims: bool,
+        refund_deadline: Option<i64>,
+        allowlist_root: Option<[u8; 32]>,
+        max_per_user: Option<u64>,
+        min_commit: u64,
+        commit_start: Option<i64>,
+        commit_end: Option<i64>,
+        commit_bonus: Option<CommitBonus>,
     ) -> Result<()> {
         require!(
             max_collectable_tokens > 0,
             ErrorCode::InvalidMaxCollectableTokens
         );
+        require!(
+            allowlist_root.is_none() || !transferable_claims,
+            ErrorCode::AllowlistUnavailable
+        );
+        require!(
+            min_commit <= max_collectable_tokens,
+            ErrorCode::InvalidCommitLimits
+        );
+        if let Some(max_per_user) = max_per_user {
+            require!(!transferable_claims, ErrorCode::PerUserCapUnavailable);
+            require!(
+                max_per_user > 0 && max_per_user >= min_commit,
+                ErrorCode::InvalidCommitLimits
+            );
+        }
+        if let Some(commit_end) = commit_end {
+            require!(
+                commit_end > Clock::get()?.unix_timestamp
+                    && !matches!(commit_start, Some(commit_start) if commit_start > commit_end),
+                ErrorCode::InvalidCommitWindow
+            );
+        }
+        if let Some(commit_bonus) = &commit_bonus {
+            require!(
+                !transferable_claims && refund_deadline.is_none(),
+                ErrorCode::CommitBonusUnavailable
+            );
+            require!(
+                commit_bonus.start_ts < commit_bonus.end_ts
+                    && commit_bonus.start_multiplier_bps >= BONUS_MULTIPLIER_ONE_BPS,
+                ErrorCode::InvalidCommitBonus
+            );
+        }
+        if let Some(refund_deadline) = refund_deadline {
+            require!(
+                !burn_tokens && !transferable_claims,
+                ErrorCode::RefundsUnavailable
+            );
+            require!(
+                refund_deadline > Clock::get()?.unix_timestamp,
+                ErrorCode::InvalidRefundDeadline
+            );
+        }
 
+        associated_token::create_idempotent(CpiContext::new(
+            ctx.accounts.associated_token_program.to_account_info(),
+            associated_token::Create {
+                payer: ctx.accounts.authority.to_account_info(),
+                associated_token: ctx.accounts.vault.to_account_info(),
+                authority: ctx.accounts.collection.to_account_info(),
+                mint: ctx.accounts.mint.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+                token_program: ctx.accounts.token_program.to_account_info(),
+            },
+        ))?;
 
         let collection = &mut ctx.accounts.collection;
         collection.authority = ctx.accounts.authority.key();
@@ -41,6 +145,265 @@ pub mod multidistribute {
         collection.bump = *ctx.bumps.get("collection").unwrap();
         collection.counter = counter;
         collection.burn_tokens = burn_tokens;
+        collection.finalized = false;
+        collection.commits_closed = false;
+        collection.transferable_claims = transferable_claims;
+        collection.refund_deadline = refund_deadline;
+        collection.creator = ctx.accounts.authority.key();
+        collection.pending_authority = None;
+        collection.guardian = None;
+        collection.paused = false;
+        collection.open_distributions = 0;
+        collection.distribution_count = 0;
+        collection.allowlist_root = allowlist_root;
+        collection.max_per_user = max_per_user;
+        collection.min_commit = min_commit;
+        collection.commit_start = commit_start;
+        collection.commit_end = commit_end;
+        collection.open_collection_mints = 0;
+        collection.commit_bonus = commit_bonus;
+
+        emit_cpi!(CollectionInitialized {
+            collection: collection.key(),
+            authority: collection.authority,
+            mint: collection.mint,
+            replacement_mint: collection.replacement_mint,
+            counter,
+            max_collectable_tokens,
+            burn_tokens,
+            transferable_claims,
+            refund_deadline,
+            allowlist_root,
+            max_per_user,
+            min_commit,
+            commit_start,
+            commit_end,
+            commit_bonus,
+        });
+
+        Ok(())
+    }
+
+    /// Proposes a new authority for the collection.
+    ///
+    /// The new authority only takes over once it calls accept_authority, so control
+    /// can't be handed to a key that isn't able to sign. Proposing again replaces the
+    /// pending authority. Can only be called by the collection authority.
+    ///
+    /// # Arguments
+    /// * `new_authority` - The key that may accept the authority role
+    pub fn propose_authority(ctx: Context<ProposeAuthority>, new_authority: Pubkey) -> Result<()> {
+        let collection = &mut ctx.accounts.collection;
+        collection.pending_authority = Some(new_authority);
+
+        emit_cpi!(AuthorityProposed {
+            collection: collection.key(),
+            authority: collection.authority,
+            pending_authority: new_authority,
+        });
+
+        Ok(())
+    }
+
+    /// Accepts the authority role of a collection previously proposed with
+    /// propose_authority.
+    ///
+    /// The collection PDA and its signer seeds keep using the original creator key,
+    /// so all accounts derived from the collection stay valid.
+    pub fn accept_authority(ctx: Context<AcceptAuthority>) -> Result<()> {
+        let collection = &mut ctx.accounts.collection;
+
+        require!(
+            collection.pending_authority == Some(ctx.accounts.new_authority.key()),
+            ErrorCode::NotPendingAuthority
+        );
+
+        let previous_authority = collection.authority;
+        collection.authority = ctx.accounts.new_authority.key();
+        collection.pending_authority = None;
+
+        emit_cpi!(AuthorityAccepted {
+            collection: collection.key(),
+            previous_authority,
+            authority: collection.authority,
+        });
+
+        Ok(())
+    }
+
+    /// Sets or removes the guardian of a collection.
+    ///
+    /// The guardian is a key that, besides the authority, may pause and unpause the
+    /// collection and its distributions. Can only be called by the collection authority.
+    ///
+    /// # Arguments
+    /// * `guardian` - The new guardian, or None to remove it
+    pub fn set_collection_guardian(
+        ctx: Context<SetCollectionGuardian>,
+        guardian: Option<Pubkey>,
+    ) -> Result<()> {
+        let collection = &mut ctx.accounts.collection;
+        collection.guardian = guardian;
+
+        emit_cpi!(GuardianSet {
+            collection: collection.key(),
+            guardian,
+        });
+
+        Ok(())
+    }
+
+    /// Sets or removes the allowlist of a collection.
+    ///
+    /// The allowlist is the merkle root of a tree whose leaves are
+    /// keccak(user || max_amount as little-endian u64), with each pair of nodes
+    /// hashed in sorted order. While it is set, commits must prove that the user is
+    /// in the tree, and the user's deposited_amount may not exceed their max_amount.
+    /// Deposits made before the allowlist was set or replaced are kept. Can only be
+    /// called by the collection authority, and not for collections with transferable
+    /// claims, since their deposits aren't tracked per committer.
+    ///
+    /// # Arguments
+    /// * `allowlist_root` - The new merkle root, or None to accept commits from anyone
+    pub fn set_collection_allowlist(
+        ctx: Context<SetCollectionAllowlist>,
+        allowlist_root: Option<[u8; 32]>,
+    ) -> Result<()> {
+        let collection = &mut ctx.accounts.collection;
+
+        require!(
+            allowlist_root.is_none() || !collection.transferable_claims,
+            ErrorCode::AllowlistUnavailable
+        );
+
+        collection.allowlist_root = allowlist_root;
+
+        emit_cpi!(AllowlistSet {
+            collection: collection.key(),
+            allowlist_root,
+        });
+
+        Ok(())
+    }
+
+    /// Sets the name, symbol and URI of a collection's replacement mint, so wallets
+    /// can display it.
+    ///
+    /// Creates the mint's Metaplex metadata account on the first call and updates it
+    /// afterwards. The collection PDA is both mint and update authority, so only the
+    /// collection authority can change the metadata, through this instruction.
+    ///
+    /// # Arguments
+    /// * `name` - Token name, at most 32 bytes
+    /// * `symbol` - Token symbol, at most 10 bytes
+    /// * `uri` - URI of the off-chain JSON metadata, at most 200 bytes
+    pub fn set_replacement_mint_metadata(
+        ctx: Context<SetReplacementMintMetadata>,
+        name: String,
+        symbol: String,
+        uri: String,
+    ) -> Result<()> {
+        let collection = &ctx.accounts.collection;
+
+        let counter_bytes = collection.counter.to_le_bytes();
+        let authority_seeds = &[
+            b"collection",
+            collection.creator.as_ref(),
+            collection.mint.as_ref(),
+            &counter_bytes,
+            &[collection.bump],
+        ];
+        let signer = &[&authority_seeds[..]];
+
+        let data = DataV2 {
+            name: name.clone(),
+            symbol: symbol.clone(),
+            uri: uri.clone(),
+            seller_fee_basis_points: 0,
+            creators: None,
+            collection: None,
+            uses: None,
+        };
+
+        if ctx.accounts.metadata.data_is_empty() {
+            let create_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_metadata_program.to_account_info(),
+                metadata::CreateMetadataAccountsV3 {
+                    metadata: ctx.accounts.metadata.to_account_info(),
+                    mint: ctx.accounts.replacement_mint.to_account_info(),
+                    mint_authority: ctx.accounts.collection.to_account_info(),
+                    payer: ctx.accounts.authority.to_account_info(),
+                    update_authority: ctx.accounts.collection.to_account_info(),
+                    system_program: ctx.accounts.system_program.to_account_info(),
+                    rent: ctx.accounts.rent.to_account_info(),
+                },
+                signer,
+            );
+            metadata::create_metadata_accounts_v3(create_ctx, data, true, true, None)?;
+        } else {
+            let update_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_metadata_program.to_account_info(),
+                metadata::UpdateMetadataAccountsV2 {
+                    metadata: ctx.accounts.metadata.to_account_info(),
+                    update_authority: ctx.accounts.collection.to_account_info(),
+                },
+                signer,
+            );
+            metadata::update_metadata_accounts_v2(update_ctx, None, Some(data), None, None)?;
+        }
+
+        emit_cpi!(ReplacementMintMetadataSet {
+            collection: ctx.accounts.collection.key(),
+            replacement_mint: ctx.accounts.replacement_mint.key(),
+            name,
+            symbol,
+            uri,
+        });
+
+        Ok(())
+    }
+
+    /// Pauses or unpauses a collection.
+    ///
+    /// While paused, users can't commit, refund, redeem replacement tokens or claim from
+    /// any of the collection's distributions. Admin actions keep working. Can be called
+    /// by the collection authority or guardian.
+    ///
+    /// # Arguments
+    /// * `paused` - Whether the collection should be paused
+    pub fn set_collection_paused(ctx: Context<SetCollectionPaused>, paused: bool) -> Result<()> {
+        let collection = &mut ctx.accounts.collection;
+        collection.paused = paused;
+
+        emit_cpi!(CollectionPauseSet {
+            collection: collection.key(),
+            signer: ctx.accounts.signer.key(),
+            paused,
+        });
+
+        Ok(())
+    }
+
+    /// Pauses or unpauses a single distribution.
+    ///
+    /// While paused, users can't claim from the distribution. Admin actions keep
+    /// working. Can be called by the collection authority or guardian.
+    ///
+    /// # Arguments
+    /// * `paused` - Whether the distribution should be paused
+    pub fn set_distribution_paused(
+        ctx: Context<SetDistributionPaused>,
+        paused: bool,
+    ) -> Result<()> {
+        let distribution = &mut ctx.accounts.distribution;
+        distribution.paused = paused;
+
+        emit_cpi!(DistributionPauseSet {
+            distribution: distribution.key(),
+            signer: ctx.accounts.signer.key(),
+            paused,
+        });
+
         Ok(())
     }
 
@@ -50,426 +413,2513 @@ pub mod multidistribute {
     /// distributions to be fully utilized. Can only be called by the collection authority.
     ///
     /// # Arguments
-    /// * `new_max_collectable_tokens` - New maximum value, must be less than current maximum
-    ///   and greater than or equal to currently collected amount
+    /// * `new_max_collectable_tokens` - New maximum value, must be less than current maximum,
+    ///   greater than or equal to currently collected amount and greater than zero
     pub fn decrease_collection_max_collectable_tokens(
         ctx: Context<DecreaseCollectionMaxTokens>,
         new_max_collectable_tokens: u64,
     ) -> Result<()> {
         let collection = &mut ctx.accounts.collection;
 
+        require!(!collection.finalized, ErrorCode::CollectionFinalized);
+        require!(!collection.commits_closed, ErrorCode::CommitsClosed);
         require!(
             new_max_collectable_tokens >= collection.lifetime_tokens_collected,
             ErrorCode::MaxCollectableTokensBelowTotal
         );
+        require!(
+            new_max_collectable_tokens > 0,
+            ErrorCode::InvalidMaxCollectableTokens
+        );
         require!(
             new_max_collectable_tokens < collection.max_collectable_tokens,
             ErrorCode::InvalidDecrease
         );
 
+        let previous_max_collectable_tokens = collection.max_collectable_tokens;
         collection.max_collectable_tokens = new_max_collectable_tokens;
+
+        emit_cpi!(MaxDecreased {
+            collection: collection.key(),
+            previous_max_collectable_tokens,
+            max_collectable_tokens: new_max_collectable_tokens,
+            lifetime_tokens_collected: collection.lifetime_tokens_collected,
+        });
+
         Ok(())
     }
 
-    /// Withdraws all tokens from the collection vault to the authority's token account.
+    /// Decreases the maximum deposited_amount a single user can reach through commits,
+    /// or sets one if the collection had none.
     ///
-    /// Can only be called by the collection authority. This does not affect users'
-    /// deposited amounts or their ability to receive from distributions.
-    pub fn withdraw_from_collection(ctx: Context<WithdrawFromCollection>) -> Result<()> {
-        let collection = &ctx.accounts.collection;
-
-        // Transfer tokens from collection vault to authority
-        let counter_bytes = collection.counter.to_le_bytes();
-        let authority_seeds = &[
-            b"collection",
-            collection.authority.as_ref(),
-            collection.mint.as_ref(),
-            &counter_bytes,
-            &[collection.bump],
-        ];
-        let signer = &[&authority_seeds[..]];
+    /// Like the collection maximum, the cap can only be tightened, so users can rely
+    /// on it while the collection is open. Users who already deposited more keep
+    /// their deposit but can't commit further. Since a user can commit from several
+    /// wallets, the cap limits wallets rather than people. Can only be called by the
+    /// collection authority.
+    ///
+    /// # Arguments
+    /// * `new_max_per_user` - New cap, must be less than the current one, greater than
+    ///   zero and at least min_commit
+    pub fn decrease_collection_max_per_user(
+        ctx: Context<DecreaseCollectionMaxPerUser>,
+        new_max_per_user: u64,
+    ) -> Result<()> {
+        let collection = &mut ctx.accounts.collection;
 
-        let transfer_ctx = CpiContext::new_with_signer(
-            ctx.accounts.token_program.to_account_info(),
-            Transfer {
-                from: ctx.accounts.vault.to_account_info(),
-                to: ctx.accounts.authority_token_account.to_account_info(),
-                authority: ctx.accounts.collection.to_account_info(),
-            },
-            signer,
+        require!(!collection.finalized, ErrorCode::CollectionFinalized);
+        require!(!collection.commits_closed, ErrorCode::CommitsClosed);
+        require!(
+            !collection.transferable_claims,
+            ErrorCode::PerUserCapUnavailable
+        );
+        require!(
+            !matches!(collection.max_per_user, Some(max_per_user) if new_max_per_user >= max_per_user),
+            ErrorCode::InvalidDecrease
+        );
+        require!(
+            new_max_per_user > 0 && new_max_per_user >= collection.min_commit,
+            ErrorCode::InvalidCommitLimits
         );
-        token::transfer(transfer_ctx, ctx.accounts.vault.amount)?;
+
+        let previous_max_per_user = collection.max_per_user;
+        collection.max_per_user = Some(new_max_per_user);
+
+        emit_cpi!(MaxPerUserDecreased {
+            collection: collection.key(),
+            previous_max_per_user,
+            max_per_user: new_max_per_user,
+        });
 
         Ok(())
     }
 
-    /// Initializes a new distribution associated with a collection.
+    /// Decreases the minimum amount of a single commit.
     ///
-    /// A distribution allows proportional sharing of tokens to collection depositors.
-    /// The distributed token type can be different from the collected token type.
-    /// Can only be called by the collection authority.
-    pub fn init_distribution(ctx: Context<InitDistribution>) -> Result<()> {
-        let distribution = &mut ctx.accounts.distribution;
-        distribution.collection = ctx.accounts.collection.key();
-        distribution.lifetime_deposited_tokens = 0;
-        distribution.mint = ctx.accounts.mint.key();
-        distribution.vault = ctx.accounts.vault.key();
-        distribution.distributed_tokens = 0;
-        distribution.bump = *ctx.bumps.get("distribution").unwrap();
+    /// The minimum can only be loosened, so commits that were accepted before keep
+    /// being accepted. Can only be called by the collection authority.
+    ///
+    /// # Arguments
+    /// * `new_min_commit` - New minimum, must be less than the current one
+    pub fn decrease_collection_min_commit(
+        ctx: Context<DecreaseCollectionMinCommit>,
+        new_min_commit: u64,
+    ) -> Result<()> {
+        let collection = &mut ctx.accounts.collection;
+
+        require!(!collection.finalized, ErrorCode::CollectionFinalized);
+        require!(!collection.commits_closed, ErrorCode::CommitsClosed);
+        require!(
+            new_min_commit < collection.min_commit,
+            ErrorCode::InvalidDecrease
+        );
+
+        let previous_min_commit = collection.min_commit;
+        collection.min_commit = new_min_commit;
+
+        emit_cpi!(MinCommitDecreased {
+            collection: collection.key(),
+            previous_min_commit,
+            min_commit: new_min_commit,
+        });
+
         Ok(())
     }
 
-    /// Adds tokens to a distribution's vault for later distribution to users.
+    /// Moves the end of a collection's commit window, extending it or closing it
+    /// early.
     ///
-    /// Anyone can add tokens to a distribution. This allows for flexible token
-    /// sourcing - the tokens don't have to come from the collection authority.
+    /// Once the window has ended it stays ended, so the deposits at that time form a
+    /// fixed snapshot. Unlike close_collection_commits, this keeps the collection
+    /// open to finalize_collection. Can only be called by the collection authority.
     ///
     /// # Arguments
-    /// * `amount` - Number of tokens to add to the distribution
-    pub fn add_distribution_tokens(ctx: Context<AddDistributionTokens>, amount: u64) -> Result<()> {
-        let distribution = &mut ctx.accounts.distribution;
+    /// * `commit_end` - New unix timestamp from which commits are rejected, not before
+    ///   the window's start, or None to accept commits without a time limit. A time
+    ///   in the past closes the window immediately and is stored as the current time.
+    pub fn set_collection_commit_end(
+        ctx: Context<SetCollectionCommitEnd>,
+        commit_end: Option<i64>,
+    ) -> Result<()> {
+        let collection = &mut ctx.accounts.collection;
+        let now = Clock::get()?.unix_timestamp;
 
-        // Transfer tokens to the distribution vault
-        let transfer_ctx = CpiContext::new(
-            ctx.accounts.token_program.to_account_info(),
-            Transfer {
-                from: ctx.accounts.authority_token_account.to_account_info(),
-                to: ctx.accounts.vault.to_account_info(),
-                authority: ctx.accounts.authority.to_account_info(),
-            },
+        require!(!collection.finalized, ErrorCode::CollectionFinalized);
+        require!(!collection.commits_closed, ErrorCode::CommitsClosed);
+        require!(
+            !collection.commit_window_ended(now),
+            ErrorCode::CommitWindowEnded
         );
-        token::transfer(transfer_ctx, amount)?;
+        let commit_end = commit_end.map(|commit_end| commit_end.max(now));
+        if let Some(commit_end) = commit_end {
+            require!(
+                !matches!(collection.commit_start, Some(commit_start) if commit_start > commit_end),
+                ErrorCode::InvalidCommitWindow
+            );
+        }
 
-        distribution.lifetime_deposited_tokens = distribution
-            .lifetime_deposited_tokens
-            .checked_add(amount)
-            .ok_or(ErrorCode::Overflow)?;
+        let previous_commit_end = collection.commit_end;
+        collection.commit_end = commit_end;
+
+        emit_cpi!(CommitEndSet {
+            collection: collection.key(),
+            previous_commit_end,
+            commit_end,
+        });
 
         Ok(())
     }
 
-    /// Commits tokens to a collection's vault.
+    /// Accepts another mint in a collection, credited at a fixed weight relative to
+    /// the collection's own mint.
     ///
-    /// Users commit tokens to become eligible for distributions. Their share of
-    /// future distributions will be proportional to their committed amount relative
-    /// to the collection's max_collectable_tokens. Users receive freshly minted
-    /// replacement tokens equal to their committed amount.
+    /// Commits of the added mint credit amount * weight_numerator / weight_denominator,
+    /// rounded down, to the user's deposited_amount and the collection's
+    /// lifetime_tokens_collected, and mint that many replacement tokens. Caps, the
+    /// minimum commit and allowlisted amounts are all in these weighted units, so every
+    /// distribution pays across all accepted mints. The added tokens are kept in a
+    /// vault of their own, or burned for burning collections. The mint must use the
+    /// replacement mint's token program. Not possible while the refund window is open,
+    /// since refunds only return the collection's own mint. Can only be called by the
+    /// collection authority.
     ///
     /// # Arguments
-    /// * `amount` - Number of tokens to commit to the collection
-    pub fn user_commit_to_collection(ctx: Context<UserCommitToCollection>, amount: u64) -> Result<()> {
+    /// * `weight_numerator` - Weighted units credited per `weight_denominator` tokens
+    /// * `weight_denominator` - Number of tokens worth `weight_numerator` weighted units
+    pub fn add_collection_mint(
+        ctx: Context<AddCollectionMint>,
+        weight_numerator: u64,
+        weight_denominator: u64,
+    ) -> Result<()> {
         let collection = &ctx.accounts.collection;
-        let user_state = &mut ctx.accounts.user_state;
 
-        // Either burn or transfer the tokens
-        if collection.burn_tokens {
-            let burn_ctx = CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
-                token::Burn {
-                    mint: ctx.accounts.mint.to_account_info(),
-                    from: ctx.accounts.user_token_account.to_account_info(),
-                    authority: ctx.accounts.user.to_account_info(),
-                },
-            );
-            token::burn(burn_ctx, amount)?;
-        } else {
-            let transfer_ctx = CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
-                Transfer {
-                    from: ctx.accounts.user_token_account.to_account_info(),
-                    to: ctx.accounts.vault.to_account_info(),
-                    authority: ctx.accounts.user.to_account_info(),
-                },
-            );
-            token::transfer(transfer_ctx, amount)?;
-        }
+        require!(!collection.finalized, ErrorCode::CollectionFinalized);
+        require!(!collection.commits_closed, ErrorCode::CommitsClosed);
+        require!(
+            !collection.refunds_open(Clock::get()?.unix_timestamp),
+            ErrorCode::RefundWindowOpen
+        );
+        require!(
+            weight_numerator > 0 && weight_denominator > 0,
+            ErrorCode::InvalidWeight
+        );
 
-        // Mint replacement tokens to user
+        associated_token::create_idempotent(CpiContext::new(
+            ctx.accounts.associated_token_program.to_account_info(),
+            associated_token::Create {
+                payer: ctx.accounts.authority.to_account_info(),
+                associated_token: ctx.accounts.vault.to_account_info(),
+                authority: ctx.accounts.collection.to_account_info(),
+                mint: ctx.accounts.mint.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+                token_program: ctx.accounts.token_program.to_account_info(),
+            },
+        ))?;
+
+        let collection_mint = &mut ctx.accounts.collection_mint;
+        collection_mint.collection = ctx.accounts.collection.key();
+        collection_mint.mint = ctx.accounts.mint.key();
+        collection_mint.vault = ctx.accounts.vault.key();
+        collection_mint.weight_numerator = weight_numerator;
+        collection_mint.weight_denominator = weight_denominator;
+        collection_mint.lifetime_tokens_collected = 0;
+        collection_mint.bump = *ctx.bumps.get("collection_mint").unwrap();
+
+        let collection = &mut ctx.accounts.collection;
+        collection.open_collection_mints = collection
+            .open_collection_mints
+            .checked_add(1)
+            .ok_or(ErrorCode::Overflow)?;
+
+        emit_cpi!(CollectionMintAdded {
+            collection: collection.key(),
+            collection_mint: collection_mint.key(),
+            mint: collection_mint.mint,
+            vault: collection_mint.vault,
+            weight_numerator,
+            weight_denominator,
+        });
+
+        Ok(())
+    }
+
+    /// Stops accepting an added mint and returns the rent of its account and vault to
+    /// the authority.
+    ///
+    /// The vault must be emptied with withdraw_from_collection first. Weighted units
+    /// already credited for the mint are kept. Can only be called by the collection
+    /// authority.
+    pub fn close_collection_mint(ctx: Context<CloseCollectionMint>) -> Result<()> {
+        require!(ctx.accounts.vault.amount == 0, ErrorCode::VaultNotEmpty);
+
+        let collection = &ctx.accounts.collection;
         let counter_bytes = collection.counter.to_le_bytes();
-        let seeds = &[
+        let authority_seeds = &[
             b"collection",
-            collection.authority.as_ref(),
+            collection.creator.as_ref(),
             collection.mint.as_ref(),
             &counter_bytes,
             &[collection.bump],
         ];
-        let signer = &[&seeds[..]];
+        let signer = &[&authority_seeds[..]];
 
-        let mint_ctx = CpiContext::new_with_signer(
+        harvest_withheld_fees(
+            &ctx.accounts.vault.to_account_info(),
+            &ctx.accounts.mint.to_account_info(),
+            &ctx.accounts.token_program.to_account_info(),
+        )?;
+
+        let close_ctx = CpiContext::new_with_signer(
             ctx.accounts.token_program.to_account_info(),
-            token::MintTo {
-                mint: ctx.accounts.replacement_mint.to_account_info(),
-                to: ctx.accounts.user_replacement_token_account.to_account_info(),
+            token_interface::CloseAccount {
+                account: ctx.accounts.vault.to_account_info(),
+                destination: ctx.accounts.authority.to_account_info(),
                 authority: ctx.accounts.collection.to_account_info(),
             },
             signer,
         );
-        token::mint_to(mint_ctx, amount)?;
+        token_interface::close_account(close_ctx)?;
 
-        // Update states
         let collection = &mut ctx.accounts.collection;
-        collection.lifetime_tokens_collected = collection
-            .lifetime_tokens_collected
-            .checked_add(amount)
-            .ok_or(ErrorCode::Overflow)?;
-        user_state.deposited_amount = user_state
-            .deposited_amount
-            .checked_add(amount)
+        collection.open_collection_mints = collection
+            .open_collection_mints
+            .checked_sub(1)
             .ok_or(ErrorCode::Overflow)?;
 
-        require!(
-            collection.lifetime_tokens_collected <= collection.max_collectable_tokens,
-            ErrorCode::MaxCollectableTokensExceeded
-        );
+        emit_cpi!(CollectionMintClosed {
+            collection: collection.key(),
+            collection_mint: ctx.accounts.collection_mint.key(),
+            mint: ctx.accounts.collection_mint.mint,
+            lifetime_tokens_collected: ctx.accounts.collection_mint.lifetime_tokens_collected,
+        });
 
         Ok(())
     }
 
-    /// Claims a user's share of tokens from a distribution.
+    /// Finalizes a collection, freezing commits and switching the share computation
+    /// of all its distributions to the actually collected total.
     ///
-    /// The amount claimed is proportional to the user's deposit in the collection
-    /// relative to the collection's max_collectable_tokens. Can be called multiple
-    /// times as more tokens are added to the distribution.
-    pub fn user_claim_from_distribution(ctx: Context<UserClaimFromDistribution>) -> Result<()> {
+    /// Before finalization, shares are computed against max_collectable_tokens, which
+    /// leaves part of every distribution unclaimed when the cap isn't reached. Once
+    /// finalized, lifetime_tokens_collected is used instead, so distributions pay out
+    /// fully. Users who already claimed under the old denominator receive the
+    /// difference on their next claim. Can only be called by the collection authority.
+    /// Not possible after close_collection_commits, since distributions may already
+    /// have returned the share of the uncollected tokens to the authority.
+    pub fn finalize_collection(ctx: Context<FinalizeCollection>) -> Result<()> {
+        let collection = &mut ctx.accounts.collection;
+
+        require!(!collection.finalized, ErrorCode::CollectionFinalized);
+        require!(!collection.commits_closed, ErrorCode::CommitsClosed);
+        require!(
+            collection.lifetime_tokens_collected > 0,
+            ErrorCode::NothingCollected
+        );
+
+        collection.finalized = true;
+
+        emit_cpi!(CollectionFinalized {
+            collection: collection.key(),
+            lifetime_tokens_collected: collection.lifetime_tokens_collected,
+            max_collectable_tokens: collection.max_collectable_tokens,
+        });
+
+        Ok(())
+    }
+
+    /// Permanently stops a collection from accepting commits, keeping
+    /// max_collectable_tokens as the share denominator.
+    ///
+    /// This is the alternative to finalize_collection: the share of distributions
+    /// belonging to the uncollected tokens becomes provably unclaimable and can be
+    /// returned to the authority with clawback_distribution_remainder. The maximum
+    /// can no longer be decreased afterwards. Can only be called by the collection
+    /// authority.
+    pub fn close_collection_commits(ctx: Context<CloseCollectionCommits>) -> Result<()> {
+        let collection = &mut ctx.accounts.collection;
+
+        require!(!collection.finalized, ErrorCode::CollectionFinalized);
+        require!(!collection.commits_closed, ErrorCode::CommitsClosed);
+
+        collection.commits_closed = true;
+
+        emit_cpi!(CollectionCommitsClosed {
+            collection: collection.key(),
+            lifetime_tokens_collected: collection.lifetime_tokens_collected,
+            max_collectable_tokens: collection.max_collectable_tokens,
+        });
+
+        Ok(())
+    }
+
+    /// Withdraws all tokens from the collection vault to the authority's token account.
+    ///
+    /// Passing an added mint's collection_mint account withdraws that mint's vault
+    /// instead. Can only be called by the collection authority. This does not affect users'
+    /// deposited amounts or their ability to receive from distributions. Not possible
+    /// while the refund window is open, since refunds are paid from the vault.
+    pub fn withdraw_from_collection(ctx: Context<WithdrawFromCollection>) -> Result<()> {
+        let collection = &ctx.accounts.collection;
+
+        require!(
+            !collection.refunds_open(Clock::get()?.unix_timestamp),
+            ErrorCode::RefundWindowOpen
+        );
+
+        // Transfer tokens from collection vault to authority
+        let counter_bytes = collection.counter.to_le_bytes();
+        let authority_seeds = &[
+            b"collection",
+            collection.creator.as_ref(),
+            collection.mint.as_ref(),
+            &counter_bytes,
+            &[collection.bump],
+        ];
+        let signer = &[&authority_seeds[..]];
+
+        let transfer_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            TransferChecked {
+                from: ctx.accounts.vault.to_account_info(),
+                mint: ctx.accounts.mint.to_account_info(),
+                to: ctx.accounts.authority_token_account.to_account_info(),
+                authority: ctx.accounts.collection.to_account_info(),
+            },
+            signer,
+        );
+        let amount = ctx.accounts.vault.amount;
+        token_interface::transfer_checked(transfer_ctx, amount, ctx.accounts.mint.decimals)?;
+
+        emit_cpi!(VaultWithdrawn {
+            collection: ctx.accounts.collection.key(),
+            vault: ctx.accounts.vault.key(),
+            destination: ctx.accounts.authority_token_account.key(),
+            amount,
+        });
+
+        Ok(())
+    }
+
+    /// Closes a wound down collection and its vault, returning their rent to the
+    /// authority.
+    ///
+    /// All distributions of the collection must be closed and the vault must be
+    /// empty, so withdraw_from_collection first. Not possible while the refund window
+    /// is open. The replacement mint can't be closed, so its mint authority is revoked
+    /// instead, which freezes the replacement supply. Since the replacement mint keeps
+    /// existing, the collection address can't be initialized again, and the states of
+    /// its users can be closed with close_collection_user_state. Can only be called by
+    /// the collection authority.
+    pub fn close_collection(ctx: Context<CloseCollection>) -> Result<()> {
+        let collection = &ctx.accounts.collection;
+
+        require!(
+            !collection.refunds_open(Clock::get()?.unix_timestamp),
+            ErrorCode::RefundWindowOpen
+        );
+        require!(
+            collection.open_distributions == 0,
+            ErrorCode::DistributionsOpen
+        );
+        require!(
+            collection.open_collection_mints == 0,
+            ErrorCode::CollectionMintsOpen
+        );
+        require!(ctx.accounts.vault.amount == 0, ErrorCode::VaultNotEmpty);
+
+        let counter_bytes = collection.counter.to_le_bytes();
+        let authority_seeds = &[
+            b"collection",
+            collection.creator.as_ref(),
+            collection.mint.as_ref(),
+            &counter_bytes,
+            &[collection.bump],
+        ];
+        let signer = &[&authority_seeds[..]];
+
+        let set_authority_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            token_interface::SetAuthority {
+                current_authority: ctx.accounts.collection.to_account_info(),
+                account_or_mint: ctx.accounts.replacement_mint.to_account_info(),
+            },
+            signer,
+        );
+        token_interface::set_authority(set_authority_ctx, AuthorityType::MintTokens, None)?;
+
+        harvest_withheld_fees(
+            &ctx.accounts.vault.to_account_info(),
+            &ctx.accounts.mint.to_account_info(),
+            &ctx.accounts.token_program.to_account_info(),
+        )?;
+
+        let close_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            token_interface::CloseAccount {
+                account: ctx.accounts.vault.to_account_info(),
+                destination: ctx.accounts.authority.to_account_info(),
+                authority: ctx.accounts.collection.to_account_info(),
+            },
+            signer,
+        );
+        token_interface::close_account(close_ctx)?;
+
+        emit_cpi!(CollectionClosed {
+            collection: ctx.accounts.collection.key(),
+            authority: ctx.accounts.authority.key(),
+            lifetime_tokens_collected: ctx.accounts.collection.lifetime_tokens_collected,
+        });
+
+        Ok(())
+    }
+
+    /// Initializes a new distribution associated with a collection.
+    ///
+    /// A distribution allows proportional sharing of tokens to collection depositors.
+    /// The distributed token type can be different from the collected token type.
+    /// A collection can have any number of distributions, also of the same mint. Each
+    /// gets the next id of the collection, starting at 0, which is part of its address,
+    /// so clients can enumerate them up to distribution_count. Can only be called by
+    /// the collection authority.
+    ///
+    /// # Arguments
+    /// * `expires_at` - Optional unix timestamp after which claims are rejected and the
+    ///   authority may sweep the remaining tokens
+    /// * `vesting` - Optional schedule over which the distribution's tokens unlock
+    ///   linearly, instead of being claimable as soon as they are added
+    pub fn init_distribution(
+        ctx: Context<InitDistribution>,
+        expires_at: Option<i64>,
+        vesting: Option<VestingSchedule>,
+    ) -> Result<()> {
+        if let Some(expires_at) = expires_at {
+            require!(
+                expires_at > Clock::get()?.unix_timestamp,
+                ErrorCode::InvalidExpiry
+            );
+        }
+        if let Some(vesting) = &vesting {
+            require!(
+                vesting.start_ts <= vesting.cliff_ts
+                    && vesting.cliff_ts <= vesting.end_ts
+                    && vesting.start_ts < vesting.end_ts,
+                ErrorCode::InvalidVestingSchedule
+            );
+        }
+
+        associated_token::create_idempotent(CpiContext::new(
+            ctx.accounts.associated_token_program.to_account_info(),
+            associated_token::Create {
+                payer: ctx.accounts.authority.to_account_info(),
+                associated_token: ctx.accounts.vault.to_account_info(),
+                authority: ctx.accounts.distribution.to_account_info(),
+                mint: ctx.accounts.mint.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+                token_program: ctx.accounts.token_program.to_account_info(),
+            },
+        ))?;
+
+        let distribution = &mut ctx.accounts.distribution;
+        distribution.collection = ctx.accounts.collection.key();
+        distribution.lifetime_deposited_tokens = 0;
+        distribution.mint = ctx.accounts.mint.key();
+        distribution.vault = ctx.accounts.vault.key();
+        distribution.distributed_tokens = 0;
+        distribution.clawed_back_tokens = 0;
+        distribution.bump = *ctx.bumps.get("distribution").unwrap();
+        distribution.expires_at = expires_at;
+        distribution.swept = false;
+        distribution.swept_tokens = 0;
+        distribution.vesting = vesting;
+        distribution.paused = false;
+        distribution.id = ctx.accounts.collection.distribution_count;
+
+        let collection = &mut ctx.accounts.collection;
+        collection.distribution_count = collection
+            .distribution_count
+            .checked_add(1)
+            .ok_or(ErrorCode::Overflow)?;
+        collection.open_distributions = collection
+            .open_distributions
+            .checked_add(1)
+            .ok_or(ErrorCode::Overflow)?;
+
+        emit_cpi!(DistributionInitialized {
+            distribution: distribution.key(),
+            collection: distribution.collection,
+            mint: distribution.mint,
+            id: distribution.id,
+            expires_at,
+            vesting,
+        });
+
+        Ok(())
+    }
+
+    /// Adds tokens to a distribution's vault for later distribution to users.
+    ///
+    /// Anyone can add tokens to a distribution. This allows for flexible token
+    /// sourcing - the tokens don't have to come from the collection authority.
+    /// Expired distributions don't accept further tokens. Only what actually arrives
+    /// in the vault is distributed, which is less than `amount` for mints with a
+    /// transfer fee.
+    ///
+    /// # Arguments
+    /// * `amount` - Number of tokens to add to the distribution
+    pub fn add_distribution_tokens(ctx: Context<AddDistributionTokens>, amount: u64) -> Result<()> {
+        let distribution = &mut ctx.accounts.distribution;
+
+        require!(
+            !distribution.is_expired(Clock::get()?.unix_timestamp),
+            ErrorCode::DistributionExpired
+        );
+
+        // Transfer tokens to the distribution vault
+        let vault_amount_before = ctx.accounts.vault.amount;
+        let transfer_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            TransferChecked {
+                from: ctx.accounts.authority_token_account.to_account_info(),
+                mint: ctx.accounts.mint.to_account_info(),
+                to: ctx.accounts.vault.to_account_info(),
+                authority: ctx.accounts.authority.to_account_info(),
+            },
+        );
+        token_interface::transfer_checked(transfer_ctx, amount, ctx.accounts.mint.decimals)?;
+
+        // Transfer fees are withheld in the vault, so measure what actually arrived
+        ctx.accounts.vault.reload()?;
+        let received_amount = ctx
+            .accounts
+            .vault
+            .amount
+            .checked_sub(vault_amount_before)
+            .ok_or(ErrorCode::Overflow)?;
+
+        distribution.lifetime_deposited_tokens = distribution
+            .lifetime_deposited_tokens
+            .checked_add(received_amount)
+            .ok_or(ErrorCode::Overflow)?;
+
+        emit_cpi!(DistributionFunded {
+            distribution: distribution.key(),
+            funder: ctx.accounts.authority.key(),
+            amount: received_amount,
+            lifetime_deposited_tokens: distribution.lifetime_deposited_tokens,
+        });
+
+        Ok(())
+    }
+
+    /// Returns the part of a distribution that can never be claimed to the authority.
+    ///
+    /// Once a collection no longer accepts commits, its depositors are entitled to at
+    /// most lifetime_tokens_collected * lifetime_deposited_tokens / max_collectable_tokens
+    /// of the distribution in total. Everything above that - the share of the
+    /// uncollected tokens plus rounding dust - is unclaimable. Tokens owed to current
+    /// depositors are never touched. Can be called again after more tokens were added
+    /// to the distribution. Can only be called by the collection authority.
+    pub fn clawback_distribution_remainder(
+        ctx: Context<ClawbackDistributionRemainder>,
+    ) -> Result<()> {
         let collection = &ctx.accounts.collection;
         let distribution = &ctx.accounts.distribution;
-        let collection_user_state = &ctx.accounts.collection_user_state;
-        let distribution_user_state = &mut ctx.accounts.distribution_user_state;
-
-        // Calculate user's share:
-        // Since user_claim_from_distribution() can be called at any time, in particular before all
-        // users have deposited, the fixed max_collectable_tokens denominator is used.
-        // That means that if less than max_collectable_tokens end up deposited, a large
-        // part of the distribution may not be handed out.
-        // If this becomes a problem, the authority may decrease max_collectable_tokens by
-        // calling decrease_collection_max_collectable_tokens.
-        let user_share = (collection_user_state.deposited_amount as u128)
+
+        require!(collection.commits_closed, ErrorCode::CommitsNotClosed);
+
+        // Sum of floor(deposit * lifetime / denominator) over all users can't exceed
+        // floor(collected * lifetime / denominator), so this bounds everything claimable.
+        let claimable_tokens = (collection.lifetime_tokens_collected as u128)
             .checked_mul(distribution.lifetime_deposited_tokens as u128)
             .ok_or(ErrorCode::Overflow)?
-            // Integer division rounds down, ensuring we never overpay users
-            .checked_div(collection.max_collectable_tokens as u128)
+            .checked_div(collection.share_denominator() as u128)
             .ok_or(ErrorCode::Overflow)? as u64;
 
-        let amount_to_receive = user_share
-            .checked_sub(distribution_user_state.received_amount)
+        let amount_to_claw_back = distribution
+            .lifetime_deposited_tokens
+            .checked_sub(claimable_tokens)
+            .ok_or(ErrorCode::Overflow)?
+            .checked_sub(distribution.clawed_back_tokens)
+            .ok_or(ErrorCode::Overflow)?;
+
+        // Transfer tokens from distribution vault to authority
+        let id_bytes = distribution.id.to_le_bytes();
+        let authority_seeds = &[
+            b"distribution",
+            distribution.collection.as_ref(),
+            &id_bytes,
+            &[distribution.bump],
+        ];
+        let signer = &[&authority_seeds[..]];
+
+        let transfer_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            TransferChecked {
+                from: ctx.accounts.vault.to_account_info(),
+                mint: ctx.accounts.mint.to_account_info(),
+                to: ctx.accounts.authority_token_account.to_account_info(),
+                authority: ctx.accounts.distribution.to_account_info(),
+            },
+            signer,
+        );
+        token_interface::transfer_checked(
+            transfer_ctx,
+            amount_to_claw_back,
+            ctx.accounts.mint.decimals,
+        )?;
+
+        let distribution = &mut ctx.accounts.distribution;
+        distribution.clawed_back_tokens = distribution
+            .clawed_back_tokens
+            .checked_add(amount_to_claw_back)
             .ok_or(ErrorCode::Overflow)?;
 
-        // Note that amount_to_receive may be zero. That is ok, the instruction
-        // should nevertheless succeed.
+        emit_cpi!(RemainderClawedBack {
+            distribution: distribution.key(),
+            destination: ctx.accounts.authority_token_account.key(),
+            amount: amount_to_claw_back,
+            clawed_back_tokens: distribution.clawed_back_tokens,
+        });
+
+        Ok(())
+    }
+
+    /// Sweeps the remaining tokens of an expired distribution to the authority and
+    /// closes the distribution vault.
+    ///
+    /// After expiry users can no longer claim, so whatever is left in the vault is
+    /// returned. The vault's rent goes to the authority as well. Can only be called
+    /// by the collection authority.
+    pub fn sweep_expired_distribution(ctx: Context<SweepExpiredDistribution>) -> Result<()> {
+        let distribution = &ctx.accounts.distribution;
+
+        require!(
+            distribution.is_expired(Clock::get()?.unix_timestamp),
+            ErrorCode::DistributionNotExpired
+        );
+
+        let amount_to_sweep = ctx.accounts.vault.amount;
 
-        // Transfer tokens from distribution vault to user
+        // Transfer remaining tokens from distribution vault to authority and close it
+        let id_bytes = distribution.id.to_le_bytes();
         let authority_seeds = &[
             b"distribution",
             distribution.collection.as_ref(),
-            distribution.mint.as_ref(),
+            &id_bytes,
             &[distribution.bump],
         ];
         let signer = &[&authority_seeds[..]];
 
         let transfer_ctx = CpiContext::new_with_signer(
             ctx.accounts.token_program.to_account_info(),
-            Transfer {
-                from: ctx.accounts.distribution_vault.to_account_info(),
-                to: ctx.accounts.user_token_account.to_account_info(),
+            TransferChecked {
+                from: ctx.accounts.vault.to_account_info(),
+                mint: ctx.accounts.mint.to_account_info(),
+                to: ctx.accounts.authority_token_account.to_account_info(),
+                authority: ctx.accounts.distribution.to_account_info(),
+            },
+            signer,
+        );
+        token_interface::transfer_checked(
+            transfer_ctx,
+            amount_to_sweep,
+            ctx.accounts.mint.decimals,
+        )?;
+
+        harvest_withheld_fees(
+            &ctx.accounts.vault.to_account_info(),
+            &ctx.accounts.mint.to_account_info(),
+            &ctx.accounts.token_program.to_account_info(),
+        )?;
+
+        let close_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            token_interface::CloseAccount {
+                account: ctx.accounts.vault.to_account_info(),
+                destination: ctx.accounts.authority.to_account_info(),
                 authority: ctx.accounts.distribution.to_account_info(),
             },
             signer,
         );
-        token::transfer(transfer_ctx, amount_to_receive)?;
+        token_interface::close_account(close_ctx)?;
+
+        let distribution = &mut ctx.accounts.distribution;
+        distribution.swept = true;
+        distribution.swept_tokens = amount_to_sweep;
+
+        emit_cpi!(DistributionSwept {
+            distribution: distribution.key(),
+            destination: ctx.accounts.authority_token_account.key(),
+            amount: amount_to_sweep,
+        });
+
+        Ok(())
+    }
+
+    /// Closes a wound down distribution and its vault, returning their rent to the
+    /// authority.
+    ///
+    /// No tokens may be owed to users anymore: either everything that was deposited
+    /// has been distributed or clawed back, or the distribution has expired. The vault
+    /// must be empty, so expired distributions are swept first, which already closes
+    /// the vault. Afterwards users can close their states of the distribution with
+    /// close_distribution_user_state. Distribution ids aren't reused, so the address
+    /// can't be initialized again. Can only be called by the collection authority.
+    pub fn close_distribution(ctx: Context<CloseDistribution>) -> Result<()> {
+        let distribution = &ctx.accounts.distribution;
+
+        let settled_tokens = distribution
+            .distributed_tokens
+            .checked_add(distribution.clawed_back_tokens)
+            .ok_or(ErrorCode::Overflow)?;
+        require!(
+            settled_tokens == distribution.lifetime_deposited_tokens
+                || distribution.is_expired(Clock::get()?.unix_timestamp),
+            ErrorCode::DistributionNotSettled
+        );
+
+        // The sweep already closed the vault
+        if !distribution.swept {
+            let vault = InterfaceAccount::<TokenAccount>::try_from(&ctx.accounts.vault)?;
+            require!(vault.amount == 0, ErrorCode::VaultNotEmpty);
+
+            let id_bytes = distribution.id.to_le_bytes();
+            let authority_seeds = &[
+                b"distribution",
+                distribution.collection.as_ref(),
+                &id_bytes,
+                &[distribution.bump],
+            ];
+            let signer = &[&authority_seeds[..]];
+
+            harvest_withheld_fees(
+                &ctx.accounts.vault.to_account_info(),
+                &ctx.accounts.mint.to_account_info(),
+                &ctx.accounts.token_program.to_account_info(),
+            )?;
+
+            let close_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token_interface::CloseAccount {
+                    account: ctx.accounts.vault.to_account_info(),
+                    destination: ctx.accounts.authority.to_account_info(),
+                    authority: ctx.accounts.distribution.to_account_info(),
+                },
+                signer,
+            );
+            token_interface::close_account(close_ctx)?;
+        }
+
+        let collection = &mut ctx.accounts.collection;
+        collection.open_distributions = collection
+            .open_distributions
+            .checked_sub(1)
+            .ok_or(ErrorCode::Overflow)?;
+
+        emit_cpi!(DistributionClosed {
+            distribution: ctx.accounts.distribution.key(),
+            collection: collection.key(),
+            distributed_tokens: ctx.accounts.distribution.distributed_tokens,
+        });
+
+        Ok(())
+    }
+
+    /// Commits tokens to a collection's vault.
+    ///
+    /// Users commit tokens to become eligible for distributions. Their share of
+    /// future distributions will be proportional to their committed amount relative
+    /// to the collection's max_collectable_tokens. Users receive freshly minted
+    /// replacement tokens equal to their committed amount. Commits are rejected
+    /// once the collection is finalized or closed to commits. For mints with a
+    /// transfer fee, the committed amount is what the vault actually received.
+    ///
+    /// If the collection has transferable claims, the deposit isn't credited to the
+    /// user directly: the replacement tokens carry the entitlement instead, and
+    /// whoever holds them can redeem them with redeem_replacement_tokens.
+    ///
+    /// If the collection has an allowlist, the user must prove their allowlisted
+    /// max_amount, which their deposited_amount may not exceed after the commit. The
+    /// collection's max_per_user caps deposited_amount the same way, and commits
+    /// whose weighted received amount is below min_commit are rejected. Commits are
+    /// only accepted within the collection's commit window, if it has one.
+    ///
+    /// Passing a collection_mint account commits tokens of that added mint instead,
+    /// credited and minted in weighted units as described in add_collection_mint.
+    ///
+    /// If the collection has a commit bonus, deposited_amount and
+    /// lifetime_tokens_collected are credited the weighted amount times the bonus
+    /// multiplier at the time of the commit, while replacement tokens are still minted
+    /// for the weighted amount alone. The minimum commit applies before the bonus.
+    ///
+    /// # Arguments
+    /// * `amount` - Number of tokens to commit to the collection
+    /// * `allowlist_proof` - The user's allowlist entry and merkle proof, required if
+    ///   the collection has an allowlist
+    pub fn user_commit_to_collection(
+        ctx: Context<UserCommitToCollection>,
+        amount: u64,
+        allowlist_proof: Option<AllowlistProof>,
+    ) -> Result<()> {
+        let collection = &ctx.accounts.collection;
+
+        require!(!collection.paused, ErrorCode::Paused);
+        require!(!collection.finalized, ErrorCode::CollectionFinalized);
+        require!(!collection.commits_closed, ErrorCode::CommitsClosed);
+        let now = Clock::get()?.unix_timestamp;
+        require!(
+            !matches!(collection.commit_start, Some(commit_start) if now < commit_start),
+            ErrorCode::CommitWindowNotStarted
+        );
+        require!(
+            !collection.commit_window_ended(now),
+            ErrorCode::CommitWindowEnded
+        );
+        let collection_mint = &ctx.accounts.collection_mint;
+
+        // Allowlisted collections only accept users with a valid proof
+        let allowlisted_amount = match (collection.allowlist_root, &allowlist_proof) {
+            (None, _) => None,
+            (Some(root), Some(allowlist_proof)) => {
+                require!(
+                    allowlist_proof.verify(&root, &ctx.accounts.user.key()),
+                    ErrorCode::NotAllowlisted
+                );
+                Some(allowlist_proof.max_amount)
+            }
+            (Some(_), None) => return err!(ErrorCode::NotAllowlisted),
+        };
+
+        // Either burn or transfer the tokens
+        let received_amount = if collection.burn_tokens {
+            let burn_ctx = CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token_interface::Burn {
+                    mint: ctx.accounts.mint.to_account_info(),
+                    from: ctx.accounts.user_token_account.to_account_info(),
+                    authority: ctx.accounts.user.to_account_info(),
+                },
+            );
+            token_interface::burn(burn_ctx, amount)?;
+            amount
+        } else {
+            let vault_amount_before = ctx.accounts.vault.amount;
+            let transfer_ctx = CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.user_token_account.to_account_info(),
+                    mint: ctx.accounts.mint.to_account_info(),
+                    to: ctx.accounts.vault.to_account_info(),
+                    authority: ctx.accounts.user.to_account_info(),
+                },
+            );
+            token_interface::transfer_checked(transfer_ctx, amount, ctx.accounts.mint.decimals)?;
+
+            // Transfer fees are withheld in the vault, so measure what actually arrived
+            ctx.accounts.vault.reload()?;
+            ctx.accounts
+                .vault
+                .amount
+                .checked_sub(vault_amount_before)
+                .ok_or(ErrorCode::Overflow)?
+        };
+        // Tokens of added mints are credited in weighted units, and early commits
+        // receive the bonus on top
+        let weighted_amount = CollectionMint::weigh(collection_mint.as_deref(), received_amount)?;
+        require!(
+            weighted_amount >= collection.min_commit,
+            ErrorCode::CommitBelowMinimum
+        );
+        let credited_amount = match &collection.commit_bonus {
+            Some(commit_bonus) => commit_bonus.apply(weighted_amount, now)?,
+            None => weighted_amount,
+        };
+
+        associated_token::create_idempotent(CpiContext::new(
+            ctx.accounts.associated_token_program.to_account_info(),
+            associated_token::Create {
+                payer: ctx.accounts.user.to_account_info(),
+                associated_token: ctx.accounts.user_replacement_token_account.to_account_info(),
+                authority: ctx.accounts.user.to_account_info(),
+                mint: ctx.accounts.replacement_mint.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+                token_program: ctx.accounts.token_program.to_account_info(),
+            },
+        ))?;
+
+        // Mint replacement tokens to user
+        let counter_bytes = collection.counter.to_le_bytes();
+        let seeds = &[
+            b"collection",
+            collection.creator.as_ref(),
+            collection.mint.as_ref(),
+            &counter_bytes,
+            &[collection.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        let mint_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            token_interface::MintTo {
+                mint: ctx.accounts.replacement_mint.to_account_info(),
+                to: ctx.accounts.user_replacement_token_account.to_account_info(),
+                authority: ctx.accounts.collection.to_account_info(),
+            },
+            signer,
+        );
+        token_interface::mint_to(mint_ctx, weighted_amount)?;
+
+        // Update states
+        if let Some(collection_mint) = &mut ctx.accounts.collection_mint {
+            collection_mint.lifetime_tokens_collected = collection_mint
+                .lifetime_tokens_collected
+                .checked_add(received_amount)
+                .ok_or(ErrorCode::Overflow)?;
+        }
+        let collection = &mut ctx.accounts.collection;
+        let user_state = &mut ctx.accounts.user_state;
+        collection.lifetime_tokens_collected = collection
+            .lifetime_tokens_collected
+            .checked_add(credited_amount)
+            .ok_or(ErrorCode::Overflow)?;
+        if !collection.transferable_claims {
+            user_state.deposited_amount = user_state
+                .deposited_amount
+                .checked_add(credited_amount)
+                .ok_or(ErrorCode::Overflow)?;
+        }
+
+        require!(
+            collection.lifetime_tokens_collected <= collection.max_collectable_tokens,
+            ErrorCode::MaxCollectableTokensExceeded
+        );
+        if let Some(max_amount) = allowlisted_amount {
+            require!(
+                user_state.deposited_amount <= max_amount,
+                ErrorCode::AllowlistAmountExceeded
+            );
+        }
+        if let Some(max_per_user) = collection.max_per_user {
+            require!(
+                user_state.deposited_amount <= max_per_user,
+                ErrorCode::MaxPerUserExceeded
+            );
+        }
+
+        emit_cpi!(TokensCommitted {
+            collection: collection.key(),
+            user: ctx.accounts.user.key(),
+            mint: ctx.accounts.mint.key(),
+            amount: received_amount,
+            weighted_amount,
+            credited_amount,
+            deposited_amount: user_state.deposited_amount,
+            lifetime_tokens_collected: collection.lifetime_tokens_collected,
+        });
+
+        Ok(())
+    }
+
+    /// Undoes a user's commit during the collection's refund window.
+    ///
+    /// The user's replacement tokens are burned and the original tokens are returned
+    /// from the vault. deposited_amount and lifetime_tokens_collected are reduced to
+    /// match. Refused once the window has closed, once the collection is finalized
+    /// (since that fixes the share denominator), or once any distribution has paid out
+    /// to the user.
+    ///
+    /// # Arguments
+    /// * `amount` - Number of committed tokens to get back
+    pub fn user_refund_from_collection(
+        ctx: Context<UserRefundFromCollection>,
+        amount: u64,
+    ) -> Result<()> {
+        let collection = &ctx.accounts.collection;
+        let user_state = &ctx.accounts.user_state;
+
+        require!(!collection.paused, ErrorCode::Paused);
+        require!(
+            collection.refunds_open(Clock::get()?.unix_timestamp),
+            ErrorCode::RefundWindowClosed
+        );
+        require!(!collection.finalized, ErrorCode::CollectionFinalized);
+        require!(
+            !user_state.received_distribution,
+            ErrorCode::DistributionAlreadyReceived
+        );
+
+        let burn_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            token_interface::Burn {
+                mint: ctx.accounts.replacement_mint.to_account_info(),
+                from: ctx.accounts.user_replacement_token_account.to_account_info(),
+                authority: ctx.accounts.user.to_account_info(),
+            },
+        );
+        token_interface::burn(burn_ctx, amount)?;
+
+        // Transfer tokens from collection vault back to the user
+        let counter_bytes = collection.counter.to_le_bytes();
+        let authority_seeds = &[
+            b"collection",
+            collection.creator.as_ref(),
+            collection.mint.as_ref(),
+            &counter_bytes,
+            &[collection.bump],
+        ];
+        let signer = &[&authority_seeds[..]];
+
+        let transfer_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            TransferChecked {
+                from: ctx.accounts.vault.to_account_info(),
+                mint: ctx.accounts.mint.to_account_info(),
+                to: ctx.accounts.user_token_account.to_account_info(),
+                authority: ctx.accounts.collection.to_account_info(),
+            },
+            signer,
+        );
+        token_interface::transfer_checked(transfer_ctx, amount, ctx.accounts.mint.decimals)?;
+
+        // Update states
+        let collection = &mut ctx.accounts.collection;
+        let user_state = &mut ctx.accounts.user_state;
+        user_state.deposited_amount = user_state
+            .deposited_amount
+            .checked_sub(amount)
+            .ok_or(ErrorCode::RefundExceedsDeposit)?;
+        collection.lifetime_tokens_collected = collection
+            .lifetime_tokens_collected
+            .checked_sub(amount)
+            .ok_or(ErrorCode::Overflow)?;
+
+        emit_cpi!(TokensRefunded {
+            collection: collection.key(),
+            user: ctx.accounts.user.key(),
+            amount,
+            deposited_amount: user_state.deposited_amount,
+            lifetime_tokens_collected: collection.lifetime_tokens_collected,
+        });
+
+        Ok(())
+    }
+
+    /// Redeems replacement tokens of a collection with transferable claims into a
+    /// claim position.
+    ///
+    /// The replacement tokens are burned and the amount is credited to the holder's
+    /// deposited_amount, which entitles them to their share of all distributions,
+    /// including tokens distributed before the redemption. This way buyers of the
+    /// replacement token on secondary markets can claim as well.
+    ///
+    /// # Arguments
+    /// * `amount` - Number of replacement tokens to redeem
+    pub fn redeem_replacement_tokens(
+        ctx: Context<RedeemReplacementTokens>,
+        amount: u64,
+    ) -> Result<()> {
+        require!(!ctx.accounts.collection.paused, ErrorCode::Paused);
+        require!(
+            ctx.accounts.collection.transferable_claims,
+            ErrorCode::ClaimsNotTransferable
+        );
+
+        let burn_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            token_interface::Burn {
+                mint: ctx.accounts.replacement_mint.to_account_info(),
+                from: ctx.accounts.user_replacement_token_account.to_account_info(),
+                authority: ctx.accounts.user.to_account_info(),
+            },
+        );
+        token_interface::burn(burn_ctx, amount)?;
+
+        let user_state = &mut ctx.accounts.user_state;
+        user_state.deposited_amount = user_state
+            .deposited_amount
+            .checked_add(amount)
+            .ok_or(ErrorCode::Overflow)?;
+
+        emit_cpi!(ReplacementTokensRedeemed {
+            collection: ctx.accounts.collection.key(),
+            user: ctx.accounts.user.key(),
+            amount,
+            deposited_amount: user_state.deposited_amount,
+        });
+
+        Ok(())
+    }
+
+    /// Claims a user's share of tokens from a distribution.
+    ///
+    /// The amount claimed is proportional to the user's deposit in the collection
+    /// relative to the collection's max_collectable_tokens, or to its
+    /// lifetime_tokens_collected once finalized. If the distribution has a vesting
+    /// schedule, only the vested part of its tokens is shared. Can be called multiple
+    /// times as more tokens are added or vest, until the distribution expires.
+    pub fn user_claim_from_distribution(ctx: Context<UserClaimFromDistribution>) -> Result<()> {
+        let amount_to_receive = process_claim(
+            &ctx.accounts.collection,
+            &mut ctx.accounts.distribution,
+            &mut ctx.accounts.collection_user_state,
+            &mut ctx.accounts.distribution_user_state,
+            &ctx.accounts.distribution_vault,
+            &ctx.accounts.distribution_mint,
+            &ctx.accounts.user_token_account.to_account_info(),
+            &ctx.accounts.token_program,
+        )?;
+
+        emit_cpi!(TokensClaimed {
+            collection: ctx.accounts.collection.key(),
+            distribution: ctx.accounts.distribution.key(),
+            user: ctx.accounts.user.key(),
+            amount: amount_to_receive,
+            received_amount: ctx.accounts.distribution_user_state.received_amount,
+            distributed_tokens: ctx.accounts.distribution.distributed_tokens,
+        });
+
+        Ok(())
+    }
+
+    /// Claims a user's share of tokens from a distribution on their behalf.
+    ///
+    /// Works like user_claim_from_distribution, but anyone can call it: the tokens
+    /// still only go to the user's associated token account, while the payer funds
+    /// the rent of the user's distribution state and, if missing, of that token
+    /// account. This allows pushing distributions out to inactive depositors. Not
+    /// possible while the collection's refund window is open, since receiving a
+    /// distribution ends the user's right to a refund, which only they may give up.
+    pub fn claim_from_distribution_for_user(
+        ctx: Context<ClaimFromDistributionForUser>,
+    ) -> Result<()> {
+        require!(
+            !ctx.accounts.collection.refunds_open(Clock::get()?.unix_timestamp),
+            ErrorCode::RefundWindowOpen
+        );
+
+        associated_token::create_idempotent(CpiContext::new(
+            ctx.accounts.associated_token_program.to_account_info(),
+            associated_token::Create {
+                payer: ctx.accounts.payer.to_account_info(),
+                associated_token: ctx.accounts.user_token_account.to_account_info(),
+                authority: ctx.accounts.user.to_account_info(),
+                mint: ctx.accounts.distribution_mint.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+                token_program: ctx.accounts.token_program.to_account_info(),
+            },
+        ))?;
+
+        let amount_to_receive = process_claim(
+            &ctx.accounts.collection,
+            &mut ctx.accounts.distribution,
+            &mut ctx.accounts.collection_user_state,
+            &mut ctx.accounts.distribution_user_state,
+            &ctx.accounts.distribution_vault,
+            &ctx.accounts.distribution_mint,
+            &ctx.accounts.user_token_account.to_account_info(),
+            &ctx.accounts.token_program,
+        )?;
+
+        emit_cpi!(TokensClaimed {
+            collection: ctx.accounts.collection.key(),
+            distribution: ctx.accounts.distribution.key(),
+            user: ctx.accounts.user.key(),
+            amount: amount_to_receive,
+            received_amount: ctx.accounts.distribution_user_state.received_amount,
+            distributed_tokens: ctx.accounts.distribution.distributed_tokens,
+        });
+
+        Ok(())
+    }
+
+    /// Claims a user's share of tokens from several distributions of a collection at once.
+    ///
+    /// Works like user_claim_from_distribution for every distribution passed in the
+    /// remaining accounts, in groups of CLAIM_ACCOUNTS_PER_DISTRIBUTION:
+    /// 1. the distribution (writable)
+    /// 2. its mint
+    /// 3. its vault (writable)
+    /// 4. the user's distribution state PDA (writable), created if missing
+    /// 5. the user's associated token account for the distribution mint (writable)
+    ///
+    /// Each group is validated like the accounts of the single claim instruction.
+    /// All distribution mints must belong to the passed token program.
+    pub fn user_claim_from_distributions<'info>(
+        ctx: Context<'_, '_, '_, 'info, UserClaimFromDistributions<'info>>,
+    ) -> Result<()> {
+        let remaining_accounts = ctx.remaining_accounts;
+        require!(
+            !remaining_accounts.is_empty()
+                && remaining_accounts.len() % CLAIM_ACCOUNTS_PER_DISTRIBUTION == 0,
+            ErrorCode::InvalidRemainingAccounts
+        );
+        for (index, info) in remaining_accounts.iter().enumerate() {
+            // Only the mint of each group is read-only
+            require!(
+                info.is_writable || index % CLAIM_ACCOUNTS_PER_DISTRIBUTION == 1,
+                anchor_lang::error::ErrorCode::ConstraintMut
+            );
+        }
+
+        let collection_key = ctx.accounts.collection.key();
+        let user_key = ctx.accounts.user.key();
+
+        for accounts in remaining_accounts.chunks(CLAIM_ACCOUNTS_PER_DISTRIBUTION) {
+            let mut distribution = Account::<Distribution>::try_from(&accounts[0])?;
+            require_keys_eq!(
+                distribution.collection,
+                collection_key,
+                anchor_lang::error::ErrorCode::ConstraintHasOne
+            );
+            let distribution_key = distribution.key();
+
+            let distribution_mint = InterfaceAccount::<Mint>::try_from(&accounts[1])?;
+            require_keys_eq!(
+                distribution_mint.key(),
+                distribution.mint,
+                anchor_lang::error::ErrorCode::ConstraintAddress
+            );
+            require_keys_eq!(
+                *accounts[1].owner,
+                ctx.accounts.token_program.key(),
+                anchor_lang::error::ErrorCode::ConstraintMintTokenProgram
+            );
+
+            let distribution_vault = InterfaceAccount::<TokenAccount>::try_from(&accounts[2])?;
+            require_keys_eq!(
+                distribution_vault.key(),
+                distribution.vault,
+                anchor_lang::error::ErrorCode::ConstraintAddress
+            );
+
+            let (user_state_key, user_state_bump) = Pubkey::find_program_address(
+                &[
+                    b"distribution_user_state",
+                    distribution_key.as_ref(),
+                    user_key.as_ref(),
+                ],
+                ctx.program_id,
+            );
+            require_keys_eq!(
+                accounts[3].key(),
+                user_state_key,
+                anchor_lang::error::ErrorCode::ConstraintSeeds
+            );
+            if accounts[3].owner == &System::id() {
+                create_distribution_user_state(
+                    &accounts[3],
+                    &ctx.accounts.user.to_account_info(),
+                    &ctx.accounts.system_program.to_account_info(),
+                    &[
+                        b"distribution_user_state",
+                        distribution_key.as_ref(),
+                        user_key.as_ref(),
+                        &[user_state_bump],
+                    ],
+                )?;
+            }
+            let mut distribution_user_state =
+                Account::<DistributionUserState>::try_from(&accounts[3])?;
+
+            require_keys_eq!(
+                accounts[4].key(),
+                get_associated_token_address_with_program_id(
+                    &user_key,
+                    &distribution.mint,
+                    &ctx.accounts.token_program.key()
+                ),
+                anchor_lang::error::ErrorCode::AccountNotAssociatedTokenAccount
+            );
+
+            let amount_to_receive = process_claim(
+                &ctx.accounts.collection,
+                &mut distribution,
+                &mut ctx.accounts.collection_user_state,
+                &mut distribution_user_state,
+                &distribution_vault,
+                &distribution_mint,
+                &accounts[4],
+                &ctx.accounts.token_program,
+            )?;
+
+            emit_cpi!(TokensClaimed {
+                collection: collection_key,
+                distribution: distribution_key,
+                user: user_key,
+                amount: amount_to_receive,
+                received_amount: distribution_user_state.received_amount,
+                distributed_tokens: distribution.distributed_tokens,
+            });
+
+            // Persist right away, so a distribution listed twice sees its updated state
+            distribution.exit(ctx.program_id)?;
+            distribution_user_state.exit(ctx.program_id)?;
+        }
+
+        Ok(())
+    }
+
+    /// Closes a user's collection state and returns its rent to the user.
+    ///
+    /// Only possible once nothing is deposited anymore, for example after a full
+    /// refund, or for committers of a collection with transferable claims who never
+    /// redeemed. Recreating the account by committing or redeeming again then yields
+    /// the same state, so closing can't be used to gain anything. Once the collection
+    /// itself is closed, the state can be closed regardless of the deposit, since a
+    /// closed collection can't be initialized again.
+    pub fn close_collection_user_state(ctx: Context<CloseCollectionUserState>) -> Result<()> {
+        let collection_closed = ctx.accounts.collection.owner != ctx.program_id;
+        require!(
+            collection_closed || ctx.accounts.user_state.deposited_amount == 0,
+            ErrorCode::DepositOutstanding
+        );
+
+        emit_cpi!(CollectionUserStateClosed {
+            collection: ctx.accounts.collection.key(),
+            user: ctx.accounts.user.key(),
+        });
+
+        Ok(())
+    }
+
+    /// Closes a user's distribution state and returns its rent to the user.
+    ///
+    /// The state records how much the user already received, so it may only be
+    /// closed when it can't be recreated to claim the same tokens twice: either the
+    /// distribution has expired (which includes swept distributions), so claims are
+    /// rejected, or the user never received anything from it. States of a closed
+    /// distribution can always be closed.
+    pub fn close_distribution_user_state(ctx: Context<CloseDistributionUserState>) -> Result<()> {
+        let received_amount = ctx.accounts.distribution_user_state.received_amount;
+
+        let claims_over = if ctx.accounts.distribution.owner == ctx.program_id {
+            let distribution = Account::<Distribution>::try_from(&ctx.accounts.distribution)?;
+            distribution.is_expired(Clock::get()?.unix_timestamp)
+        } else {
+            true
+        };
+        require!(
+            claims_over || received_amount == 0,
+            ErrorCode::DistributionUserStateInUse
+        );
+
+        emit_cpi!(DistributionUserStateClosed {
+            distribution: ctx.accounts.distribution.key(),
+            user: ctx.accounts.user.key(),
+            received_amount,
+        });
+
+        Ok(())
+    }
+}
+
+/// Creates a user's DistributionUserState PDA for user_claim_from_distributions,
+/// the way init_if_needed does for the single claim instructions.
+///
+/// Handles accounts that were already sent lamports, so nobody can block the
+/// creation by funding the address upfront.
+fn create_distribution_user_state<'info>(
+    distribution_user_state: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    seeds: &[&[u8]],
+) -> Result<()> {
+    let space = 8 + size_of::<DistributionUserState>();
+    let rent = Rent::get()?.minimum_balance(space);
+    let signer = &[seeds];
+
+    let current_lamports = distribution_user_state.lamports();
+    if current_lamports == 0 {
+        system_program::create_account(
+            CpiContext::new_with_signer(
+                system_program.clone(),
+                system_program::CreateAccount {
+                    from: payer.clone(),
+                    to: distribution_user_state.clone(),
+                },
+                signer,
+            ),
+            rent,
+            space as u64,
+            &crate::ID,
+        )?;
+    } else {
+        let top_up = rent.saturating_sub(current_lamports);
+        if top_up > 0 {
+            system_program::transfer(
+                CpiContext::new(
+                    system_program.clone(),
+                    system_program::Transfer {
+                        from: payer.clone(),
+                        to: distribution_user_state.clone(),
+                    },
+                ),
+                top_up,
+            )?;
+        }
+        system_program::allocate(
+            CpiContext::new_with_signer(
+                system_program.clone(),
+                system_program::Allocate {
+                    account_to_allocate: distribution_user_state.clone(),
+                },
+                signer,
+            ),
+            space as u64,
+        )?;
+        system_program::assign(
+            CpiContext::new_with_signer(
+                system_program.clone(),
+                system_program::Assign {
+                    account_to_assign: distribution_user_state.clone(),
+                },
+                signer,
+            ),
+            &crate::ID,
+        )?;
+    }
+
+    // Write the discriminator, so the account deserializes as a DistributionUserState
+    let mut data = distribution_user_state.try_borrow_mut_data()?;
+    DistributionUserState { received_amount: 0 }.try_serialize(&mut &mut data[..])
+}
+
+/// Moves the transfer fees withheld in a vault to its mint, so the vault can be closed.
+///
+/// Token-2022 refuses to close token accounts that still hold withheld fees. The
+/// harvest is permissionless and leaves the fees to the mint's withdraw authority.
+/// Does nothing for vaults without withheld fees, including all SPL Token vaults.
+fn harvest_withheld_fees<'info>(
+    vault: &AccountInfo<'info>,
+    mint: &AccountInfo<'info>,
+    token_program: &AccountInfo<'info>,
+) -> Result<()> {
+    if *vault.owner != spl_token_2022::ID {
+        return Ok(());
+    }
+
+    let withheld_amount = {
+        let data = vault.try_borrow_data()?;
+        let vault_state = StateWithExtensions::<spl_token_2022::state::Account>::unpack(&data)?;
+        vault_state
+            .get_extension::<TransferFeeAmount>()
+            .map_or(0, |fee_amount| u64::from(fee_amount.withheld_amount))
+    };
+    if withheld_amount == 0 {
+        return Ok(());
+    }
+
+    let instruction = harvest_withheld_tokens_to_mint(token_program.key, mint.key, &[vault.key])?;
+    invoke(&instruction, &[mint.clone(), vault.clone(), token_program.clone()])?;
+    Ok(())
+}
+
+/// Pays out a user's outstanding share of a distribution and updates the claim state.
+///
+/// Shared by all claim instructions. Returns the amount transferred to the user.
+#[allow(clippy::too_many_arguments)]
+fn process_claim<'info>(
+    collection: &Account<'info, Collection>,
+    distribution: &mut Account<'info, Distribution>,
+    collection_user_state: &mut Account<'info, CollectionUserState>,
+    distribution_user_state: &mut Account<'info, DistributionUserState>,
+    distribution_vault: &InterfaceAccount<'info, TokenAccount>,
+    distribution_mint: &InterfaceAccount<'info, Mint>,
+    user_token_account: &AccountInfo<'info>,
+    token_program: &Interface<'info, TokenInterface>,
+) -> Result<u64> {
+    require!(
+        !collection.paused && !distribution.paused,
+        ErrorCode::Paused
+    );
+    let now = Clock::get()?.unix_timestamp;
+    require!(
+        !distribution.is_expired(now),
+        ErrorCode::DistributionExpired
+    );
+
+    // Calculate user's share:
+    // Since claims can happen at any time, in particular before all users have deposited, the fixed max_collectable_tokens denominator is used.
+    // That means that if less than max_collectable_tokens end up deposited, a large
+    // part of the distribution may not be handed out.
+    // If this becomes a problem, the authority may decrease max_collectable_tokens by
+    // calling decrease_collection_max_collectable_tokens, or finalize the collection.
+    // After finalization the denominator is lifetime_tokens_collected, which can't
+    // change anymore. Because received_amount records the user's full share so far,
+    // users who claimed under the larger denominator get the difference paid out here.
+    let user_share = (collection_user_state.deposited_amount as u128)
+        .checked_mul(distribution.vested_tokens(now)? as u128)
+        .ok_or(ErrorCode::Overflow)?
+        // Integer division rounds down, ensuring we never overpay users
+        .checked_div(collection.share_denominator() as u128)
+        .ok_or(ErrorCode::Overflow)? as u64;
+
+    let amount_to_receive = user_share
+        .checked_sub(distribution_user_state.received_amount)
+        .ok_or(ErrorCode::Overflow)?;
+
+    // Note that amount_to_receive may be zero. That is ok, the instruction
+    // should nevertheless succeed.
+
+    // Transfer tokens from distribution vault to user
+    let id_bytes = distribution.id.to_le_bytes();
+    let authority_seeds = &[
+        b"distribution",
+        distribution.collection.as_ref(),
+        &id_bytes,
+        &[distribution.bump],
+    ];
+    let signer = &[&authority_seeds[..]];
+
+    let transfer_ctx = CpiContext::new_with_signer(
+        token_program.to_account_info(),
+        TransferChecked {
+            from: distribution_vault.to_account_info(),
+            mint: distribution_mint.to_account_info(),
+            to: user_token_account.clone(),
+            authority: distribution.to_account_info(),
+        },
+        signer,
+    );
+    token_interface::transfer_checked(transfer_ctx, amount_to_receive, distribution_mint.decimals)?;
+
+    // Update states
+    distribution_user_state.received_amount = user_share;
+    if amount_to_receive > 0 {
+        // Deposits that were paid out on can't be refunded anymore
+        collection_user_state.received_distribution = true;
+    }
+    distribution.distributed_tokens = distribution
+        .distributed_tokens
+        .checked_add(amount_to_receive)
+        .ok_or(ErrorCode::Overflow)?;
+
+    Ok(amount_to_receive)
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(counter: u64)]
+pub struct InitCollection<'info> {
+    /// The collection PDA that is created to hold configuration and state
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + size_of::<Collection>(),
+        seeds = [
+            b"collection",
+            authority.key().as_ref(),
+            mint.key().as_ref(),
+            counter.to_le_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub collection: Account<'info, Collection>,
+
+    /// The SPL token mint for tokens being collected, under either token program
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// CHECK: Associated token account owned by the collection PDA that holds deposited
+    /// tokens, created by the instruction. Anchor 0.28's associated_token constraints
+    /// only derive addresses for the legacy token program, so it is checked by address.
+    #[account(
+        mut,
+        address = get_associated_token_address_with_program_id(
+            &collection.key(),
+            &mint.key(),
+            &token_program.key()
+        )
+    )]
+    pub vault: UncheckedAccount<'info>,
+
+    /// The replacement mint owned by the collection
+    #[account(
+        init,
+        payer = authority,
+        mint::decimals = mint.decimals,
+        mint::authority = collection,
+        seeds = [
+            b"replacement_mint",
+            collection.key().as_ref()
+        ],
+        bump
+    )]
+    pub replacement_mint: InterfaceAccount<'info, Mint>,
+
+    /// The authority who can manage this collection and pays for these accounts
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct ProposeAuthority<'info> {
+    /// The collection whose authority is being transferred
+    #[account(
+        mut,
+        has_one = authority
+    )]
+    pub collection: Account<'info, Collection>,
+
+    /// The current authority of the collection
+    pub authority: Signer<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct AcceptAuthority<'info> {
+    /// The collection whose authority is being transferred
+    #[account(mut)]
+    pub collection: Account<'info, Collection>,
+
+    /// The proposed authority accepting the role
+    pub new_authority: Signer<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct SetCollectionGuardian<'info> {
+    /// The collection whose guardian is set
+    #[account(
+        mut,
+        has_one = authority
+    )]
+    pub collection: Account<'info, Collection>,
+
+    /// The authority of the collection
+    pub authority: Signer<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct SetCollectionAllowlist<'info> {
+    /// The collection whose allowlist is set
+    #[account(
+        mut,
+        has_one = authority
+    )]
+    pub collection: Account<'info, Collection>,
+
+    /// The authority of the collection
+    pub authority: Signer<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct SetReplacementMintMetadata<'info> {
+    /// The collection owning the replacement mint
+    #[account(
+        has_one = authority
+    )]
+    pub collection: Account<'info, Collection>,
+
+    /// The replacement mint to describe
+    #[account(
+        address = collection.replacement_mint
+    )]
+    pub replacement_mint: InterfaceAccount<'info, Mint>,
+
+    /// CHECK: The Metaplex metadata PDA of the replacement mint, created or updated
+    /// by the metadata program
+    #[account(
+        mut,
+        seeds = [
+            b"metadata",
+            token_metadata_program.key().as_ref(),
+            replacement_mint.key().as_ref()
+        ],
+        bump,
+        seeds::program = token_metadata_program.key()
+    )]
+    pub metadata: UncheckedAccount<'info>,
+
+    /// The authority of the collection, paying for the metadata account
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub token_metadata_program: Program<'info, Metadata>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct SetCollectionPaused<'info> {
+    /// The collection to pause or unpause
+    #[account(
+        mut,
+        constraint = collection.is_authority_or_guardian(&signer.key()) @ ErrorCode::NotAuthorityOrGuardian
+    )]
+    pub collection: Account<'info, Collection>,
+
+    /// The authority or guardian of the collection
+    pub signer: Signer<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct SetDistributionPaused<'info> {
+    /// The collection associated with this distribution
+    #[account(
+        constraint = collection.is_authority_or_guardian(&signer.key()) @ ErrorCode::NotAuthorityOrGuardian
+    )]
+    pub collection: Account<'info, Collection>,
+
+    /// The distribution to pause or unpause
+    #[account(
+        mut,
+        has_one = collection
+    )]
+    pub distribution: Account<'info, Distribution>,
+
+    /// The authority or guardian of the collection
+    pub signer: Signer<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct DecreaseCollectionMaxTokens<'info> {
+    /// The collection whose max tokens will be decreased
+    #[account(
+        mut,
+        constraint = collection.authority == authority.key()
+    )]
+    pub collection: Account<'info, Collection>,
+
+    /// The authority of the collection
+    pub authority: Signer<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct DecreaseCollectionMaxPerUser<'info> {
+    /// The collection whose per-user cap will be decreased
+    #[account(
+        mut,
+        has_one = authority
+    )]
+    pub collection: Account<'info, Collection>,
+
+    /// The authority of the collection
+    pub authority: Signer<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct DecreaseCollectionMinCommit<'info> {
+    /// The collection whose minimum commit will be decreased
+    #[account(
+        mut,
+        has_one = authority
+    )]
+    pub collection: Account<'info, Collection>,
+
+    /// The authority of the collection
+    pub authority: Signer<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct SetCollectionCommitEnd<'info> {
+    /// The collection whose commit window is changed
+    #[account(
+        mut,
+        has_one = authority
+    )]
+    pub collection: Account<'info, Collection>,
+
+    /// The authority of the collection
+    pub authority: Signer<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct FinalizeCollection<'info> {
+    /// The collection to finalize
+    #[account(
+        mut,
+        has_one = authority
+    )]
+    pub collection: Account<'info, Collection>,
+
+    /// The authority of the collection
+    pub authority: Signer<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct CloseCollectionCommits<'info> {
+    /// The collection to close for commits
+    #[account(
+        mut,
+        has_one = authority
+    )]
+    pub collection: Account<'info, Collection>,
+
+    /// The authority of the collection
+    pub authority: Signer<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct WithdrawFromCollection<'info> {
+    /// The collection to withdraw from
+    #[account(
+        has_one = authority
+    )]
+    pub collection: Account<'info, Collection>,
+
+    /// The added mint to withdraw, or None for the collection's own mint
+    #[account(
+        has_one = collection
+    )]
+    pub collection_mint: Option<Account<'info, CollectionMint>>,
+
+    /// The collection's vault for the mint, holding the tokens to withdraw
+    #[account(
+        mut,
+        address = collection_mint.as_ref().map_or(collection.vault, |added| added.vault)
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The mint of the collected tokens
+    #[account(
+        address = collection_mint.as_ref().map_or(collection.mint, |added| added.mint)
+    )]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// The token account to receive the withdrawn tokens
+    #[account(
+        mut,
+        token::mint = vault.mint
+    )]
+    pub authority_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The authority of the collection
+    pub authority: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct CloseCollection<'info> {
+    /// The collection to close
+    #[account(
+        mut,
+        close = authority,
+        has_one = authority
+    )]
+    pub collection: Account<'info, Collection>,
+
+    /// The collection's vault, closed
+    #[account(
+        mut,
+        address = collection.vault
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The collected mint, receiving the vault's withheld transfer fees
+    #[account(
+        mut,
+        address = collection.mint
+    )]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// The replacement mint owned by the collection, whose mint authority is revoked
+    #[account(
+        mut,
+        address = collection.replacement_mint
+    )]
+    pub replacement_mint: InterfaceAccount<'info, Mint>,
+
+    /// The authority of the collection, receiving the rent
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct AddCollectionMint<'info> {
+    /// The collection accepting the mint
+    #[account(
+        mut,
+        has_one = authority,
+        has_one = replacement_mint
+    )]
+    pub collection: Account<'info, Collection>,
+
+    /// PDA holding the mint's weight
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + size_of::<CollectionMint>(),
+        seeds = [
+            b"collection_mint",
+            collection.key().as_ref(),
+            mint.key().as_ref()
+        ],
+        bump
+    )]
+    pub collection_mint: Account<'info, CollectionMint>,
+
+    /// The added mint, under the replacement mint's token program
+    #[account(
+        constraint = mint.key() != collection.mint @ ErrorCode::InvalidCollectionMint,
+        constraint = mint.key() != collection.replacement_mint @ ErrorCode::InvalidCollectionMint,
+        constraint = *mint.to_account_info().owner == token_program.key() @ ErrorCode::InvalidCollectionMint
+    )]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// CHECK: Associated token account owned by the collection PDA that holds the
+    /// added mint's deposits, created by the instruction
+    #[account(
+        mut,
+        address = get_associated_token_address_with_program_id(
+            &collection.key(),
+            &mint.key(),
+            &token_program.key()
+        )
+    )]
+    pub vault: UncheckedAccount<'info>,
+
+    /// The collection's replacement mint, fixing the token program
+    #[account(
+        constraint = *replacement_mint.to_account_info().owner == token_program.key() @ ErrorCode::InvalidCollectionMint
+    )]
+    pub replacement_mint: InterfaceAccount<'info, Mint>,
+
+    /// The authority of the collection, paying for the new accounts
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct CloseCollectionMint<'info> {
+    /// The collection that stops accepting the mint
+    #[account(
+        mut,
+        has_one = authority
+    )]
+    pub collection: Account<'info, Collection>,
+
+    /// The added mint's PDA, closed
+    #[account(
+        mut,
+        close = authority,
+        has_one = collection,
+        has_one = vault
+    )]
+    pub collection_mint: Account<'info, CollectionMint>,
+
+    /// The added mint's vault, closed
+    #[account(mut)]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The added mint, receiving the vault's withheld transfer fees
+    #[account(
+        mut,
+        address = collection_mint.mint
+    )]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// The authority of the collection, receiving the rent
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct InitDistribution<'info> {
+    /// The created distribution PDA that manages token distribution
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + size_of::<Distribution>(),
+        seeds = [
+            b"distribution",
+            collection.key().as_ref(),
+            collection.distribution_count.to_le_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub distribution: Account<'info, Distribution>,
+
+    /// The collection this distribution is associated with
+    #[account(
+        mut,
+        has_one = authority
+    )]
+    pub collection: Account<'info, Collection>,
+
+    /// The SPL token mint for tokens being distributed. Can be the same as or
+    /// different from the collection's mint, and under either token program.
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// CHECK: Associated token account owned by the distribution PDA that holds tokens
+    /// to distribute, created by the instruction
+    #[account(
+        mut,
+        address = get_associated_token_address_with_program_id(
+            &distribution.key(),
+            &mint.key(),
+            &token_program.key()
+        )
+    )]
+    pub vault: UncheckedAccount<'info>,
+
+    /// The collection's authority and payer for the distribution accounts
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+/// Add tokens to a distribution.
+///
+/// Note that _anyone_ can add tokens. This is because often the authority for
+/// tokens to be distributed may not be the same as the collection authority.
+#[event_cpi]
+#[derive(Accounts)]
+pub struct AddDistributionTokens<'info> {
+    /// The distribution to add tokens to
+    #[account(mut)]
+    pub distribution: Account<'info, Distribution>,
+
+    /// The distribution's vault to receive the tokens
+    #[account(
+        mut,
+        constraint = vault.key() == distribution.vault
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The mint of the distributed tokens
+    #[account(
+        address = distribution.mint
+    )]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// The token account providing the tokens to distribute
+    #[account(
+        mut,
+        constraint = authority_token_account.mint == vault.mint
+    )]
+    pub authority_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The signer who owns the token account providing the tokens
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct ClawbackDistributionRemainder<'info> {
+    /// The collection associated with this distribution
+    #[account(
+        has_one = authority
+    )]
+    pub collection: Account<'info, Collection>,
+
+    /// The distribution to claw back unclaimable tokens from
+    #[account(
+        mut,
+        has_one = collection
+    )]
+    pub distribution: Account<'info, Distribution>,
+
+    /// The distribution's vault, holding the tokens to claw back
+    #[account(
+        mut,
+        address = distribution.vault
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The mint of the distributed tokens
+    #[account(
+        address = distribution.mint
+    )]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// The token account to receive the clawed back tokens
+    #[account(
+        mut,
+        token::mint = distribution.mint
+    )]
+    pub authority_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The authority of the collection
+    pub authority: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct SweepExpiredDistribution<'info> {
+    /// The collection associated with this distribution
+    #[account(
+        has_one = authority
+    )]
+    pub collection: Account<'info, Collection>,
+
+    /// The expired distribution to sweep
+    #[account(
+        mut,
+        has_one = collection
+    )]
+    pub distribution: Account<'info, Distribution>,
+
+    /// The distribution's vault, swept and closed
+    #[account(
+        mut,
+        address = distribution.vault
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// The mint of the distributed tokens, receiving the vault's withheld transfer fees
+    #[account(
+        mut,
+        address = distribution.mint
+    )]
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    /// The token account to receive the swept tokens
+    #[account(
+        mut,
+        token::mint = distribution.mint
+    )]
+    pub authority_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The authority of the collection, receiving the vault's rent
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct CloseDistribution<'info> {
+    /// The collection associated with this distribution
+    #[account(
+        mut,
+        has_one = authority
+    )]
+    pub collection: Account<'info, Collection>,
+
+    /// The distribution to close
+    #[account(
+        mut,
+        close = authority,
+        has_one = collection
+    )]
+    pub distribution: Account<'info, Distribution>,
+
+    /// CHECK: The distribution's vault, closed unless the sweep already closed it
+    #[account(
+        mut,
+        address = distribution.vault
+    )]
+    pub vault: UncheckedAccount<'info>,
 
-        // Update states
-        let distribution = &mut ctx.accounts.distribution;
-        distribution_user_state.received_amount = user_share;
-        distribution.distributed_tokens = distribution
-            .distributed_tokens
-            .checked_add(amount_to_receive)
-            .ok_or(ErrorCode::Overflow)?;
+    /// The mint of the distributed tokens, receiving the vault's withheld transfer fees
+    #[account(
+        mut,
+        address = distribution.mint
+    )]
+    pub mint: InterfaceAccount<'info, Mint>,
 
-        Ok(())
-    }
+    /// The authority of the collection, receiving the rent
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
 }
 
+#[event_cpi]
 #[derive(Accounts)]
-#[instruction(counter: u64)]
-pub struct InitCollection<'info> {
-    /// The collection PDA that is created to hold configuration and state
+pub struct UserCommitToCollection<'info> {
+    /// The collection to commit tokens to
+    #[account(mut)]
+    pub collection: Account<'info, Collection>,
+
+    /// PDA tracking this user's deposits to this collection
     #[account(
-        init,
-        payer = authority,
-        space = 8 + size_of::<Collection>(),
+        init_if_needed,
+        payer = user,
+        space = 8 + size_of::<CollectionUserState>(),
         seeds = [
-            b"collection",
-            authority.key().as_ref(),
-            mint.key().as_ref(),
-            counter.to_le_bytes().as_ref()
+            b"user_state",
+            collection.key().as_ref(),
+            user.key().as_ref()
         ],
         bump
     )]
-    pub collection: Account<'info, Collection>,
+    pub user_state: Account<'info, CollectionUserState>,
+
+    /// The added mint being committed, or None for the collection's own mint
+    #[account(
+        mut,
+        has_one = collection
+    )]
+    pub collection_mint: Option<Account<'info, CollectionMint>>,
 
     /// The SPL token mint for tokens being collected
-    pub mint: Account<'info, Mint>,
+    #[account(
+        mut,
+        address = collection_mint.as_ref().map_or(collection.mint, |added| added.mint)
+    )]
+    pub mint: InterfaceAccount<'info, Mint>,
 
-    /// Associated token account owned by the collection PDA that holds deposited tokens
+    /// The token account providing the tokens to deposit
     #[account(
-        init,
-        payer = authority,
-        associated_token::mint = mint,
-        associated_token::authority = collection
+        mut,
+        token::mint = mint
+    )]
+    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The collection's vault for the mint, receiving the deposited tokens (if not burning)
+    #[account(
+        mut,
+        address = collection_mint.as_ref().map_or(collection.vault, |added| added.vault)
     )]
-    pub vault: Account<'info, TokenAccount>,
+    pub vault: InterfaceAccount<'info, TokenAccount>,
 
     /// The replacement mint owned by the collection
     #[account(
-        init,
-        payer = authority,
-        mint::decimals = mint.decimals,
-        mint::authority = collection,
-        seeds = [
-            b"replacement_mint",
-            collection.key().as_ref()
-        ],
-        bump
+        mut,
+        address = collection.replacement_mint
     )]
-    pub replacement_mint: Account<'info, Mint>,
+    pub replacement_mint: InterfaceAccount<'info, Mint>,
 
-    /// The authority who can manage this collection and pays for these accounts
+    /// CHECK: The user's associated token account to receive replacement tokens,
+    /// created by the instruction if needed
+    #[account(
+        mut,
+        address = get_associated_token_address_with_program_id(
+            &user.key(),
+            &replacement_mint.key(),
+            &token_program.key()
+        )
+    )]
+    pub user_replacement_token_account: UncheckedAccount<'info>,
+
+    /// The user depositing tokens, potentially paying for the user_state account
     #[account(mut)]
-    pub authority: Signer<'info>,
+    pub user: Signer<'info>,
 
+    pub token_program: Interface<'info, TokenInterface>,
     pub system_program: Program<'info, System>,
-    pub token_program: Program<'info, Token>,
     pub associated_token_program: Program<'info, AssociatedToken>,
     pub rent: Sysvar<'info, Rent>,
 }
 
+#[event_cpi]
 #[derive(Accounts)]
-pub struct DecreaseCollectionMaxTokens<'info> {
-    /// The collection whose max tokens will be decreased
+pub struct UserRefundFromCollection<'info> {
+    /// The collection to get committed tokens back from
+    #[account(mut)]
+    pub collection: Account<'info, Collection>,
+
+    /// The user's state for the collection, tracking their deposits
     #[account(
         mut,
-        constraint = collection.authority == authority.key()
+        seeds = [
+            b"user_state",
+            collection.key().as_ref(),
+            user.key().as_ref()
+        ],
+        bump
     )]
-    pub collection: Account<'info, Collection>,
+    pub user_state: Account<'info, CollectionUserState>,
 
-    /// The authority of the collection
-    pub authority: Signer<'info>,
-}
+    /// The mint of the collected tokens
+    #[account(
+        address = collection.mint
+    )]
+    pub mint: InterfaceAccount<'info, Mint>,
 
-#[derive(Accounts)]
-pub struct WithdrawFromCollection<'info> {
-    /// The collection to withdraw from
+    /// The token account to receive the refunded tokens
     #[account(
-        has_one = authority
+        mut,
+        token::mint = collection.mint
     )]
-    pub collection: Account<'info, Collection>,
+    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
 
-    /// The collection's vault, holding the tokens to withdraw
+    /// The collection's vault, holding the committed tokens
     #[account(
         mut,
         address = collection.vault
     )]
-    pub vault: Account<'info, TokenAccount>,
+    pub vault: InterfaceAccount<'info, TokenAccount>,
 
-    /// The token account to receive the withdrawn tokens
+    /// The replacement mint owned by the collection
     #[account(
         mut,
-        token::mint = vault.mint
+        address = collection.replacement_mint
     )]
-    pub authority_token_account: Account<'info, TokenAccount>,
+    pub replacement_mint: InterfaceAccount<'info, Mint>,
 
-    /// The authority of the collection
-    pub authority: Signer<'info>,
+    /// The user's token account holding the replacement tokens to burn
+    #[account(
+        mut,
+        token::mint = replacement_mint
+    )]
+    pub user_replacement_token_account: InterfaceAccount<'info, TokenAccount>,
 
-    pub token_program: Program<'info, Token>,
+    /// The user getting their committed tokens back
+    pub user: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
 }
 
+#[event_cpi]
 #[derive(Accounts)]
-pub struct InitDistribution<'info> {
-    /// The created distribution PDA that manages token distribution
+pub struct RedeemReplacementTokens<'info> {
+    /// The collection whose replacement tokens are redeemed
+    pub collection: Account<'info, Collection>,
+
+    /// PDA tracking this user's claim position in this collection
     #[account(
-        init,
-        payer = authority,
-        space = 8 + size_of::<Distribution>(),
+        init_if_needed,
+        payer = user,
+        space = 8 + size_of::<CollectionUserState>(),
         seeds = [
-            b"distribution",
+            b"user_state",
             collection.key().as_ref(),
-            mint.key().as_ref()
+            user.key().as_ref()
         ],
         bump
     )]
-    pub distribution: Account<'info, Distribution>,
+    pub user_state: Account<'info, CollectionUserState>,
 
-    /// The collection this distribution is associated with
+    /// The replacement mint owned by the collection
     #[account(
-        has_one = authority
+        mut,
+        address = collection.replacement_mint
     )]
-    pub collection: Account<'info, Collection>,
+    pub replacement_mint: InterfaceAccount<'info, Mint>,
 
-    /// The SPL token mint for tokens being distributed. Can be the same as or
-    /// different from the collection's mint.
-    pub mint: Account<'info, Mint>,
-
-    /// Associated token account owned by the distribution PDA that holds tokens to distribute
+    /// The token account holding the replacement tokens to redeem
     #[account(
-        init,
-        payer = authority,
-        associated_token::mint = mint,
-        associated_token::authority = distribution
+        mut,
+        token::mint = replacement_mint
     )]
-    pub vault: Account<'info, TokenAccount>,
+    pub user_replacement_token_account: InterfaceAccount<'info, TokenAccount>,
 
-    /// The collection's authority and payer for the distribution accounts
+    /// The holder redeeming replacement tokens, potentially paying for the user_state account
     #[account(mut)]
-    pub authority: Signer<'info>,
+    pub user: Signer<'info>,
 
+    pub token_program: Interface<'info, TokenInterface>,
     pub system_program: Program<'info, System>,
-    pub token_program: Program<'info, Token>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
 }
 
-/// Add tokens to a distribution.
-///
-/// Note that _anyone_ can add tokens. This is because often the authority for
-/// tokens to be distributed may not be the same as the collection authority.
+#[event_cpi]
 #[derive(Accounts)]
-pub struct AddDistributionTokens<'info> {
-    /// The distribution to add tokens to
-    #[account(mut)]
+pub struct UserClaimFromDistribution<'info> {
+    /// The collection associated with this distribution
+    pub collection: Account<'info, Collection>,
+
+    /// The distribution to claim tokens from
+    #[account(
+        mut,
+        has_one = collection
+    )]
     pub distribution: Account<'info, Distribution>,
 
-    /// The distribution's vault to receive the tokens
+    /// The user's state for the collection, tracking their deposits
     #[account(
         mut,
-        constraint = vault.key() == distribution.vault
+        seeds = [
+            b"user_state",
+            collection.key().as_ref(),
+            user.key().as_ref()
+        ],
+        bump
     )]
-    pub vault: Account<'info, TokenAccount>,
+    pub collection_user_state: Account<'info, CollectionUserState>,
 
-    /// The token account providing the tokens to distribute
+    /// PDA tracking how many tokens this user has claimed from this distribution
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + size_of::<DistributionUserState>(),
+        seeds = [
+            b"distribution_user_state",
+            distribution.key().as_ref(),
+            user.key().as_ref()
+        ],
+        bump
+    )]
+    pub distribution_user_state: Account<'info, DistributionUserState>,
+
+    /// The vault holding the tokens to be distributed
     #[account(
         mut,
-        constraint = authority_token_account.mint == vault.mint
+        address = distribution.vault
     )]
-    pub authority_token_account: Account<'info, TokenAccount>,
+    pub distribution_vault: InterfaceAccount<'info, TokenAccount>,
 
-    /// The signer who owns the token account providing the tokens
+    /// The SPL token mint for tokens being distributed
+    #[account(
+        address = distribution.mint
+    )]
+    pub distribution_mint: InterfaceAccount<'info, Mint>,
+
+    /// The user's associated token account to receive the claimed tokens
+    #[account(
+        mut,
+        address = get_associated_token_address_with_program_id(
+            &user.key(),
+            &distribution.mint,
+            &token_program.key()
+        )
+    )]
+    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The user claiming tokens from the distribution
     #[account(mut)]
-    pub authority: Signer<'info>,
+    pub user: Signer<'info>,
 
-    pub token_program: Program<'info, Token>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
 }
 
+#[event_cpi]
 #[derive(Accounts)]
-pub struct UserCommitToCollection<'info> {
-    /// The collection to commit tokens to
-    #[account(mut)]
+pub struct ClaimFromDistributionForUser<'info> {
+    /// The collection associated with this distribution
     pub collection: Account<'info, Collection>,
 
-    /// PDA tracking this user's deposits to this collection
+    /// The distribution to claim tokens from
     #[account(
-        init_if_needed,
-        payer = user,
-        space = 8 + size_of::<CollectionUserState>(),
+        mut,
+        has_one = collection
+    )]
+    pub distribution: Account<'info, Distribution>,
+
+    /// The user's state for the collection, tracking their deposits
+    #[account(
+        mut,
         seeds = [
             b"user_state",
             collection.key().as_ref(),
@@ -477,69 +2927,97 @@ pub struct UserCommitToCollection<'info> {
         ],
         bump
     )]
-    pub user_state: Account<'info, CollectionUserState>,
+    pub collection_user_state: Account<'info, CollectionUserState>,
 
-    /// The SPL token mint for tokens being collected
+    /// PDA tracking how many tokens this user has claimed from this distribution
     #[account(
-        mut,
-        address = collection.mint
+        init_if_needed,
+        payer = payer,
+        space = 8 + size_of::<DistributionUserState>(),
+        seeds = [
+            b"distribution_user_state",
+            distribution.key().as_ref(),
+            user.key().as_ref()
+        ],
+        bump
     )]
-    pub mint: Account<'info, Mint>,
+    pub distribution_user_state: Account<'info, DistributionUserState>,
 
-    /// The token account providing the tokens to deposit
+    /// The vault holding the tokens to be distributed
     #[account(
         mut,
-        token::mint = mint
+        address = distribution.vault
     )]
-    pub user_token_account: Account<'info, TokenAccount>,
+    pub distribution_vault: InterfaceAccount<'info, TokenAccount>,
 
-    /// The collection's vault to receive the deposited tokens (if not burning)
+    /// CHECK: The user's associated token account to receive the claimed tokens,
+    /// created by the instruction if needed
     #[account(
         mut,
-        address = collection.vault
+        address = get_associated_token_address_with_program_id(
+            &user.key(),
+            &distribution.mint,
+            &token_program.key()
+        )
     )]
-    pub vault: Account<'info, TokenAccount>,
+    pub user_token_account: UncheckedAccount<'info>,
 
-    /// The replacement mint owned by the collection
+    /// The SPL token mint for tokens being distributed
     #[account(
-        mut,
-        address = collection.replacement_mint
+        address = distribution.mint
     )]
-    pub replacement_mint: Account<'info, Mint>,
+    pub distribution_mint: InterfaceAccount<'info, Mint>,
+
+    /// CHECK: The user whose share is claimed. Only used to derive their state accounts
+    /// and associated token account, so the tokens can't go anywhere else.
+    pub user: UncheckedAccount<'info>,
+
+    /// Anyone triggering the claim, paying for the user's new accounts
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct UserClaimFromDistributions<'info> {
+    /// The collection whose distributions are claimed from
+    pub collection: Account<'info, Collection>,
 
-    /// The user's token account to receive replacement tokens
+    /// The user's state for the collection, tracking their deposits
     #[account(
-        init_if_needed,
-        payer = user,
-        associated_token::mint = replacement_mint,
-        associated_token::authority = user
+        mut,
+        seeds = [
+            b"user_state",
+            collection.key().as_ref(),
+            user.key().as_ref()
+        ],
+        bump
     )]
-    pub user_replacement_token_account: Account<'info, TokenAccount>,
+    pub collection_user_state: Account<'info, CollectionUserState>,
 
-    /// The user depositing tokens, potentially paying for the user_state account
+    /// The user claiming tokens, paying for their new distribution states
     #[account(mut)]
     pub user: Signer<'info>,
 
-    pub token_program: Program<'info, Token>,
+    pub token_program: Interface<'info, TokenInterface>,
     pub system_program: Program<'info, System>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
-    pub rent: Sysvar<'info, Rent>,
 }
 
+#[event_cpi]
 #[derive(Accounts)]
-pub struct UserClaimFromDistribution<'info> {
-    /// The collection associated with this distribution
-    pub collection: Account<'info, Collection>,
+pub struct CloseCollectionUserState<'info> {
+    /// CHECK: The collection the user state belongs to, which may have been closed.
+    /// Only used to derive the user state and to check whether it still exists.
+    pub collection: UncheckedAccount<'info>,
 
-    /// The distribution to claim tokens from
+    /// The user's state for the collection, closed
     #[account(
         mut,
-        has_one = collection
-    )]
-    pub distribution: Account<'info, Distribution>,
-
-    /// The user's state for the collection, tracking their deposits
-    #[account(
+        close = user,
         seeds = [
             b"user_state",
             collection.key().as_ref(),
@@ -547,13 +3025,24 @@ pub struct UserClaimFromDistribution<'info> {
         ],
         bump
     )]
-    pub collection_user_state: Account<'info, CollectionUserState>,
+    pub user_state: Account<'info, CollectionUserState>,
 
-    /// PDA tracking how many tokens this user has claimed from this distribution
+    /// The user the state belongs to, receiving its rent
+    #[account(mut)]
+    pub user: Signer<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct CloseDistributionUserState<'info> {
+    /// CHECK: The distribution the user state belongs to, which may have been closed.
+    /// Deserialized by the instruction if it still exists.
+    pub distribution: UncheckedAccount<'info>,
+
+    /// The user's state for the distribution, closed
     #[account(
-        init_if_needed,
-        payer = user,
-        space = 8 + size_of::<DistributionUserState>(),
+        mut,
+        close = user,
         seeds = [
             b"distribution_user_state",
             distribution.key().as_ref(),
@@ -563,33 +3052,16 @@ pub struct UserClaimFromDistribution<'info> {
     )]
     pub distribution_user_state: Account<'info, DistributionUserState>,
 
-    /// The vault holding the tokens to be distributed
-    #[account(
-        mut,
-        address = distribution.vault
-    )]
-    pub distribution_vault: Account<'info, TokenAccount>,
-
-    /// The user's associated token account to receive the claimed tokens
-    #[account(
-        mut,
-        associated_token::mint = distribution.mint,
-        associated_token::authority = user
-    )]
-    pub user_token_account: Account<'info, TokenAccount>,
-
-    /// The user claiming tokens from the distribution
+    /// The user the state belongs to, receiving its rent
     #[account(mut)]
     pub user: Signer<'info>,
-
-    pub token_program: Program<'info, Token>,
-    pub system_program: Program<'info, System>,
 }
 
 /// Tracks configuration and state for token collection.
 /// Holds deposited tokens and manages distribution eligibility.
 #[account]
 pub struct Collection {
+    /// current authority, may differ from the creator after an authority transfer
     pub authority: Pubkey,
     /// sum of tokens ever collected (including previously withdrawn!)
     pub lifetime_tokens_collected: u64,
@@ -602,13 +3074,109 @@ pub struct Collection {
     pub counter: u64,
     /// whether to burn input tokens instead of collecting them
     pub burn_tokens: bool,
+    /// whether commits are frozen and shares use lifetime_tokens_collected
+    pub finalized: bool,
+    /// whether commits are frozen while shares keep using max_collectable_tokens
+    pub commits_closed: bool,
+    /// whether distribution entitlement follows the replacement tokens until redeemed
+    pub transferable_claims: bool,
+    /// unix timestamp until which users can get their committed tokens back, if any
+    pub refund_deadline: Option<i64>,
+    /// authority that created the collection, used in the collection PDA seeds
+    pub creator: Pubkey,
+    /// authority proposed by propose_authority, waiting for accept_authority
+    pub pending_authority: Option<Pubkey>,
+    /// key that may pause and unpause besides the authority, if any
+    pub guardian: Option<Pubkey>,
+    /// whether user actions on this collection and its distributions are stopped
+    pub paused: bool,
+    /// number of distributions of this collection that have not been closed
+    pub open_distributions: u64,
+    /// number of distributions ever created, and the id of the next one
+    pub distribution_count: u64,
+    /// merkle root of the (user, max_amount) pairs allowed to commit, if any
+    pub allowlist_root: Option<[u8; 32]>,
+    /// maximum deposited_amount a user can reach through commits, if any
+    pub max_per_user: Option<u64>,
+    /// minimum amount of a single commit
+    pub min_commit: u64,
+    /// unix timestamp from which commits are accepted, if any
+    pub commit_start: Option<i64>,
+    /// unix timestamp from which commits are rejected, if any
+    pub commit_end: Option<i64>,
+    /// number of added mints accepted besides `mint` that have not been closed
+    pub open_collection_mints: u64,
+    /// early-bird multiplier applied to commits, if any
+    pub commit_bonus: Option<CommitBonus>,
+}
+
+impl Collection {
+    /// Denominator for computing a user's share of a distribution
+    pub fn share_denominator(&self) -> u64 {
+        if self.finalized {
+            self.lifetime_tokens_collected
+        } else {
+            self.max_collectable_tokens
+        }
+    }
+
+    /// Whether users can get their committed tokens back at the given unix timestamp
+    pub fn refunds_open(&self, now: i64) -> bool {
+        matches!(self.refund_deadline, Some(refund_deadline) if now < refund_deadline)
+    }
+
+    /// Whether the commit window has ended at the given unix timestamp
+    pub fn commit_window_ended(&self, now: i64) -> bool {
+        matches!(self.commit_end, Some(commit_end) if now >= commit_end)
+    }
+
+    /// Whether the key may pause and unpause this collection and its distributions
+    pub fn is_authority_or_guardian(&self, key: &Pubkey) -> bool {
+        self.authority == *key || self.guardian == Some(*key)
+    }
+}
+
+/// A mint accepted by a collection besides its own, credited at a fixed weight.
+#[account]
+pub struct CollectionMint {
+    pub collection: Pubkey,
+    pub mint: Pubkey,
+    pub vault: Pubkey,
+    /// weighted units credited per weight_denominator tokens
+    pub weight_numerator: u64,
+    pub weight_denominator: u64,
+    /// sum of tokens of this mint ever collected, before weighting
+    pub lifetime_tokens_collected: u64,
+    pub bump: u8,
+}
+
+impl CollectionMint {
+    /// Weighted units credited for an amount of the given added mint, rounded down,
+    /// or the amount itself for the collection's own mint
+    pub fn weigh(collection_mint: Option<&Self>, amount: u64) -> Result<u64> {
+        match collection_mint {
+            None => Ok(amount),
+            Some(collection_mint) => {
+                let weighted = (amount as u128)
+                    .checked_mul(collection_mint.weight_numerator as u128)
+                    .ok_or(ErrorCode::Overflow)?
+                    // Integer division rounds down, never crediting more than the weight
+                    .checked_div(collection_mint.weight_denominator as u128)
+                    .ok_or(ErrorCode::Overflow)?;
+                u64::try_from(weighted).map_err(|_| error!(ErrorCode::Overflow))
+            }
+        }
+    }
 }
 
-/// Tracks an individual user's deposits into a collection.
+/// Tracks an individual user's deposits into a collection, or their redeemed
+/// replacement tokens if claims are transferable.
 /// Used to calculate their share of distributions.
 #[account]
 pub struct CollectionUserState {
     pub deposited_amount: u64,
+    /// whether any distribution has paid out to this user, which rules out refunds
+    pub received_distribution: bool,
 }
 
 /// Manages token distribution to collection participants.
@@ -623,6 +3191,146 @@ pub struct Distribution {
     /// amount of tokens handed out to users
     pub distributed_tokens: u64,
     pub bump: u8,
+    /// amount of unclaimable tokens returned to the collection authority
+    pub clawed_back_tokens: u64,
+    /// unix timestamp after which claims are rejected, if any
+    pub expires_at: Option<i64>,
+    /// whether the vault was swept and closed after expiry
+    pub swept: bool,
+    /// amount of tokens returned to the collection authority by the sweep
+    pub swept_tokens: u64,
+    /// schedule over which lifetime_deposited_tokens unlock, if any
+    pub vesting: Option<VestingSchedule>,
+    /// whether claims from this distribution are stopped
+    pub paused: bool,
+    /// position among the collection's distributions, used in the distribution PDA seeds
+    pub id: u64,
+}
+
+impl Distribution {
+    /// Whether claims are no longer possible at the given unix timestamp
+    pub fn is_expired(&self, now: i64) -> bool {
+        matches!(self.expires_at, Some(expires_at) if now >= expires_at)
+    }
+
+    /// Part of lifetime_deposited_tokens that is unlocked at the given unix timestamp
+    pub fn vested_tokens(&self, now: i64) -> Result<u64> {
+        let Some(vesting) = &self.vesting else {
+            return Ok(self.lifetime_deposited_tokens);
+        };
+        if now < vesting.cliff_ts {
+            return Ok(0);
+        }
+        if now >= vesting.end_ts {
+            return Ok(self.lifetime_deposited_tokens);
+        }
+
+        // Linear unlock from start_ts, rounding down
+        let elapsed = now.checked_sub(vesting.start_ts).ok_or(ErrorCode::Overflow)? as u128;
+        let duration = vesting
+            .end_ts
+            .checked_sub(vesting.start_ts)
+            .ok_or(ErrorCode::Overflow)? as u128;
+        let vested = (self.lifetime_deposited_tokens as u128)
+            .checked_mul(elapsed)
+            .ok_or(ErrorCode::Overflow)?
+            .checked_div(duration)
+            .ok_or(ErrorCode::Overflow)? as u64;
+        Ok(vested)
+    }
+}
+
+/// Linear vesting schedule for a distribution's tokens.
+/// Nothing is claimable before the cliff; after it, the unlocked part grows linearly
+/// from start_ts to end_ts, so tokens vested between start and cliff unlock at once.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VestingSchedule {
+    pub start_ts: i64,
+    pub cliff_ts: i64,
+    pub end_ts: i64,
+}
+
+/// Early-bird bonus of a collection's commits.
+/// Commits up to start_ts are credited start_multiplier_bps / 10_000 times their
+/// amount; the multiplier then decays linearly to 1.0 at end_ts.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CommitBonus {
+    pub start_ts: i64,
+    pub end_ts: i64,
+    /// multiplier at start_ts in basis points, at least BONUS_MULTIPLIER_ONE_BPS
+    pub start_multiplier_bps: u64,
+}
+
+impl CommitBonus {
+    /// Multiplier in basis points at the given unix timestamp
+    pub fn multiplier_bps(&self, now: i64) -> Result<u64> {
+        if now <= self.start_ts {
+            return Ok(self.start_multiplier_bps);
+        }
+        if now >= self.end_ts {
+            return Ok(BONUS_MULTIPLIER_ONE_BPS);
+        }
+
+        // Linear decay of the bonus part, rounding down
+        let remaining = self.end_ts.checked_sub(now).ok_or(ErrorCode::Overflow)? as u128;
+        let duration = self
+            .end_ts
+            .checked_sub(self.start_ts)
+            .ok_or(ErrorCode::Overflow)? as u128;
+        let bonus = (self
+            .start_multiplier_bps
+            .checked_sub(BONUS_MULTIPLIER_ONE_BPS)
+            .ok_or(ErrorCode::Overflow)? as u128)
+            .checked_mul(remaining)
+            .ok_or(ErrorCode::Overflow)?
+            .checked_div(duration)
+            .ok_or(ErrorCode::Overflow)? as u64;
+        Ok(BONUS_MULTIPLIER_ONE_BPS + bonus)
+    }
+
+    /// Units credited for committing `amount` at the given unix timestamp, rounded down
+    pub fn apply(&self, amount: u64, now: i64) -> Result<u64> {
+        let credited = (amount as u128)
+            .checked_mul(self.multiplier_bps(now)? as u128)
+            .ok_or(ErrorCode::Overflow)?
+            .checked_div(BONUS_MULTIPLIER_ONE_BPS as u128)
+            .ok_or(ErrorCode::Overflow)?;
+        u64::try_from(credited).map_err(|_| error!(ErrorCode::Overflow))
+    }
+}
+
+/// A user's allowlist entry and the merkle proof of its inclusion in a collection's
+/// allowlist.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct AllowlistProof {
+    /// maximum deposited_amount the user may reach through commits
+    pub max_amount: u64,
+    /// sibling hashes from the user's leaf up to the root
+    pub proof: Vec<[u8; 32]>,
+}
+
+impl AllowlistProof {
+    /// Leaf of a user's allowlist entry
+    pub fn leaf(user: &Pubkey, max_amount: u64) -> [u8; 32] {
+        keccak::hashv(&[user.as_ref(), &max_amount.to_le_bytes()]).to_bytes()
+    }
+
+    /// Parent of two nodes, which are hashed in sorted order so that proofs don't
+    /// need to record the side of each sibling
+    pub fn parent(a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
+        let (left, right) = if a <= b { (a, b) } else { (b, a) };
+        keccak::hashv(&[left, right]).to_bytes()
+    }
+
+    /// Whether the proof leads from the user's entry to `root`
+    pub fn verify(&self, root: &[u8; 32], user: &Pubkey) -> bool {
+        let leaf = Self::leaf(user, self.max_amount);
+        let computed = self
+            .proof
+            .iter()
+            .fold(leaf, |node, sibling| Self::parent(&node, sibling));
+        computed == *root
+    }
 }
 
 /// Tracks how many tokens a user has received from a specific distribution.
@@ -632,6 +3340,257 @@ pub struct DistributionUserState {
     pub received_amount: u64,
 }
 
+#[event]
+pub struct CollectionInitialized {
+    pub collection: Pubkey,
+    pub authority: Pubkey,
+    pub mint: Pubkey,
+    pub replacement_mint: Pubkey,
+    pub counter: u64,
+    pub max_collectable_tokens: u64,
+    pub burn_tokens: bool,
+    pub transferable_claims: bool,
+    pub refund_deadline: Option<i64>,
+    pub allowlist_root: Option<[u8; 32]>,
+    pub max_per_user: Option<u64>,
+    pub min_commit: u64,
+    pub commit_start: Option<i64>,
+    pub commit_end: Option<i64>,
+    pub commit_bonus: Option<CommitBonus>,
+}
+
+#[event]
+pub struct AuthorityProposed {
+    pub collection: Pubkey,
+    pub authority: Pubkey,
+    pub pending_authority: Pubkey,
+}
+
+#[event]
+pub struct AuthorityAccepted {
+    pub collection: Pubkey,
+    pub previous_authority: Pubkey,
+    pub authority: Pubkey,
+}
+
+#[event]
+pub struct GuardianSet {
+    pub collection: Pubkey,
+    pub guardian: Option<Pubkey>,
+}
+
+#[event]
+pub struct AllowlistSet {
+    pub collection: Pubkey,
+    pub allowlist_root: Option<[u8; 32]>,
+}
+
+#[event]
+pub struct ReplacementMintMetadataSet {
+    pub collection: Pubkey,
+    pub replacement_mint: Pubkey,
+    pub name: String,
+    pub symbol: String,
+    pub uri: String,
+}
+
+#[event]
+pub struct CollectionPauseSet {
+    pub collection: Pubkey,
+    pub signer: Pubkey,
+    pub paused: bool,
+}
+
+#[event]
+pub struct DistributionPauseSet {
+    pub distribution: Pubkey,
+    pub signer: Pubkey,
+    pub paused: bool,
+}
+
+#[event]
+pub struct MaxDecreased {
+    pub collection: Pubkey,
+    pub previous_max_collectable_tokens: u64,
+    pub max_collectable_tokens: u64,
+    pub lifetime_tokens_collected: u64,
+}
+
+#[event]
+pub struct MaxPerUserDecreased {
+    pub collection: Pubkey,
+    pub previous_max_per_user: Option<u64>,
+    pub max_per_user: u64,
+}
+
+#[event]
+pub struct MinCommitDecreased {
+    pub collection: Pubkey,
+    pub previous_min_commit: u64,
+    pub min_commit: u64,
+}
+
+#[event]
+pub struct CommitEndSet {
+    pub collection: Pubkey,
+    pub previous_commit_end: Option<i64>,
+    pub commit_end: Option<i64>,
+}
+
+#[event]
+pub struct CollectionMintAdded {
+    pub collection: Pubkey,
+    pub collection_mint: Pubkey,
+    pub mint: Pubkey,
+    pub vault: Pubkey,
+    pub weight_numerator: u64,
+    pub weight_denominator: u64,
+}
+
+#[event]
+pub struct CollectionMintClosed {
+    pub collection: Pubkey,
+    pub collection_mint: Pubkey,
+    pub mint: Pubkey,
+    /// tokens of the mint ever collected, before weighting
+    pub lifetime_tokens_collected: u64,
+}
+
+#[event]
+pub struct CollectionFinalized {
+    pub collection: Pubkey,
+    pub lifetime_tokens_collected: u64,
+    pub max_collectable_tokens: u64,
+}
+
+#[event]
+pub struct CollectionCommitsClosed {
+    pub collection: Pubkey,
+    pub lifetime_tokens_collected: u64,
+    pub max_collectable_tokens: u64,
+}
+
+#[event]
+pub struct VaultWithdrawn {
+    pub collection: Pubkey,
+    pub vault: Pubkey,
+    pub destination: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct CollectionClosed {
+    pub collection: Pubkey,
+    pub authority: Pubkey,
+    pub lifetime_tokens_collected: u64,
+}
+
+#[event]
+pub struct DistributionInitialized {
+    pub distribution: Pubkey,
+    pub collection: Pubkey,
+    pub mint: Pubkey,
+    pub id: u64,
+    pub expires_at: Option<i64>,
+    pub vesting: Option<VestingSchedule>,
+}
+
+#[event]
+pub struct DistributionFunded {
+    pub distribution: Pubkey,
+    pub funder: Pubkey,
+    pub amount: u64,
+    /// lifetime_deposited_tokens after funding
+    pub lifetime_deposited_tokens: u64,
+}
+
+#[event]
+pub struct RemainderClawedBack {
+    pub distribution: Pubkey,
+    pub destination: Pubkey,
+    pub amount: u64,
+    /// clawed_back_tokens after the clawback
+    pub clawed_back_tokens: u64,
+}
+
+#[event]
+pub struct DistributionSwept {
+    pub distribution: Pubkey,
+    pub destination: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct DistributionClosed {
+    pub distribution: Pubkey,
+    pub collection: Pubkey,
+    pub distributed_tokens: u64,
+}
+
+#[event]
+pub struct TokensCommitted {
+    pub collection: Pubkey,
+    pub user: Pubkey,
+    /// the committed mint, the collection's own or an added one
+    pub mint: Pubkey,
+    /// tokens received, after transfer fees
+    pub amount: u64,
+    /// weighted units of the received tokens, minted as replacement tokens
+    pub weighted_amount: u64,
+    /// units credited to the deposit, including any commit bonus
+    pub credited_amount: u64,
+    /// user's deposited_amount after the commit
+    pub deposited_amount: u64,
+    /// collection's lifetime_tokens_collected after the commit
+    pub lifetime_tokens_collected: u64,
+}
+
+#[event]
+pub struct TokensRefunded {
+    pub collection: Pubkey,
+    pub user: Pubkey,
+    pub amount: u64,
+    /// user's deposited_amount after the refund
+    pub deposited_amount: u64,
+    /// collection's lifetime_tokens_collected after the refund
+    pub lifetime_tokens_collected: u64,
+}
+
+#[event]
+pub struct ReplacementTokensRedeemed {
+    pub collection: Pubkey,
+    pub user: Pubkey,
+    pub amount: u64,
+    /// user's deposited_amount after the redemption
+    pub deposited_amount: u64,
+}
+
+#[event]
+pub struct TokensClaimed {
+    pub collection: Pubkey,
+    pub distribution: Pubkey,
+    pub user: Pubkey,
+    pub amount: u64,
+    /// user's received_amount after the claim
+    pub received_amount: u64,
+    /// distribution's distributed_tokens after the claim
+    pub distributed_tokens: u64,
+}
+
+#[event]
+pub struct CollectionUserStateClosed {
+    pub collection: Pubkey,
+    pub user: Pubkey,
+}
+
+#[event]
+pub struct DistributionUserStateClosed {
+    pub distribution: Pubkey,
+    pub user: Pubkey,
+    /// user's received_amount when the state was closed
+    pub received_amount: u64,
+}
+
 #[error_code]
 pub enum ErrorCode {
     #[msg("Arithmetic overflow in calculation")]
@@ -646,6 +3605,123 @@ pub enum ErrorCode {
     #[msg("Maximum collectable tokens must be greater than zero")]
     InvalidMaxCollectableTokens,
 
-    #[msg("New maximum tokens must be less than current maximum")]
+    #[msg("New value must be less than the current one")]
     InvalidDecrease,
+
+    #[msg("Collection is finalized")]
+    CollectionFinalized,
+
+    #[msg("Collection has not collected any tokens")]
+    NothingCollected,
+
+    #[msg("Collection no longer accepts commits")]
+    CommitsClosed,
+
+    #[msg("Collection must be closed to commits")]
+    CommitsNotClosed,
+
+    #[msg("Expiry must be in the future")]
+    InvalidExpiry,
+
+    #[msg("Distribution has expired")]
+    DistributionExpired,
+
+    #[msg("Distribution has not expired")]
+    DistributionNotExpired,
+
+    #[msg("Vesting schedule must satisfy start <= cliff <= end and start < end")]
+    InvalidVestingSchedule,
+
+    #[msg("Collection does not have transferable claims")]
+    ClaimsNotTransferable,
+
+    #[msg("Refunds require a non-burning collection without transferable claims")]
+    RefundsUnavailable,
+
+    #[msg("Refund deadline must be in the future")]
+    InvalidRefundDeadline,
+
+    #[msg("Refund window is closed")]
+    RefundWindowClosed,
+
+    #[msg("Refund window is still open")]
+    RefundWindowOpen,
+
+    #[msg("User has already received tokens from a distribution")]
+    DistributionAlreadyReceived,
+
+    #[msg("Refund amount exceeds deposited amount")]
+    RefundExceedsDeposit,
+
+    #[msg("Signer is not the pending authority")]
+    NotPendingAuthority,
+
+    #[msg("Signer is neither the authority nor the guardian")]
+    NotAuthorityOrGuardian,
+
+    #[msg("Paused")]
+    Paused,
+
+    #[msg("Remaining accounts must be groups of distribution, mint, vault, user state and token account")]
+    InvalidRemainingAccounts,
+
+    #[msg("User still has deposited tokens")]
+    DepositOutstanding,
+
+    #[msg("Distribution user state can only be closed after expiry or if nothing was received")]
+    DistributionUserStateInUse,
+
+    #[msg("Vault still holds tokens")]
+    VaultNotEmpty,
+
+    #[msg("Distribution still owes tokens to users")]
+    DistributionNotSettled,
+
+    #[msg("Collection still has open distributions")]
+    DistributionsOpen,
+
+    #[msg("Allowlists require a collection without transferable claims")]
+    AllowlistUnavailable,
+
+    #[msg("User is not on the collection's allowlist")]
+    NotAllowlisted,
+
+    #[msg("Deposited amount exceeds the user's allowlisted amount")]
+    AllowlistAmountExceeded,
+
+    #[msg("Per-user caps require a collection without transferable claims")]
+    PerUserCapUnavailable,
+
+    #[msg("Per-user cap must be positive and at least the minimum commit, which may not exceed the maximum")]
+    InvalidCommitLimits,
+
+    #[msg("Commit amount is below the collection's minimum")]
+    CommitBelowMinimum,
+
+    #[msg("Deposited amount exceeds the collection's per-user cap")]
+    MaxPerUserExceeded,
+
+    #[msg("Commit window must end in the future and not before it starts")]
+    InvalidCommitWindow,
+
+    #[msg("Commit window has not started")]
+    CommitWindowNotStarted,
+
+    #[msg("Commit window has ended")]
+    CommitWindowEnded,
+
+    #[msg("Mint weights must be positive")]
+    InvalidWeight,
+
+    #[msg("Added mint must differ from the collection's own and replacement mints and use its token program")]
+    InvalidCollectionMint,
+
+    #[msg("Collection still accepts added mints")]
+    CollectionMintsOpen,
+
+    #[msg("Commit bonuses require a collection without transferable claims or refunds")]
+    CommitBonusUnavailable,
+
+    #[msg("Commit bonus must end after it starts and not multiply by less than 1.0")]
+    InvalidCommitBonus,
 }
@@ -0,0 +1,168 @@
+//! Blocking wrapper around a solana-program-test bank for the integration tests.
+//!
+//! Runs the program and the Metaplex token metadata program as native processors,
+//! next to the SPL programs solana-program-test ships, so instructions go through
+//! the runtime's account rules and CPI checks without building the programs first.
+//! Tests sign with the pubkeys of wallets created here, and the clock only moves
+//! with `warp`.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use anchor_lang::solana_program::account_info::AccountInfo;
+use anchor_lang::solana_program::clock::Clock;
+use anchor_lang::solana_program::entrypoint::ProgramResult;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::message::Message;
+use anchor_lang::solana_program::native_token::LAMPORTS_PER_SOL;
+use anchor_lang::solana_program::program_error::ProgramError;
+use anchor_lang::solana_program::pubkey::Pubkey;
+use anchor_lang::solana_program::system_program;
+use solana_program_test::{processor, BanksClientError, ProgramTest, ProgramTestContext};
+use solana_sdk::account::{Account, AccountSharedData};
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::transaction::{Transaction, TransactionError};
+use tokio::runtime::Runtime;
+
+/// Unix timestamp the clock starts at
+pub const START_TIMESTAMP: i64 = 1_700_000_000;
+
+/// Metaplex token metadata processor, whose signature ties the program id and the
+/// accounts to one lifetime that `processor!` can't name
+fn process_token_metadata(_: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    // SAFETY: the accounts outlive the call, and the processor doesn't keep them
+    let accounts: &'static [AccountInfo<'static>] = unsafe { std::mem::transmute(accounts) };
+    mpl_token_metadata::processor::process_instruction(&mpl_token_metadata::ID, accounts, data)
+}
+
+pub struct Bank {
+    context: RefCell<ProgramTestContext>,
+    runtime: Runtime,
+    /// Keypairs of the wallets, by pubkey
+    wallets: HashMap<Pubkey, Keypair>,
+    slot: u64,
+    unix_timestamp: i64,
+}
+
+impl Bank {
+    /// Starts a bank with the program and the Metaplex token metadata program
+    pub fn new() -> Self {
+        let mut program_test = ProgramTest::default();
+        program_test.prefer_bpf(false);
+        program_test.add_program(
+            "multidistribute",
+            multidistribute::ID,
+            processor!(multidistribute::entry),
+        );
+        program_test.add_program(
+            "mpl_token_metadata",
+            mpl_token_metadata::ID,
+            processor!(process_token_metadata),
+        );
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let mut context = runtime.block_on(program_test.start_with_context());
+        let clock: Clock = runtime.block_on(context.banks_client.get_sysvar()).unwrap();
+        Self {
+            context: RefCell::new(context),
+            runtime,
+            wallets: HashMap::new(),
+            slot: clock.slot,
+            unix_timestamp: START_TIMESTAMP,
+        }
+    }
+
+    pub fn account(&self, key: &Pubkey) -> Option<Account> {
+        let mut context = self.context.borrow_mut();
+        self.runtime
+            .block_on(context.banks_client.get_account(*key))
+            .unwrap()
+    }
+
+    pub fn set_account(&mut self, key: Pubkey, account: Account) {
+        self.context
+            .get_mut()
+            .set_account(&key, &AccountSharedData::from(account));
+    }
+
+    pub fn lamports(&self, key: &Pubkey) -> u64 {
+        self.account(key).map_or(0, |account| account.lamports)
+    }
+
+    /// Creates a funded wallet that can sign transactions
+    pub fn new_wallet(&mut self) -> Pubkey {
+        let keypair = Keypair::new();
+        let wallet = keypair.pubkey();
+        self.wallets.insert(wallet, keypair);
+        self.set_account(
+            wallet,
+            Account {
+                lamports: 100 * LAMPORTS_PER_SOL,
+                owner: system_program::ID,
+                ..Account::default()
+            },
+        );
+        wallet
+    }
+
+    pub fn now(&self) -> i64 {
+        self.unix_timestamp
+    }
+
+    /// Moves the clock forward by `seconds`
+    pub fn warp(&mut self, seconds: i64) {
+        self.unix_timestamp += seconds;
+    }
+
+    /// Executes the instructions as one transaction signed by `signers`, wallets
+    /// created with `new_wallet`. The bank's payer pays the fees.
+    pub fn process(&mut self, instructions: &[Instruction], signers: &[Pubkey]) -> ProgramResult {
+        let context = self.context.get_mut();
+        let message = Message::new(instructions, Some(&context.payer.pubkey()));
+        let signer_keys =
+            message.account_keys[1..message.header.num_required_signatures as usize].to_vec();
+        if signer_keys.iter().any(|key| !signers.contains(key)) {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        // Every transaction gets its own slot, so repeated transactions have a new
+        // blockhash, and the clock is set back to the warped time
+        self.slot += 1;
+        context.warp_to_slot(self.slot).unwrap();
+        let mut clock: Clock = self
+            .runtime
+            .block_on(context.banks_client.get_sysvar())
+            .unwrap();
+        clock.unix_timestamp = self.unix_timestamp;
+        context.set_sysvar(&clock);
+        let blockhash = self
+            .runtime
+            .block_on(context.banks_client.get_latest_blockhash())
+            .unwrap();
+
+        let mut keypairs = vec![&context.payer];
+        for key in &signer_keys {
+            let keypair = self
+                .wallets
+                .get(key)
+                .unwrap_or_else(|| panic!("{key} isn't a wallet"));
+            keypairs.push(keypair);
+        }
+        let transaction = Transaction::new(&keypairs, message, blockhash);
+        match self
+            .runtime
+            .block_on(context.banks_client.process_transaction(transaction))
+        {
+            Ok(()) => Ok(()),
+            Err(BanksClientError::TransactionError(TransactionError::InstructionError(
+                _,
+                error,
+            ))) => Err(ProgramError::try_from(error)
+                .unwrap_or_else(|error| panic!("unexpected instruction error: {error}"))),
+            Err(error) => panic!("transaction failed: {error}"),
+        }
+    }
+}
@@ -0,0 +1,347 @@
+//! Shared fixtures of the integration tests.
+
+#![allow(dead_code)]
+
+pub mod bank;
+
+use anchor_lang::solana_program::entrypoint::ProgramResult;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::program_error::ProgramError;
+use anchor_lang::solana_program::program_pack::Pack;
+use anchor_lang::solana_program::pubkey::Pubkey;
+use anchor_lang::solana_program::rent::Rent;
+use anchor_lang::AccountDeserialize;
+use multidistribute::{AllowlistProof, Collection, VestingSchedule};
+use multidistribute_client::{instructions, pda};
+use solana_sdk::account::Account;
+use spl_token_2022::extension::{BaseStateWithExtensions, ExtensionType, StateWithExtensions};
+
+pub use bank::Bank;
+
+pub const DECIMALS: u8 = 6;
+
+/// Asserts that `result` failed with `error`, a program or Anchor error code
+pub fn assert_error(result: ProgramResult, error: impl Into<u32>) {
+    assert_eq!(result, Err(ProgramError::Custom(error.into())));
+}
+
+/// A bank with an authority holding a collection mint and a distribution mint
+pub struct Env {
+    pub bank: Bank,
+    pub token_program: Pubkey,
+    pub authority: Pubkey,
+    pub mint: Pubkey,
+    pub reward_mint: Pubkey,
+}
+
+impl Env {
+    pub fn new() -> Self {
+        Self::with_token_program(spl_token::ID)
+    }
+
+    pub fn with_token_program(token_program: Pubkey) -> Self {
+        let mut bank = Bank::new();
+        let authority = bank.new_wallet();
+        let mint = create_mint(&mut bank, &token_program, &authority);
+        let reward_mint = create_mint(&mut bank, &token_program, &authority);
+        Self {
+            bank,
+            token_program,
+            authority,
+            mint,
+            reward_mint,
+        }
+    }
+
+    pub fn run(&mut self, instruction: Instruction, signer: &Pubkey) -> ProgramResult {
+        self.bank.process(&[instruction], &[*signer])
+    }
+
+    pub fn fetch<T: AccountDeserialize>(&self, key: &Pubkey) -> T {
+        let account = self.bank.account(key).expect("account exists");
+        T::try_deserialize(&mut account.data.as_slice()).unwrap()
+    }
+
+    pub fn balance(&self, token_account: &Pubkey) -> u64 {
+        token_balance(&self.bank, token_account)
+    }
+
+    pub fn ata(&self, owner: &Pubkey, mint: &Pubkey) -> Pubkey {
+        pda::vault(owner, mint, &self.token_program)
+    }
+
+    /// Creates a collection of `mint` with the given maximum and no options set
+    pub fn init_collection(&mut self, counter: u64, max_collectable_tokens: u64) -> Pubkey {
+        self.init_collection_with(multidistribute::instruction::InitCollection {
+            counter,
+            max_collectable_tokens,
+            burn_tokens: false,
+            transferable_claims: false,
+            refund_deadline: None,
+            allowlist_root: None,
+            max_per_user: None,
+            min_commit: 0,
+            commit_start: None,
+            commit_end: None,
+            commit_bonus: None,
+        })
+        .unwrap()
+    }
+
+    pub fn init_collection_with(
+        &mut self,
+        args: multidistribute::instruction::InitCollection,
+    ) -> Result<Pubkey, ProgramError> {
+        let collection = pda::find_collection(&self.authority, &self.mint, args.counter).0;
+        let instruction =
+            instructions::init_collection(&self.authority, &self.mint, &self.token_program, args);
+        self.run(instruction, &self.authority.clone())?;
+        Ok(collection)
+    }
+
+    /// Creates a distribution of `reward_mint` without expiry or vesting
+    pub fn init_distribution(&mut self, collection: &Pubkey) -> Pubkey {
+        self.init_distribution_with(collection, None, None).unwrap()
+    }
+
+    pub fn init_distribution_with(
+        &mut self,
+        collection: &Pubkey,
+        expires_at: Option<i64>,
+        vesting: Option<VestingSchedule>,
+    ) -> Result<Pubkey, ProgramError> {
+        let id = self.fetch::<Collection>(collection).distribution_count;
+        let instruction = instructions::init_distribution(
+            collection,
+            id,
+            &self.authority,
+            &self.reward_mint,
+            &self.token_program,
+            expires_at,
+            vesting,
+        );
+        self.run(instruction, &self.authority.clone())?;
+        Ok(pda::find_distribution(collection, id).0)
+    }
+
+    /// Creates a mint and adds it to `collection` at the given weight
+    pub fn add_collection_mint(
+        &mut self,
+        collection: &Pubkey,
+        weight_numerator: u64,
+        weight_denominator: u64,
+    ) -> Result<Pubkey, ProgramError> {
+        let mint = create_mint(&mut self.bank, &self.token_program, &self.authority);
+        let instruction = instructions::add_collection_mint(
+            collection,
+            &mint,
+            &self.authority,
+            &self.token_program,
+            weight_numerator,
+            weight_denominator,
+        );
+        self.run(instruction, &self.authority.clone())?;
+        Ok(mint)
+    }
+
+    /// Commits `amount` tokens of `mint`, an added mint of `collection`
+    pub fn commit_added_mint(
+        &mut self,
+        collection: &Pubkey,
+        mint: &Pubkey,
+        user: &Pubkey,
+        amount: u64,
+    ) -> ProgramResult {
+        let instruction = instructions::user_commit_added_mint_to_collection(
+            collection,
+            mint,
+            user,
+            &self.ata(user, mint),
+            &self.token_program,
+            amount,
+            None,
+        );
+        self.run(instruction, user)
+    }
+
+    /// Creates a funded user holding `amount` tokens of `mint`
+    pub fn new_user(&mut self, amount: u64) -> Pubkey {
+        let user = self.bank.new_wallet();
+        let mint = self.mint;
+        self.mint_to(&mint, &user, amount);
+        user
+    }
+
+    /// Mints `amount` tokens to the associated token account of `owner`, creating it
+    pub fn mint_to(&mut self, mint: &Pubkey, owner: &Pubkey, amount: u64) {
+        let token_account = self.ata(owner, mint);
+        let instructions = [
+            spl_associated_token_account::instruction::create_associated_token_account_idempotent(
+                &self.authority,
+                owner,
+                mint,
+                &self.token_program,
+            ),
+            spl_token_2022::instruction::mint_to(
+                &self.token_program,
+                mint,
+                &token_account,
+                &self.authority,
+                &[],
+                amount,
+            )
+            .unwrap(),
+        ];
+        self.bank.process(&instructions, &[self.authority]).unwrap();
+    }
+
+    pub fn commit(&mut self, collection: &Pubkey, user: &Pubkey, amount: u64) -> ProgramResult {
+        self.commit_with(collection, user, amount, None)
+    }
+
+    pub fn commit_with(
+        &mut self,
+        collection: &Pubkey,
+        user: &Pubkey,
+        amount: u64,
+        allowlist_proof: Option<AllowlistProof>,
+    ) -> ProgramResult {
+        let instruction = instructions::user_commit_to_collection(
+            collection,
+            &self.mint,
+            user,
+            &self.ata(user, &self.mint),
+            &self.token_program,
+            amount,
+            allowlist_proof,
+        );
+        self.run(instruction, user)
+    }
+
+    /// Adds `amount` freshly minted reward tokens to the first distribution of `collection`
+    pub fn fund(&mut self, collection: &Pubkey, amount: u64) -> ProgramResult {
+        let (authority, reward_mint) = (self.authority, self.reward_mint);
+        self.mint_to(&reward_mint, &authority, amount);
+        let instruction = instructions::add_distribution_tokens(
+            &pda::find_distribution(collection, 0).0,
+            &reward_mint,
+            &authority,
+            &self.ata(&authority, &reward_mint),
+            &self.token_program,
+            amount,
+        );
+        self.run(instruction, &authority)
+    }
+
+    /// Claims from the first distribution of `collection`, creating the user's token account
+    pub fn claim(&mut self, collection: &Pubkey, user: &Pubkey) -> ProgramResult {
+        let instructions = [
+            spl_associated_token_account::instruction::create_associated_token_account_idempotent(
+                user,
+                user,
+                &self.reward_mint,
+                &self.token_program,
+            ),
+            instructions::user_claim_from_distribution(
+                collection,
+                0,
+                &self.reward_mint,
+                user,
+                &self.token_program,
+            ),
+        ];
+        self.bank.process(&instructions, &[*user])
+    }
+}
+
+/// Creates a mint with `authority` as mint authority
+pub fn create_mint(bank: &mut Bank, token_program: &Pubkey, authority: &Pubkey) -> Pubkey {
+    let mint = allocate_mint(bank, token_program, spl_token::state::Mint::LEN);
+    let instruction = spl_token_2022::instruction::initialize_mint2(
+        token_program,
+        &mint,
+        authority,
+        None,
+        DECIMALS,
+    )
+    .unwrap();
+    bank.process(&[instruction], &[]).unwrap();
+    mint
+}
+
+/// Creates a Token-2022 mint with a transfer fee of `basis_points`
+pub fn create_transfer_fee_mint(bank: &mut Bank, authority: &Pubkey, basis_points: u16) -> Pubkey {
+    let space = ExtensionType::get_account_len::<spl_token_2022::state::Mint>(&[
+        ExtensionType::TransferFeeConfig,
+    ]);
+    let mint = allocate_mint(bank, &spl_token_2022::ID, space);
+    let instructions = [
+        spl_token_2022::extension::transfer_fee::instruction::initialize_transfer_fee_config(
+            &spl_token_2022::ID,
+            &mint,
+            None,
+            None,
+            basis_points,
+            u64::MAX,
+        )
+        .unwrap(),
+        spl_token_2022::instruction::initialize_mint2(
+            &spl_token_2022::ID,
+            &mint,
+            authority,
+            None,
+            DECIMALS,
+        )
+        .unwrap(),
+    ];
+    bank.process(&instructions, &[]).unwrap();
+    mint
+}
+
+fn allocate_mint(bank: &mut Bank, token_program: &Pubkey, space: usize) -> Pubkey {
+    let mint = Pubkey::new_unique();
+    bank.set_account(
+        mint,
+        Account {
+            lamports: Rent::default().minimum_balance(space),
+            data: vec![0; space],
+            owner: *token_program,
+            ..Account::default()
+        },
+    );
+    mint
+}
+
+pub fn token_balance(bank: &Bank, token_account: &Pubkey) -> u64 {
+    bank.account(token_account).map_or(0, |account| {
+        StateWithExtensions::<spl_token_2022::state::Account>::unpack(&account.data)
+            .unwrap()
+            .base
+            .amount
+    })
+}
+
+/// Withheld transfer fee of a Token-2022 token account
+pub fn withheld_amount(bank: &Bank, token_account: &Pubkey) -> u64 {
+    let account = bank.account(token_account).unwrap();
+    let state =
+        StateWithExtensions::<spl_token_2022::state::Account>::unpack(&account.data).unwrap();
+    u64::from(
+        state
+            .get_extension::<spl_token_2022::extension::transfer_fee::TransferFeeAmount>()
+            .unwrap()
+            .withheld_amount,
+    )
+}
+
+/// Transfer fees harvested to a Token-2022 mint
+pub fn mint_withheld_amount(bank: &Bank, mint: &Pubkey) -> u64 {
+    let account = bank.account(mint).unwrap();
+    let state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&account.data).unwrap();
+    u64::from(
+        state
+            .get_extension::<spl_token_2022::extension::transfer_fee::TransferFeeConfig>()
+            .unwrap()
+            .withheld_amount,
+    )
+}
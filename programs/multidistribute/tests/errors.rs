@@ -0,0 +1,1173 @@
+//! Every ErrorCode variant, triggered through the instruction that raises it.
+
+mod common;
+
+use anchor_lang::solana_program::entrypoint::ProgramResult;
+use anchor_lang::solana_program::pubkey::Pubkey;
+use common::{assert_error, create_mint, create_transfer_fee_mint, Env, DECIMALS};
+use multidistribute::instruction::InitCollection;
+use multidistribute::{CommitBonus, ErrorCode, VestingSchedule};
+use multidistribute_client::allowlist::Allowlist;
+use multidistribute_client::{instructions, pda};
+
+fn collection_args(max_collectable_tokens: u64) -> InitCollection {
+    InitCollection {
+        counter: 0,
+        max_collectable_tokens,
+        burn_tokens: false,
+        transferable_claims: false,
+        refund_deadline: None,
+        allowlist_root: None,
+        max_per_user: None,
+        min_commit: 0,
+        commit_start: None,
+        commit_end: None,
+        commit_bonus: None,
+    }
+}
+
+/// A collection with a refund window of 100 seconds
+fn refundable_collection(env: &mut Env) -> Pubkey {
+    let refund_deadline = Some(env.bank.now() + 100);
+    env.init_collection_with(InitCollection {
+        refund_deadline,
+        ..collection_args(1000)
+    })
+    .unwrap()
+}
+
+fn refund(env: &mut Env, collection: &Pubkey, user: &Pubkey, amount: u64) -> ProgramResult {
+    let instruction = instructions::user_refund_from_collection(
+        collection,
+        &env.mint,
+        user,
+        &env.ata(user, &env.mint),
+        &env.token_program,
+        amount,
+    );
+    env.run(instruction, user)
+}
+
+fn finalize(env: &mut Env, collection: &Pubkey) -> ProgramResult {
+    let authority = env.authority;
+    env.run(
+        instructions::finalize_collection(collection, &authority),
+        &authority,
+    )
+}
+
+fn close_commits(env: &mut Env, collection: &Pubkey) -> ProgramResult {
+    let authority = env.authority;
+    env.run(
+        instructions::close_collection_commits(collection, &authority),
+        &authority,
+    )
+}
+
+fn decrease_max(env: &mut Env, collection: &Pubkey, max_collectable_tokens: u64) -> ProgramResult {
+    let authority = env.authority;
+    env.run(
+        instructions::decrease_collection_max_collectable_tokens(
+            collection,
+            &authority,
+            max_collectable_tokens,
+        ),
+        &authority,
+    )
+}
+
+/// Stops compiling when a variant is added, as a reminder to test it here
+#[allow(dead_code)]
+fn all_variants_tested(error: ErrorCode) {
+    match error {
+        ErrorCode::Overflow
+        | ErrorCode::MaxCollectableTokensExceeded
+        | ErrorCode::MaxCollectableTokensBelowTotal
+        | ErrorCode::InvalidMaxCollectableTokens
+        | ErrorCode::InvalidDecrease
+        | ErrorCode::CollectionFinalized
+        | ErrorCode::NothingCollected
+        | ErrorCode::CommitsClosed
+        | ErrorCode::CommitsNotClosed
+        | ErrorCode::InvalidExpiry
+        | ErrorCode::DistributionExpired
+        | ErrorCode::DistributionNotExpired
+        | ErrorCode::InvalidVestingSchedule
+        | ErrorCode::ClaimsNotTransferable
+        | ErrorCode::RefundsUnavailable
+        | ErrorCode::InvalidRefundDeadline
+        | ErrorCode::RefundWindowClosed
+        | ErrorCode::RefundWindowOpen
+        | ErrorCode::DistributionAlreadyReceived
+        | ErrorCode::RefundExceedsDeposit
+        | ErrorCode::NotPendingAuthority
+        | ErrorCode::NotAuthorityOrGuardian
+        | ErrorCode::Paused
+        | ErrorCode::InvalidRemainingAccounts
+        | ErrorCode::DepositOutstanding
+        | ErrorCode::DistributionUserStateInUse
+        | ErrorCode::VaultNotEmpty
+        | ErrorCode::DistributionNotSettled
+        | ErrorCode::DistributionsOpen
+        | ErrorCode::AllowlistUnavailable
+        | ErrorCode::NotAllowlisted
+        | ErrorCode::AllowlistAmountExceeded
+        | ErrorCode::PerUserCapUnavailable
+        | ErrorCode::InvalidCommitLimits
+        | ErrorCode::CommitBelowMinimum
+        | ErrorCode::MaxPerUserExceeded
+        | ErrorCode::InvalidCommitWindow
+        | ErrorCode::CommitWindowNotStarted
+        | ErrorCode::CommitWindowEnded
+        | ErrorCode::InvalidWeight
+        | ErrorCode::InvalidCollectionMint
+        | ErrorCode::CollectionMintsOpen
+        | ErrorCode::CommitBonusUnavailable
+        | ErrorCode::InvalidCommitBonus => {}
+    }
+}
+
+#[test]
+fn overflow() {
+    let mut env = Env::new();
+    let collection = env.init_collection(0, 1);
+    let user = env.new_user(1);
+    env.commit(&collection, &user, 1).unwrap();
+    let distribution = env.init_distribution(&collection);
+    env.fund(&collection, u64::MAX).unwrap();
+    env.claim(&collection, &user).unwrap();
+
+    // The whole supply went through the distribution, so any further token overflows
+    // its lifetime total
+    let reward_mint = env.reward_mint;
+    let result = env.run(
+        instructions::add_distribution_tokens(
+            &distribution,
+            &reward_mint,
+            &user,
+            &env.ata(&user, &reward_mint),
+            &env.token_program,
+            1,
+        ),
+        &user,
+    );
+    assert_error(result, ErrorCode::Overflow);
+}
+
+#[test]
+fn max_collectable_tokens_exceeded() {
+    let mut env = Env::new();
+    let collection = env.init_collection(0, 100);
+    let user = env.new_user(101);
+    env.commit(&collection, &user, 60).unwrap();
+    let result = env.commit(&collection, &user, 41);
+    assert_error(result, ErrorCode::MaxCollectableTokensExceeded);
+    env.commit(&collection, &user, 40).unwrap();
+}
+
+#[test]
+fn max_collectable_tokens_below_total() {
+    let mut env = Env::new();
+    let collection = env.init_collection(0, 1000);
+    let user = env.new_user(100);
+    env.commit(&collection, &user, 100).unwrap();
+    let result = decrease_max(&mut env, &collection, 99);
+    assert_error(result, ErrorCode::MaxCollectableTokensBelowTotal);
+}
+
+#[test]
+fn invalid_max_collectable_tokens() {
+    let mut env = Env::new();
+    let result = env.init_collection_with(collection_args(0));
+    assert_error(result.map(drop), ErrorCode::InvalidMaxCollectableTokens);
+
+    // Decreasing to zero would leave shares without a denominator
+    let collection = env.init_collection(0, 1000);
+    assert_error(
+        decrease_max(&mut env, &collection, 0),
+        ErrorCode::InvalidMaxCollectableTokens,
+    );
+}
+
+#[test]
+fn invalid_decrease() {
+    let mut env = Env::new();
+    let collection = env.init_collection(0, 1000);
+    let result = decrease_max(&mut env, &collection, 1000);
+    assert_error(result, ErrorCode::InvalidDecrease);
+
+    // The commit limits can only be tightened, the minimum only loosened
+    let collection = limited_collection(&mut env, 100, 10);
+    let authority = env.authority;
+    assert_error(
+        env.run(
+            instructions::decrease_collection_max_per_user(&collection, &authority, 100),
+            &authority,
+        ),
+        ErrorCode::InvalidDecrease,
+    );
+    assert_error(
+        env.run(
+            instructions::decrease_collection_min_commit(&collection, &authority, 10),
+            &authority,
+        ),
+        ErrorCode::InvalidDecrease,
+    );
+}
+
+#[test]
+fn collection_finalized() {
+    let mut env = Env::new();
+    let collection = env.init_collection(0, 1000);
+    let user = env.new_user(200);
+    env.commit(&collection, &user, 100).unwrap();
+    finalize(&mut env, &collection).unwrap();
+
+    assert_error(
+        env.commit(&collection, &user, 100),
+        ErrorCode::CollectionFinalized,
+    );
+    assert_error(
+        decrease_max(&mut env, &collection, 500),
+        ErrorCode::CollectionFinalized,
+    );
+    assert_error(
+        finalize(&mut env, &collection),
+        ErrorCode::CollectionFinalized,
+    );
+    assert_error(
+        close_commits(&mut env, &collection),
+        ErrorCode::CollectionFinalized,
+    );
+    assert_error(
+        env.add_collection_mint(&collection, 1, 1).map(drop),
+        ErrorCode::CollectionFinalized,
+    );
+}
+
+#[test]
+fn collection_finalized_refund() {
+    let mut env = Env::new();
+    let collection = refundable_collection(&mut env);
+    let user = env.new_user(100);
+    env.commit(&collection, &user, 100).unwrap();
+    finalize(&mut env, &collection).unwrap();
+    assert_error(
+        refund(&mut env, &collection, &user, 100),
+        ErrorCode::CollectionFinalized,
+    );
+}
+
+#[test]
+fn nothing_collected() {
+    let mut env = Env::new();
+    let collection = env.init_collection(0, 1000);
+    assert_error(finalize(&mut env, &collection), ErrorCode::NothingCollected);
+}
+
+#[test]
+fn commits_closed() {
+    let mut env = Env::new();
+    let collection = env.init_collection(0, 1000);
+    let user = env.new_user(100);
+    close_commits(&mut env, &collection).unwrap();
+
+    assert_error(
+        env.commit(&collection, &user, 100),
+        ErrorCode::CommitsClosed,
+    );
+    assert_error(
+        decrease_max(&mut env, &collection, 500),
+        ErrorCode::CommitsClosed,
+    );
+    assert_error(finalize(&mut env, &collection), ErrorCode::CommitsClosed);
+    assert_error(
+        close_commits(&mut env, &collection),
+        ErrorCode::CommitsClosed,
+    );
+    assert_error(
+        env.add_collection_mint(&collection, 1, 1).map(drop),
+        ErrorCode::CommitsClosed,
+    );
+}
+
+#[test]
+fn commits_not_closed() {
+    let mut env = Env::new();
+    let collection = env.init_collection(0, 1000);
+    env.init_distribution(&collection);
+    env.fund(&collection, 100).unwrap();
+    let result = env.run(
+        instructions::clawback_distribution_remainder(
+            &collection,
+            0,
+            &env.reward_mint,
+            &env.authority,
+            &env.ata(&env.authority, &env.reward_mint),
+            &env.token_program,
+        ),
+        &env.authority.clone(),
+    );
+    assert_error(result, ErrorCode::CommitsNotClosed);
+}
+
+#[test]
+fn invalid_expiry() {
+    let mut env = Env::new();
+    let collection = env.init_collection(0, 1000);
+    let now = env.bank.now();
+    let result = env.init_distribution_with(&collection, Some(now), None);
+    assert_error(result.map(drop), ErrorCode::InvalidExpiry);
+}
+
+#[test]
+fn distribution_expired() {
+    let mut env = Env::new();
+    let collection = env.init_collection(0, 1000);
+    let user = env.new_user(100);
+    env.commit(&collection, &user, 100).unwrap();
+    let expires_at = env.bank.now() + 100;
+    env.init_distribution_with(&collection, Some(expires_at), None)
+        .unwrap();
+    env.fund(&collection, 100).unwrap();
+
+    env.bank.warp(100);
+    assert_error(
+        env.claim(&collection, &user),
+        ErrorCode::DistributionExpired,
+    );
+    assert_error(env.fund(&collection, 100), ErrorCode::DistributionExpired);
+}
+
+#[test]
+fn distribution_not_expired() {
+    let mut env = Env::new();
+    let collection = env.init_collection(0, 1000);
+    let expires_at = env.bank.now() + 100;
+    env.init_distribution_with(&collection, Some(expires_at), None)
+        .unwrap();
+    env.fund(&collection, 100).unwrap();
+
+    env.bank.warp(99);
+    let result = env.run(
+        instructions::sweep_expired_distribution(
+            &collection,
+            0,
+            &env.reward_mint,
+            &env.authority,
+            &env.ata(&env.authority, &env.reward_mint),
+            &env.token_program,
+        ),
+        &env.authority.clone(),
+    );
+    assert_error(result, ErrorCode::DistributionNotExpired);
+}
+
+#[test]
+fn invalid_vesting_schedule() {
+    let mut env = Env::new();
+    let collection = env.init_collection(0, 1000);
+    let now = env.bank.now();
+    for (start_ts, cliff_ts, end_ts) in [(now, now + 20, now + 10), (now, now, now)] {
+        let result = env.init_distribution_with(
+            &collection,
+            None,
+            Some(VestingSchedule {
+                start_ts,
+                cliff_ts,
+                end_ts,
+            }),
+        );
+        assert_error(result.map(drop), ErrorCode::InvalidVestingSchedule);
+    }
+}
+
+#[test]
+fn claims_not_transferable() {
+    let mut env = Env::new();
+    let collection = env.init_collection(0, 1000);
+    let user = env.new_user(100);
+    env.commit(&collection, &user, 100).unwrap();
+    let result = env.run(
+        instructions::redeem_replacement_tokens(&collection, &user, &env.token_program, 100),
+        &user,
+    );
+    assert_error(result, ErrorCode::ClaimsNotTransferable);
+}
+
+#[test]
+fn refunds_unavailable() {
+    let mut env = Env::new();
+    let refund_deadline = Some(env.bank.now() + 100);
+    for (burn_tokens, transferable_claims) in [(true, false), (false, true)] {
+        let result = env.init_collection_with(InitCollection {
+            burn_tokens,
+            transferable_claims,
+            refund_deadline,
+            ..collection_args(1000)
+        });
+        assert_error(result.map(drop), ErrorCode::RefundsUnavailable);
+    }
+}
+
+#[test]
+fn invalid_refund_deadline() {
+    let mut env = Env::new();
+    let result = env.init_collection_with(InitCollection {
+        refund_deadline: Some(env.bank.now()),
+        ..collection_args(1000)
+    });
+    assert_error(result.map(drop), ErrorCode::InvalidRefundDeadline);
+}
+
+#[test]
+fn refund_window_closed() {
+    let mut env = Env::new();
+    let collection = refundable_collection(&mut env);
+    let user = env.new_user(100);
+    env.commit(&collection, &user, 100).unwrap();
+    env.bank.warp(100);
+    assert_error(
+        refund(&mut env, &collection, &user, 100),
+        ErrorCode::RefundWindowClosed,
+    );
+
+    // Collections without a refund window never accept refunds
+    env.mint = common::create_mint(&mut env.bank, &spl_token::ID, &env.authority.clone());
+    let collection = env.init_collection(0, 1000);
+    let user = env.new_user(100);
+    env.commit(&collection, &user, 100).unwrap();
+    assert_error(
+        refund(&mut env, &collection, &user, 100),
+        ErrorCode::RefundWindowClosed,
+    );
+}
+
+#[test]
+fn refund_window_open() {
+    let mut env = Env::new();
+    let collection = refundable_collection(&mut env);
+    let user = env.new_user(100);
+    env.commit(&collection, &user, 100).unwrap();
+    let (authority, mint) = (env.authority, env.mint);
+    env.mint_to(&mint, &authority, 0);
+
+    let withdraw = instructions::withdraw_from_collection(
+        &collection,
+        &mint,
+        &authority,
+        &env.ata(&authority, &mint),
+        &env.token_program,
+    );
+    assert_error(
+        env.run(withdraw.clone(), &authority),
+        ErrorCode::RefundWindowOpen,
+    );
+    // Refunds only return the collection's own mint
+    assert_error(
+        env.add_collection_mint(&collection, 1, 1).map(drop),
+        ErrorCode::RefundWindowOpen,
+    );
+    env.bank.warp(100);
+    env.run(withdraw, &authority).unwrap();
+    env.add_collection_mint(&collection, 1, 1).unwrap();
+}
+
+#[test]
+fn refund_window_open_claim_for_user() {
+    let mut env = Env::new();
+    let collection = refundable_collection(&mut env);
+    let user = env.new_user(100);
+    env.commit(&collection, &user, 100).unwrap();
+    env.init_distribution(&collection);
+    env.fund(&collection, 100).unwrap();
+
+    // A cranked claim would take away the user's refund
+    let payer = env.bank.new_wallet();
+    let claim = instructions::claim_from_distribution_for_user(
+        &collection,
+        0,
+        &env.reward_mint,
+        &user,
+        &payer,
+        &env.token_program,
+    );
+    assert_error(env.run(claim.clone(), &payer), ErrorCode::RefundWindowOpen);
+    refund(&mut env, &collection, &user, 50).unwrap();
+
+    env.bank.warp(100);
+    env.run(claim, &payer).unwrap();
+}
+
+#[test]
+fn distribution_already_received() {
+    let mut env = Env::new();
+    let collection = refundable_collection(&mut env);
+    let user = env.new_user(100);
+    env.commit(&collection, &user, 100).unwrap();
+    env.init_distribution(&collection);
+    env.fund(&collection, 100).unwrap();
+    env.claim(&collection, &user).unwrap();
+    assert_error(
+        refund(&mut env, &collection, &user, 100),
+        ErrorCode::DistributionAlreadyReceived,
+    );
+}
+
+#[test]
+fn refund_exceeds_deposit() {
+    let mut env = Env::new();
+    let collection = refundable_collection(&mut env);
+    let user = env.new_user(100);
+    let other = env.new_user(100);
+    env.commit(&collection, &user, 100).unwrap();
+    env.commit(&collection, &other, 100).unwrap();
+
+    // Replacement tokens received from someone else can't be refunded
+    let replacement_mint = pda::find_replacement_mint(&collection).0;
+    let transfer = spl_token_2022::instruction::transfer_checked(
+        &env.token_program,
+        &env.ata(&other, &replacement_mint),
+        &replacement_mint,
+        &env.ata(&user, &replacement_mint),
+        &other,
+        &[],
+        50,
+        DECIMALS,
+    )
+    .unwrap();
+    env.run(transfer, &other).unwrap();
+    assert_error(
+        refund(&mut env, &collection, &user, 150),
+        ErrorCode::RefundExceedsDeposit,
+    );
+}
+
+#[test]
+fn not_pending_authority() {
+    let mut env = Env::new();
+    let collection = env.init_collection(0, 1000);
+    let new_authority = env.bank.new_wallet();
+    let impostor = env.bank.new_wallet();
+
+    assert_error(
+        env.run(
+            instructions::accept_authority(&collection, &new_authority),
+            &new_authority,
+        ),
+        ErrorCode::NotPendingAuthority,
+    );
+    env.run(
+        instructions::propose_authority(&collection, &env.authority, &new_authority),
+        &env.authority.clone(),
+    )
+    .unwrap();
+    assert_error(
+        env.run(
+            instructions::accept_authority(&collection, &impostor),
+            &impostor,
+        ),
+        ErrorCode::NotPendingAuthority,
+    );
+}
+
+#[test]
+fn not_authority_or_guardian() {
+    let mut env = Env::new();
+    let collection = env.init_collection(0, 1000);
+    let distribution = env.init_distribution(&collection);
+    let stranger = env.bank.new_wallet();
+
+    assert_error(
+        env.run(
+            instructions::set_collection_paused(&collection, &stranger, true),
+            &stranger,
+        ),
+        ErrorCode::NotAuthorityOrGuardian,
+    );
+    assert_error(
+        env.run(
+            instructions::set_distribution_paused(&collection, &distribution, &stranger, true),
+            &stranger,
+        ),
+        ErrorCode::NotAuthorityOrGuardian,
+    );
+}
+
+#[test]
+fn paused() {
+    let mut env = Env::new();
+    let collection = env
+        .init_collection_with(InitCollection {
+            transferable_claims: true,
+            ..collection_args(1000)
+        })
+        .unwrap();
+    let user = env.new_user(200);
+    env.commit(&collection, &user, 100).unwrap();
+    let distribution = env.init_distribution(&collection);
+    env.fund(&collection, 100).unwrap();
+    let authority = env.authority;
+
+    env.run(
+        instructions::set_distribution_paused(&collection, &distribution, &authority, true),
+        &authority,
+    )
+    .unwrap();
+    assert_error(env.claim(&collection, &user), ErrorCode::Paused);
+
+    env.run(
+        instructions::set_collection_paused(&collection, &authority, true),
+        &authority,
+    )
+    .unwrap();
+    env.run(
+        instructions::set_distribution_paused(&collection, &distribution, &authority, false),
+        &authority,
+    )
+    .unwrap();
+    assert_error(env.claim(&collection, &user), ErrorCode::Paused);
+    assert_error(env.commit(&collection, &user, 100), ErrorCode::Paused);
+    assert_error(
+        env.run(
+            instructions::redeem_replacement_tokens(&collection, &user, &env.token_program, 100),
+            &user,
+        ),
+        ErrorCode::Paused,
+    );
+}
+
+#[test]
+fn paused_refund() {
+    let mut env = Env::new();
+    let collection = refundable_collection(&mut env);
+    let user = env.new_user(100);
+    env.commit(&collection, &user, 100).unwrap();
+    let authority = env.authority;
+    env.run(
+        instructions::set_collection_paused(&collection, &authority, true),
+        &authority,
+    )
+    .unwrap();
+    assert_error(refund(&mut env, &collection, &user, 100), ErrorCode::Paused);
+}
+
+#[test]
+fn invalid_remaining_accounts() {
+    let mut env = Env::new();
+    let collection = env.init_collection(0, 1000);
+    let user = env.new_user(100);
+    env.commit(&collection, &user, 100).unwrap();
+    env.init_distribution(&collection);
+
+    let mut instruction = instructions::user_claim_from_distributions(
+        &collection,
+        &[(0, env.reward_mint)],
+        &user,
+        &env.token_program,
+    );
+    instruction.accounts.pop();
+    assert_error(
+        env.run(instruction.clone(), &user),
+        ErrorCode::InvalidRemainingAccounts,
+    );
+
+    instruction
+        .accounts
+        .truncate(instruction.accounts.len() - 4);
+    assert_error(
+        env.run(instruction, &user),
+        ErrorCode::InvalidRemainingAccounts,
+    );
+}
+
+#[test]
+fn deposit_outstanding() {
+    let mut env = Env::new();
+    let collection = env.init_collection(0, 1000);
+    let user = env.new_user(100);
+    env.commit(&collection, &user, 100).unwrap();
+    assert_error(
+        env.run(
+            instructions::close_collection_user_state(&collection, &user),
+            &user,
+        ),
+        ErrorCode::DepositOutstanding,
+    );
+}
+
+#[test]
+fn distribution_user_state_in_use() {
+    let mut env = Env::new();
+    let collection = env.init_collection(0, 1000);
+    let user = env.new_user(100);
+    env.commit(&collection, &user, 100).unwrap();
+    let distribution = env.init_distribution(&collection);
+    env.fund(&collection, 500).unwrap();
+    env.claim(&collection, &user).unwrap();
+    assert_error(
+        env.run(
+            instructions::close_distribution_user_state(&distribution, &user),
+            &user,
+        ),
+        ErrorCode::DistributionUserStateInUse,
+    );
+}
+
+#[test]
+fn vault_not_empty() {
+    let mut env = Env::new();
+    let collection = env.init_collection(0, 1000);
+    let user = env.new_user(100);
+    env.commit(&collection, &user, 100).unwrap();
+    let authority = env.authority;
+    assert_error(
+        env.run(
+            instructions::close_collection(&collection, &env.mint, &authority, &env.token_program),
+            &authority,
+        ),
+        ErrorCode::VaultNotEmpty,
+    );
+
+    let mint = env.add_collection_mint(&collection, 1, 1).unwrap();
+    env.mint_to(&mint, &user, 100);
+    env.commit_added_mint(&collection, &mint, &user, 100)
+        .unwrap();
+    assert_error(
+        env.run(
+            instructions::close_collection_mint(&collection, &mint, &authority, &env.token_program),
+            &authority,
+        ),
+        ErrorCode::VaultNotEmpty,
+    );
+}
+
+#[test]
+fn distribution_not_settled() {
+    let mut env = Env::new();
+    let collection = env.init_collection(0, 1000);
+    let user = env.new_user(100);
+    env.commit(&collection, &user, 100).unwrap();
+    env.init_distribution(&collection);
+    env.fund(&collection, 500).unwrap();
+    env.claim(&collection, &user).unwrap();
+
+    // Only the user's share of 50 went out, the rest belongs to uncollected tokens
+    let authority = env.authority;
+    assert_error(
+        env.run(
+            instructions::close_distribution(
+                &collection,
+                0,
+                &env.reward_mint,
+                &authority,
+                &env.token_program,
+            ),
+            &authority,
+        ),
+        ErrorCode::DistributionNotSettled,
+    );
+}
+
+#[test]
+fn distributions_open() {
+    let mut env = Env::new();
+    let collection = env.init_collection(0, 1000);
+    env.init_distribution(&collection);
+    let authority = env.authority;
+    assert_error(
+        env.run(
+            instructions::close_collection(&collection, &env.mint, &authority, &env.token_program),
+            &authority,
+        ),
+        ErrorCode::DistributionsOpen,
+    );
+}
+
+/// Creates a collection that allows `user` to commit up to `max_amount`
+fn allowlisted_collection(env: &mut Env, user: &Pubkey, max_amount: u64) -> (Pubkey, Allowlist) {
+    let allowlist = Allowlist::new(vec![(*user, max_amount), (Pubkey::new_unique(), 1000)]);
+    let collection = env
+        .init_collection_with(InitCollection {
+            allowlist_root: allowlist.root(),
+            ..collection_args(1000)
+        })
+        .unwrap();
+    (collection, allowlist)
+}
+
+#[test]
+fn allowlist_unavailable() {
+    let mut env = Env::new();
+    let result = env.init_collection_with(InitCollection {
+        transferable_claims: true,
+        allowlist_root: Some([1; 32]),
+        ..collection_args(1000)
+    });
+    assert_error(result.map(drop), ErrorCode::AllowlistUnavailable);
+
+    let collection = env
+        .init_collection_with(InitCollection {
+            transferable_claims: true,
+            ..collection_args(1000)
+        })
+        .unwrap();
+    let authority = env.authority;
+    assert_error(
+        env.run(
+            instructions::set_collection_allowlist(&collection, &authority, Some([1; 32])),
+            &authority,
+        ),
+        ErrorCode::AllowlistUnavailable,
+    );
+}
+
+#[test]
+fn not_allowlisted() {
+    let mut env = Env::new();
+    let user = env.new_user(100);
+    let (collection, allowlist) = allowlisted_collection(&mut env, &user, 100);
+
+    assert_error(
+        env.commit(&collection, &user, 10),
+        ErrorCode::NotAllowlisted,
+    );
+
+    // The proof only holds for the user's own entry
+    let other = env.new_user(100);
+    let proof = allowlist.proof(&user);
+    assert_error(
+        env.commit_with(&collection, &other, 10, proof.clone()),
+        ErrorCode::NotAllowlisted,
+    );
+    let mut proof = proof.unwrap();
+    proof.max_amount = 1000;
+    assert_error(
+        env.commit_with(&collection, &user, 10, Some(proof)),
+        ErrorCode::NotAllowlisted,
+    );
+}
+
+#[test]
+fn allowlist_amount_exceeded() {
+    let mut env = Env::new();
+    let user = env.new_user(100);
+    let (collection, allowlist) = allowlisted_collection(&mut env, &user, 60);
+
+    env.commit_with(&collection, &user, 40, allowlist.proof(&user))
+        .unwrap();
+    assert_error(
+        env.commit_with(&collection, &user, 21, allowlist.proof(&user)),
+        ErrorCode::AllowlistAmountExceeded,
+    );
+}
+
+/// Creates a collection with a per-user cap and a minimum commit, with counter 1 to
+/// leave counter 0 to other collections of the test
+fn limited_collection(env: &mut Env, max_per_user: u64, min_commit: u64) -> Pubkey {
+    env.init_collection_with(InitCollection {
+        counter: 1,
+        max_per_user: Some(max_per_user),
+        min_commit,
+        ..collection_args(1000)
+    })
+    .unwrap()
+}
+
+#[test]
+fn per_user_cap_unavailable() {
+    let mut env = Env::new();
+    let result = env.init_collection_with(InitCollection {
+        transferable_claims: true,
+        max_per_user: Some(100),
+        ..collection_args(1000)
+    });
+    assert_error(result.map(drop), ErrorCode::PerUserCapUnavailable);
+
+    let collection = env
+        .init_collection_with(InitCollection {
+            transferable_claims: true,
+            ..collection_args(1000)
+        })
+        .unwrap();
+    let authority = env.authority;
+    assert_error(
+        env.run(
+            instructions::decrease_collection_max_per_user(&collection, &authority, 100),
+            &authority,
+        ),
+        ErrorCode::PerUserCapUnavailable,
+    );
+}
+
+#[test]
+fn invalid_commit_limits() {
+    let mut env = Env::new();
+    for (max_per_user, min_commit) in [(Some(0), 0), (Some(10), 20), (None, 1001)] {
+        let result = env.init_collection_with(InitCollection {
+            max_per_user,
+            min_commit,
+            ..collection_args(1000)
+        });
+        assert_error(result.map(drop), ErrorCode::InvalidCommitLimits);
+    }
+
+    let collection = limited_collection(&mut env, 100, 10);
+    let authority = env.authority;
+    assert_error(
+        env.run(
+            instructions::decrease_collection_max_per_user(&collection, &authority, 5),
+            &authority,
+        ),
+        ErrorCode::InvalidCommitLimits,
+    );
+}
+
+#[test]
+fn commit_below_minimum() {
+    let mut env = Env::new();
+    let collection = limited_collection(&mut env, 100, 10);
+    let user = env.new_user(100);
+    assert_error(
+        env.commit(&collection, &user, 9),
+        ErrorCode::CommitBelowMinimum,
+    );
+
+    // The minimum applies to the weighted amount of added mints
+    let mint = env.add_collection_mint(&collection, 1, 2).unwrap();
+    env.mint_to(&mint, &user, 100);
+    assert_error(
+        env.commit_added_mint(&collection, &mint, &user, 19),
+        ErrorCode::CommitBelowMinimum,
+    );
+    env.commit_added_mint(&collection, &mint, &user, 20)
+        .unwrap();
+}
+
+#[test]
+fn commit_below_minimum_after_transfer_fee() {
+    let mut env = Env::with_token_program(spl_token_2022::ID);
+    env.mint = create_transfer_fee_mint(&mut env.bank, &env.authority.clone(), 100);
+    let collection = limited_collection(&mut env, 100, 10);
+    let user = env.new_user(100);
+
+    // The minimum applies to what the vault receives: a 1% fee on 10 withholds 1
+    assert_error(
+        env.commit(&collection, &user, 10),
+        ErrorCode::CommitBelowMinimum,
+    );
+    env.commit(&collection, &user, 11).unwrap();
+}
+
+#[test]
+fn max_per_user_exceeded() {
+    let mut env = Env::new();
+    let collection = limited_collection(&mut env, 100, 10);
+    let user = env.new_user(200);
+    env.commit(&collection, &user, 60).unwrap();
+    assert_error(
+        env.commit(&collection, &user, 41),
+        ErrorCode::MaxPerUserExceeded,
+    );
+}
+
+/// Creates a collection accepting commits from 10 to 100 seconds from now
+fn windowed_collection(env: &mut Env) -> Pubkey {
+    let now = env.bank.now();
+    env.init_collection_with(InitCollection {
+        commit_start: Some(now + 10),
+        commit_end: Some(now + 100),
+        ..collection_args(1000)
+    })
+    .unwrap()
+}
+
+#[test]
+fn invalid_commit_window() {
+    let mut env = Env::new();
+    let now = env.bank.now();
+    for (commit_start, commit_end) in [(None, now), (Some(now + 20), now + 10)] {
+        let result = env.init_collection_with(InitCollection {
+            commit_start,
+            commit_end: Some(commit_end),
+            ..collection_args(1000)
+        });
+        assert_error(result.map(drop), ErrorCode::InvalidCommitWindow);
+    }
+
+    let collection = windowed_collection(&mut env);
+    let authority = env.authority;
+    for commit_end in [now - 1, now + 5] {
+        assert_error(
+            env.run(
+                instructions::set_collection_commit_end(&collection, &authority, Some(commit_end)),
+                &authority,
+            ),
+            ErrorCode::InvalidCommitWindow,
+        );
+    }
+}
+
+#[test]
+fn commit_window_not_started() {
+    let mut env = Env::new();
+    let collection = windowed_collection(&mut env);
+    let user = env.new_user(100);
+    assert_error(
+        env.commit(&collection, &user, 10),
+        ErrorCode::CommitWindowNotStarted,
+    );
+}
+
+#[test]
+fn commit_window_ended() {
+    let mut env = Env::new();
+    let collection = windowed_collection(&mut env);
+    let user = env.new_user(100);
+    env.bank.warp(100);
+    assert_error(
+        env.commit(&collection, &user, 10),
+        ErrorCode::CommitWindowEnded,
+    );
+
+    // An ended window can't be reopened
+    let authority = env.authority;
+    assert_error(
+        env.run(
+            instructions::set_collection_commit_end(&collection, &authority, None),
+            &authority,
+        ),
+        ErrorCode::CommitWindowEnded,
+    );
+}
+
+#[test]
+fn invalid_weight() {
+    let mut env = Env::new();
+    let collection = env.init_collection(0, 1000);
+    for (weight_numerator, weight_denominator) in [(0, 1), (1, 0)] {
+        assert_error(
+            env.add_collection_mint(&collection, weight_numerator, weight_denominator)
+                .map(drop),
+            ErrorCode::InvalidWeight,
+        );
+    }
+}
+
+#[test]
+fn invalid_collection_mint() {
+    let mut env = Env::new();
+    let collection = env.init_collection(0, 1000);
+    let authority = env.authority;
+
+    // The collection's own mint is already accepted
+    let result = env.run(
+        instructions::add_collection_mint(
+            &collection,
+            &env.mint,
+            &authority,
+            &env.token_program,
+            1,
+            1,
+        ),
+        &authority,
+    );
+    assert_error(result, ErrorCode::InvalidCollectionMint);
+
+    // Nor can replacement tokens be committed for more replacement tokens
+    let replacement_mint = pda::find_replacement_mint(&collection).0;
+    let result = env.run(
+        instructions::add_collection_mint(
+            &collection,
+            &replacement_mint,
+            &authority,
+            &env.token_program,
+            1,
+            1,
+        ),
+        &authority,
+    );
+    assert_error(result, ErrorCode::InvalidCollectionMint);
+
+    // Replacement tokens are minted under the collection's token program
+    let mint = create_mint(&mut env.bank, &spl_token_2022::ID, &authority);
+    for token_program in [env.token_program, spl_token_2022::ID] {
+        let result = env.run(
+            instructions::add_collection_mint(&collection, &mint, &authority, &token_program, 1, 1),
+            &authority,
+        );
+        assert_error(result, ErrorCode::InvalidCollectionMint);
+    }
+}
+
+#[test]
+fn collection_mints_open() {
+    let mut env = Env::new();
+    let collection = env.init_collection(0, 1000);
+    env.add_collection_mint(&collection, 1, 1).unwrap();
+    let authority = env.authority;
+    assert_error(
+        env.run(
+            instructions::close_collection(&collection, &env.mint, &authority, &env.token_program),
+            &authority,
+        ),
+        ErrorCode::CollectionMintsOpen,
+    );
+}
+
+/// A commit bonus of 2.0 decaying over the next 100 seconds
+fn commit_bonus(env: &Env) -> Option<CommitBonus> {
+    let now = env.bank.now();
+    Some(CommitBonus {
+        start_ts: now,
+        end_ts: now + 100,
+        start_multiplier_bps: 20_000,
+    })
+}
+
+#[test]
+fn commit_bonus_unavailable() {
+    let mut env = Env::new();
+    let refund_deadline = Some(env.bank.now() + 100);
+    let invalid_args = [
+        InitCollection {
+            transferable_claims: true,
+            commit_bonus: commit_bonus(&env),
+            ..collection_args(1000)
+        },
+        InitCollection {
+            refund_deadline,
+            commit_bonus: commit_bonus(&env),
+            ..collection_args(1000)
+        },
+    ];
+    for args in invalid_args {
+        let result = env.init_collection_with(args);
+        assert_error(result.map(drop), ErrorCode::CommitBonusUnavailable);
+    }
+}
+
+#[test]
+fn invalid_commit_bonus() {
+    let mut env = Env::new();
+    let now = env.bank.now();
+    let invalid_bonuses = [
+        CommitBonus {
+            start_ts: now,
+            end_ts: now,
+            start_multiplier_bps: 20_000,
+        },
+        CommitBonus {
+            start_ts: now,
+            end_ts: now + 100,
+            start_multiplier_bps: 9_999,
+        },
+    ];
+    for commit_bonus in invalid_bonuses {
+        let result = env.init_collection_with(InitCollection {
+            commit_bonus: Some(commit_bonus),
+            ..collection_args(1000)
+        });
+        assert_error(result.map(drop), ErrorCode::InvalidCommitBonus);
+    }
+}
@@ -0,0 +1,1432 @@
+//! Happy paths of all instructions.
+
+mod common;
+
+use anchor_lang::solana_program::program_pack::Pack;
+use anchor_lang::solana_program::pubkey::Pubkey;
+use common::{assert_error, create_mint, create_transfer_fee_mint, Env, DECIMALS};
+use mpl_token_metadata::state::{Metadata, TokenMetadataAccount};
+use multidistribute::instruction::InitCollection;
+use multidistribute::{
+    Collection, CollectionMint, CollectionUserState, CommitBonus, Distribution,
+    DistributionUserState, ErrorCode, VestingSchedule,
+};
+use multidistribute_client::allowlist::Allowlist;
+use multidistribute_client::{instructions, pda};
+
+/// Transfers replacement tokens of `collection` from `from` to `to`, creating the
+/// token account of `to`
+fn transfer_replacement_tokens(
+    env: &mut Env,
+    collection: &Pubkey,
+    from: &Pubkey,
+    to: &Pubkey,
+    amount: u64,
+) {
+    let replacement_mint = pda::find_replacement_mint(collection).0;
+    let instructions = [
+        spl_associated_token_account::instruction::create_associated_token_account_idempotent(
+            from,
+            to,
+            &replacement_mint,
+            &env.token_program,
+        ),
+        spl_token_2022::instruction::transfer_checked(
+            &env.token_program,
+            &env.ata(from, &replacement_mint),
+            &replacement_mint,
+            &env.ata(to, &replacement_mint),
+            from,
+            &[],
+            amount,
+            DECIMALS,
+        )
+        .unwrap(),
+    ];
+    env.bank.process(&instructions, &[*from]).unwrap();
+}
+
+#[test]
+fn init_collection() {
+    let mut env = Env::new();
+    let collection_key = env.init_collection_with(InitCollection {
+        counter: 7,
+        max_collectable_tokens: 1000,
+        burn_tokens: false,
+        transferable_claims: false,
+        refund_deadline: Some(env.bank.now() + 100),
+        allowlist_root: None,
+        max_per_user: None,
+        min_commit: 0,
+        commit_start: None,
+        commit_end: None,
+        commit_bonus: None,
+    });
+    let collection_key = collection_key.unwrap();
+
+    let collection: Collection = env.fetch(&collection_key);
+    assert_eq!(collection.authority, env.authority);
+    assert_eq!(collection.creator, env.authority);
+    assert_eq!(collection.mint, env.mint);
+    assert_eq!(collection.counter, 7);
+    assert_eq!(collection.max_collectable_tokens, 1000);
+    assert_eq!(collection.lifetime_tokens_collected, 0);
+    assert_eq!(collection.refund_deadline, Some(env.bank.now() + 100));
+    assert_eq!(collection.vault, env.ata(&collection_key, &env.mint));
+    assert_eq!(env.balance(&collection.vault), 0);
+
+    let replacement_mint = env.bank.account(&collection.replacement_mint).unwrap();
+    let replacement_mint = spl_token::state::Mint::unpack(&replacement_mint.data).unwrap();
+    assert_eq!(replacement_mint.decimals, DECIMALS);
+    assert_eq!(replacement_mint.mint_authority, Some(collection_key).into());
+}
+
+#[test]
+fn propose_and_accept_authority() {
+    let mut env = Env::new();
+    let collection = env.init_collection(0, 1000);
+    let new_authority = env.bank.new_wallet();
+
+    env.run(
+        instructions::propose_authority(&collection, &env.authority, &new_authority),
+        &env.authority.clone(),
+    )
+    .unwrap();
+    let state: Collection = env.fetch(&collection);
+    assert_eq!(state.pending_authority, Some(new_authority));
+    assert_eq!(state.authority, env.authority);
+
+    env.run(
+        instructions::accept_authority(&collection, &new_authority),
+        &new_authority,
+    )
+    .unwrap();
+    let state: Collection = env.fetch(&collection);
+    assert_eq!(state.authority, new_authority);
+    assert_eq!(state.pending_authority, None);
+    assert_eq!(state.creator, env.authority);
+
+    // The new authority controls the collection
+    env.run(
+        instructions::decrease_collection_max_collectable_tokens(&collection, &new_authority, 500),
+        &new_authority,
+    )
+    .unwrap();
+}
+
+#[test]
+fn set_collection_guardian_and_pause() {
+    let mut env = Env::new();
+    let collection = env.init_collection(0, 1000);
+    let distribution = env.init_distribution(&collection);
+    let guardian = env.bank.new_wallet();
+
+    env.run(
+        instructions::set_collection_guardian(&collection, &env.authority, Some(guardian)),
+        &env.authority.clone(),
+    )
+    .unwrap();
+    let state: Collection = env.fetch(&collection);
+    assert_eq!(state.guardian, Some(guardian));
+
+    env.run(
+        instructions::set_collection_paused(&collection, &guardian, true),
+        &guardian,
+    )
+    .unwrap();
+    let state: Collection = env.fetch(&collection);
+    assert!(state.paused);
+
+    env.run(
+        instructions::set_distribution_paused(&collection, &distribution, &guardian, true),
+        &guardian,
+    )
+    .unwrap();
+    let state: Distribution = env.fetch(&distribution);
+    assert!(state.paused);
+
+    env.run(
+        instructions::set_collection_paused(&collection, &env.authority, false),
+        &env.authority.clone(),
+    )
+    .unwrap();
+    env.run(
+        instructions::set_distribution_paused(&collection, &distribution, &env.authority, false),
+        &env.authority.clone(),
+    )
+    .unwrap();
+    let state: Collection = env.fetch(&collection);
+    assert!(!state.paused);
+    let state: Distribution = env.fetch(&distribution);
+    assert!(!state.paused);
+
+    env.run(
+        instructions::set_collection_guardian(&collection, &env.authority, None),
+        &env.authority.clone(),
+    )
+    .unwrap();
+    let state: Collection = env.fetch(&collection);
+    assert_eq!(state.guardian, None);
+}
+
+#[test]
+fn set_collection_allowlist() {
+    let mut env = Env::new();
+    let users: Vec<_> = (0..3).map(|_| env.new_user(100)).collect();
+    let allowlist = Allowlist::new(users.iter().map(|user| (*user, 60)).collect());
+    let collection = env
+        .init_collection_with(InitCollection {
+            counter: 0,
+            max_collectable_tokens: 1000,
+            burn_tokens: false,
+            transferable_claims: false,
+            refund_deadline: None,
+            allowlist_root: allowlist.root(),
+            max_per_user: None,
+            min_commit: 0,
+            commit_start: None,
+            commit_end: None,
+            commit_bonus: None,
+        })
+        .unwrap();
+    let state: Collection = env.fetch(&collection);
+    assert_eq!(state.allowlist_root, allowlist.root());
+
+    // Allowlisted users commit up to their amount, in as many commits as they like
+    for user in &users {
+        env.commit_with(&collection, user, 40, allowlist.proof(user))
+            .unwrap();
+        env.commit_with(&collection, user, 20, allowlist.proof(user))
+            .unwrap();
+    }
+    let state: Collection = env.fetch(&collection);
+    assert_eq!(state.lifetime_tokens_collected, 180);
+
+    // A new allowlist applies to the deposits made so far
+    let outsider = env.new_user(100);
+    let allowlist = Allowlist::new(vec![(users[0], 100), (outsider, 50)]);
+    let authority = env.authority;
+    env.run(
+        instructions::set_collection_allowlist(&collection, &authority, allowlist.root()),
+        &authority,
+    )
+    .unwrap();
+    env.commit_with(&collection, &users[0], 40, allowlist.proof(&users[0]))
+        .unwrap();
+    env.commit_with(&collection, &outsider, 50, allowlist.proof(&outsider))
+        .unwrap();
+    let state: CollectionUserState =
+        env.fetch(&pda::find_collection_user_state(&collection, &users[0]).0);
+    assert_eq!(state.deposited_amount, 100);
+
+    // Without an allowlist anyone can commit again
+    env.run(
+        instructions::set_collection_allowlist(&collection, &authority, None),
+        &authority,
+    )
+    .unwrap();
+    env.commit(&collection, &users[1], 40).unwrap();
+    let state: Collection = env.fetch(&collection);
+    assert_eq!(state.allowlist_root, None);
+    assert_eq!(state.lifetime_tokens_collected, 310);
+}
+
+#[test]
+fn set_replacement_mint_metadata() {
+    let mut env = Env::new();
+    let collection = env.init_collection(0, 1000);
+    let metadata_key = pda::find_metadata(&pda::find_replacement_mint(&collection).0).0;
+
+    for (name, symbol, uri) in [
+        ("Committed Token", "cTOK", "https://example.com/1.json"),
+        ("Committed Token v2", "cTOK2", "https://example.com/2.json"),
+    ] {
+        env.run(
+            instructions::set_replacement_mint_metadata(
+                &collection,
+                &env.authority,
+                name.to_string(),
+                symbol.to_string(),
+                uri.to_string(),
+            ),
+            &env.authority.clone(),
+        )
+        .unwrap();
+
+        let account = env.bank.account(&metadata_key).unwrap();
+        let metadata = Metadata::safe_deserialize(&account.data).unwrap();
+        assert_eq!(metadata.update_authority, collection);
+        assert_eq!(metadata.data.name.trim_end_matches('\0'), name);
+        assert_eq!(metadata.data.symbol.trim_end_matches('\0'), symbol);
+        assert_eq!(metadata.data.uri.trim_end_matches('\0'), uri);
+    }
+}
+
+#[test]
+fn decrease_collection_max_collectable_tokens() {
+    let mut env = Env::new();
+    let collection = env.init_collection(0, 1000);
+    let user = env.new_user(100);
+    env.commit(&collection, &user, 100).unwrap();
+
+    env.run(
+        instructions::decrease_collection_max_collectable_tokens(&collection, &env.authority, 100),
+        &env.authority.clone(),
+    )
+    .unwrap();
+    let state: Collection = env.fetch(&collection);
+    assert_eq!(state.max_collectable_tokens, 100);
+
+    // The user now owns the whole distribution
+    env.init_distribution(&collection);
+    env.fund(&collection, 500).unwrap();
+    env.claim(&collection, &user).unwrap();
+    assert_eq!(env.balance(&env.ata(&user, &env.reward_mint)), 500);
+}
+
+#[test]
+fn decrease_collection_commit_limits() {
+    let mut env = Env::new();
+    let collection = env
+        .init_collection_with(InitCollection {
+            counter: 0,
+            max_collectable_tokens: 1000,
+            burn_tokens: false,
+            transferable_claims: false,
+            refund_deadline: None,
+            allowlist_root: None,
+            max_per_user: Some(100),
+            min_commit: 10,
+            commit_start: None,
+            commit_end: None,
+            commit_bonus: None,
+        })
+        .unwrap();
+    let user = env.new_user(100);
+    env.commit(&collection, &user, 10).unwrap();
+    env.commit(&collection, &user, 90).unwrap();
+
+    let authority = env.authority;
+    env.run(
+        instructions::decrease_collection_max_per_user(&collection, &authority, 50),
+        &authority,
+    )
+    .unwrap();
+    env.run(
+        instructions::decrease_collection_min_commit(&collection, &authority, 5),
+        &authority,
+    )
+    .unwrap();
+    let state: Collection = env.fetch(&collection);
+    assert_eq!(state.max_per_user, Some(50));
+    assert_eq!(state.min_commit, 5);
+
+    // Deposits above the new cap are kept, smaller commits are accepted
+    let other = env.new_user(100);
+    env.commit(&collection, &other, 5).unwrap();
+    env.commit(&collection, &other, 45).unwrap();
+    let state: Collection = env.fetch(&collection);
+    assert_eq!(state.lifetime_tokens_collected, 150);
+    let state: CollectionUserState =
+        env.fetch(&pda::find_collection_user_state(&collection, &user).0);
+    assert_eq!(state.deposited_amount, 100);
+}
+
+#[test]
+fn set_collection_commit_end() {
+    let mut env = Env::new();
+    let now = env.bank.now();
+    let collection = env
+        .init_collection_with(InitCollection {
+            counter: 0,
+            max_collectable_tokens: 1000,
+            burn_tokens: false,
+            transferable_claims: false,
+            refund_deadline: None,
+            allowlist_root: None,
+            max_per_user: None,
+            min_commit: 0,
+            commit_start: Some(now + 10),
+            commit_end: Some(now + 100),
+            commit_bonus: None,
+        })
+        .unwrap();
+    let user = env.new_user(100);
+    env.bank.warp(10);
+    env.commit(&collection, &user, 10).unwrap();
+
+    // Extending the window keeps commits open past the original end
+    let authority = env.authority;
+    env.run(
+        instructions::set_collection_commit_end(&collection, &authority, Some(now + 200)),
+        &authority,
+    )
+    .unwrap();
+    env.bank.warp(140);
+    env.commit(&collection, &user, 10).unwrap();
+
+    // Closing it early with a past time takes effect immediately
+    let now = env.bank.now();
+    env.run(
+        instructions::set_collection_commit_end(&collection, &authority, Some(0)),
+        &authority,
+    )
+    .unwrap();
+    assert_error(
+        env.commit(&collection, &user, 10),
+        ErrorCode::CommitWindowEnded,
+    );
+    let state: Collection = env.fetch(&collection);
+    assert_eq!(state.commit_end, Some(now));
+    assert_eq!(state.lifetime_tokens_collected, 20);
+
+    // The snapshot can still be finalized
+    env.run(
+        instructions::finalize_collection(&collection, &authority),
+        &authority,
+    )
+    .unwrap();
+}
+
+#[test]
+fn add_and_close_collection_mints() {
+    let mut env = Env::new();
+    let collection_key = env.init_collection(0, 1000);
+    let double = env.add_collection_mint(&collection_key, 2, 1).unwrap();
+    let third = env.add_collection_mint(&collection_key, 1, 3).unwrap();
+
+    let collection_mint: CollectionMint =
+        env.fetch(&pda::find_collection_mint(&collection_key, &double).0);
+    assert_eq!(collection_mint.collection, collection_key);
+    assert_eq!(collection_mint.mint, double);
+    assert_eq!(collection_mint.vault, env.ata(&collection_key, &double));
+    assert_eq!(
+        (
+            collection_mint.weight_numerator,
+            collection_mint.weight_denominator
+        ),
+        (2, 1)
+    );
+    let collection: Collection = env.fetch(&collection_key);
+    assert_eq!(collection.open_collection_mints, 2);
+
+    // Every mint is credited in weighted units, rounded down
+    let (own, doubled, thirded) = (env.new_user(100), env.new_user(0), env.new_user(0));
+    env.mint_to(&double, &doubled, 100);
+    env.mint_to(&third, &thirded, 100);
+    env.commit(&collection_key, &own, 100).unwrap();
+    env.commit_added_mint(&collection_key, &double, &doubled, 100)
+        .unwrap();
+    env.commit_added_mint(&collection_key, &third, &thirded, 100)
+        .unwrap();
+
+    let collection: Collection = env.fetch(&collection_key);
+    assert_eq!(collection.lifetime_tokens_collected, 333);
+    assert_eq!(env.balance(&collection.vault), 100);
+    assert_eq!(env.balance(&env.ata(&collection_key, &double)), 100);
+    assert_eq!(env.balance(&env.ata(&collection_key, &third)), 100);
+    for (user, weighted) in [(own, 100), (doubled, 200), (thirded, 33)] {
+        let state: CollectionUserState =
+            env.fetch(&pda::find_collection_user_state(&collection_key, &user).0);
+        assert_eq!(state.deposited_amount, weighted);
+        assert_eq!(
+            env.balance(&env.ata(&user, &collection.replacement_mint)),
+            weighted
+        );
+    }
+    let collection_mint: CollectionMint =
+        env.fetch(&pda::find_collection_mint(&collection_key, &third).0);
+    assert_eq!(collection_mint.lifetime_tokens_collected, 100);
+
+    // A single distribution pays across all mints
+    env.init_distribution(&collection_key);
+    env.fund(&collection_key, 10_000).unwrap();
+    for (user, claimed) in [(own, 1000), (doubled, 2000), (thirded, 330)] {
+        env.claim(&collection_key, &user).unwrap();
+        assert_eq!(env.balance(&env.ata(&user, &env.reward_mint)), claimed);
+    }
+
+    // Added mints are withdrawn from their own vault before closing
+    let authority = env.authority;
+    env.mint_to(&double, &authority, 0);
+    let vault = env.ata(&collection_key, &double);
+    let collection_mint_key = pda::find_collection_mint(&collection_key, &double).0;
+    let rent = env.bank.lamports(&vault) + env.bank.lamports(&collection_mint_key);
+    let instructions = [
+        instructions::withdraw_added_mint_from_collection(
+            &collection_key,
+            &double,
+            &authority,
+            &env.ata(&authority, &double),
+            &env.token_program,
+        ),
+        instructions::close_collection_mint(
+            &collection_key,
+            &double,
+            &authority,
+            &env.token_program,
+        ),
+    ];
+    let authority_lamports = env.bank.lamports(&authority);
+    env.bank.process(&instructions, &[authority]).unwrap();
+
+    assert_eq!(env.balance(&env.ata(&authority, &double)), 100);
+    assert!(env.bank.account(&vault).is_none());
+    assert!(env.bank.account(&collection_mint_key).is_none());
+    assert_eq!(env.bank.lamports(&authority), authority_lamports + rent);
+    let collection: Collection = env.fetch(&collection_key);
+    assert_eq!(collection.open_collection_mints, 1);
+    assert_eq!(collection.lifetime_tokens_collected, 333);
+
+    // A closed mint is no longer accepted
+    env.mint_to(&double, &doubled, 100);
+    let result = env.commit_added_mint(&collection_key, &double, &doubled, 100);
+    assert_error(result, anchor_lang::error::ErrorCode::AccountNotInitialized);
+}
+
+#[test]
+fn finalize_collection() {
+    let mut env = Env::new();
+    let collection = env.init_collection(0, 1000);
+    let user = env.new_user(100);
+    let other = env.new_user(300);
+    env.commit(&collection, &user, 100).unwrap();
+    env.commit(&collection, &other, 300).unwrap();
+    env.init_distribution(&collection);
+    env.fund(&collection, 800).unwrap();
+
+    // Before finalization shares are computed against the maximum
+    env.claim(&collection, &user).unwrap();
+    assert_eq!(env.balance(&env.ata(&user, &env.reward_mint)), 80);
+
+    env.run(
+        instructions::finalize_collection(&collection, &env.authority),
+        &env.authority.clone(),
+    )
+    .unwrap();
+    let state: Collection = env.fetch(&collection);
+    assert!(state.finalized);
+
+    // Afterwards against the collected total, paying out the difference
+    env.claim(&collection, &user).unwrap();
+    env.claim(&collection, &other).unwrap();
+    assert_eq!(env.balance(&env.ata(&user, &env.reward_mint)), 200);
+    assert_eq!(env.balance(&env.ata(&other, &env.reward_mint)), 600);
+}
+
+#[test]
+fn close_collection_commits_and_claw_back() {
+    let mut env = Env::new();
+    let collection = env.init_collection(0, 1000);
+    let user = env.new_user(100);
+    env.commit(&collection, &user, 100).unwrap();
+    let distribution = env.init_distribution(&collection);
+    env.fund(&collection, 500).unwrap();
+
+    env.run(
+        instructions::close_collection_commits(&collection, &env.authority),
+        &env.authority.clone(),
+    )
+    .unwrap();
+    let state: Collection = env.fetch(&collection);
+    assert!(state.commits_closed);
+
+    let authority_token_account = env.ata(&env.authority, &env.reward_mint);
+    let clawback = instructions::clawback_distribution_remainder(
+        &collection,
+        0,
+        &env.reward_mint,
+        &env.authority,
+        &authority_token_account,
+        &env.token_program,
+    );
+    env.run(clawback.clone(), &env.authority.clone()).unwrap();
+    assert_eq!(env.balance(&authority_token_account), 450);
+    let state: Distribution = env.fetch(&distribution);
+    assert_eq!(state.clawed_back_tokens, 450);
+
+    // Clawing back again only returns the remainder of new tokens
+    env.fund(&collection, 100).unwrap();
+    env.run(clawback, &env.authority.clone()).unwrap();
+    assert_eq!(env.balance(&authority_token_account), 540);
+
+    // The user's share is untouched
+    env.claim(&collection, &user).unwrap();
+    assert_eq!(env.balance(&env.ata(&user, &env.reward_mint)), 60);
+    assert_eq!(env.balance(&state.vault), 0);
+}
+
+#[test]
+fn withdraw_from_collection() {
+    let mut env = Env::new();
+    let collection = env.init_collection(0, 1000);
+    let user = env.new_user(100);
+    env.commit(&collection, &user, 100).unwrap();
+    let (authority, mint) = (env.authority, env.mint);
+    env.mint_to(&mint, &authority, 0);
+
+    let authority_token_account = env.ata(&authority, &mint);
+    env.run(
+        instructions::withdraw_from_collection(
+            &collection,
+            &mint,
+            &authority,
+            &authority_token_account,
+            &env.token_program,
+        ),
+        &authority,
+    )
+    .unwrap();
+    assert_eq!(env.balance(&authority_token_account), 100);
+    assert_eq!(env.balance(&env.ata(&collection, &mint)), 0);
+
+    // Withdrawing doesn't affect the user's deposit
+    let state: CollectionUserState =
+        env.fetch(&pda::find_collection_user_state(&collection, &user).0);
+    assert_eq!(state.deposited_amount, 100);
+}
+
+#[test]
+fn init_distribution() {
+    let mut env = Env::new();
+    let collection = env.init_collection(0, 1000);
+    let now = env.bank.now();
+    let vesting = VestingSchedule {
+        start_ts: now,
+        cliff_ts: now + 10,
+        end_ts: now + 100,
+    };
+    let distribution_key = env
+        .init_distribution_with(&collection, Some(now + 1000), Some(vesting))
+        .unwrap();
+
+    let distribution: Distribution = env.fetch(&distribution_key);
+    assert_eq!(distribution.collection, collection);
+    assert_eq!(distribution.id, 0);
+    assert_eq!(distribution.mint, env.reward_mint);
+    assert_eq!(
+        distribution.vault,
+        env.ata(&distribution_key, &env.reward_mint)
+    );
+    assert_eq!(distribution.expires_at, Some(now + 1000));
+    assert_eq!(distribution.vesting, Some(vesting));
+    assert_eq!(distribution.lifetime_deposited_tokens, 0);
+    assert_eq!(env.balance(&distribution.vault), 0);
+    let collection: Collection = env.fetch(&collection);
+    assert_eq!(collection.distribution_count, 1);
+}
+
+#[test]
+fn init_distributions_of_the_same_mint() {
+    let mut env = Env::new();
+    let collection = env.init_collection(0, 1000);
+    let user = env.new_user(100);
+    env.commit(&collection, &user, 100).unwrap();
+    let first = env.init_distribution(&collection);
+    let second = env.init_distribution(&collection);
+    assert_eq!(first, pda::find_distribution(&collection, 0).0);
+    assert_eq!(second, pda::find_distribution(&collection, 1).0);
+    let state: Collection = env.fetch(&collection);
+    assert_eq!(state.distribution_count, 2);
+    assert_eq!(state.open_distributions, 2);
+
+    // Each distribution has its own vault and is claimed from separately
+    let distribution: Distribution = env.fetch(&second);
+    assert_eq!(distribution.id, 1);
+    assert_eq!(distribution.vault, env.ata(&second, &env.reward_mint));
+    let (authority, reward_mint) = (env.authority, env.reward_mint);
+    env.fund(&collection, 500).unwrap();
+    env.mint_to(&reward_mint, &authority, 300);
+    env.run(
+        instructions::add_distribution_tokens(
+            &second,
+            &reward_mint,
+            &authority,
+            &env.ata(&authority, &reward_mint),
+            &env.token_program,
+            300,
+        ),
+        &authority,
+    )
+    .unwrap();
+
+    env.claim(&collection, &user).unwrap();
+    env.run(
+        instructions::user_claim_from_distribution(
+            &collection,
+            1,
+            &reward_mint,
+            &user,
+            &env.token_program,
+        ),
+        &user,
+    )
+    .unwrap();
+    assert_eq!(env.balance(&env.ata(&user, &reward_mint)), 80);
+    assert_eq!(env.balance(&env.ata(&first, &reward_mint)), 450);
+    assert_eq!(env.balance(&env.ata(&second, &reward_mint)), 270);
+}
+
+#[test]
+fn add_distribution_tokens() {
+    let mut env = Env::new();
+    let collection = env.init_collection(0, 1000);
+    let distribution_key = env.init_distribution(&collection);
+    env.fund(&collection, 300).unwrap();
+    env.fund(&collection, 200).unwrap();
+
+    let distribution: Distribution = env.fetch(&distribution_key);
+    assert_eq!(distribution.lifetime_deposited_tokens, 500);
+    assert_eq!(env.balance(&distribution.vault), 500);
+
+    // Anyone can add tokens
+    let funder = env.bank.new_wallet();
+    let reward_mint = env.reward_mint;
+    env.mint_to(&reward_mint, &funder, 50);
+    env.run(
+        instructions::add_distribution_tokens(
+            &distribution_key,
+            &reward_mint,
+            &funder,
+            &env.ata(&funder, &reward_mint),
+            &env.token_program,
+            50,
+        ),
+        &funder,
+    )
+    .unwrap();
+    let distribution: Distribution = env.fetch(&distribution_key);
+    assert_eq!(distribution.lifetime_deposited_tokens, 550);
+}
+
+#[test]
+fn sweep_expired_distribution() {
+    let mut env = Env::new();
+    let collection = env.init_collection(0, 1000);
+    let user = env.new_user(100);
+    env.commit(&collection, &user, 100).unwrap();
+    let expires_at = env.bank.now() + 100;
+    let distribution_key = env
+        .init_distribution_with(&collection, Some(expires_at), None)
+        .unwrap();
+    env.fund(&collection, 500).unwrap();
+    env.claim(&collection, &user).unwrap();
+
+    env.bank.warp(100);
+    let authority_token_account = env.ata(&env.authority, &env.reward_mint);
+    let vault = env.ata(&distribution_key, &env.reward_mint);
+    let vault_lamports = env.bank.lamports(&vault);
+    let authority_lamports = env.bank.lamports(&env.authority);
+    env.run(
+        instructions::sweep_expired_distribution(
+            &collection,
+            0,
+            &env.reward_mint,
+            &env.authority,
+            &authority_token_account,
+            &env.token_program,
+        ),
+        &env.authority.clone(),
+    )
+    .unwrap();
+
+    assert_eq!(env.balance(&authority_token_account), 450);
+    assert!(env.bank.account(&vault).is_none());
+    assert_eq!(
+        env.bank.lamports(&env.authority),
+        authority_lamports + vault_lamports
+    );
+    let distribution: Distribution = env.fetch(&distribution_key);
+    assert!(distribution.swept);
+    assert_eq!(distribution.swept_tokens, 450);
+}
+
+#[test]
+fn sweep_expired_distribution_with_transfer_fee() {
+    let mut env = Env::with_token_program(spl_token_2022::ID);
+    let authority = env.authority;
+    env.reward_mint = create_transfer_fee_mint(&mut env.bank, &authority, 100);
+    let collection = env.init_collection(0, 1000);
+    let expires_at = env.bank.now() + 100;
+    let distribution = env
+        .init_distribution_with(&collection, Some(expires_at), None)
+        .unwrap();
+    env.fund(&collection, 500).unwrap();
+
+    env.bank.warp(100);
+    let authority_token_account = env.ata(&authority, &env.reward_mint);
+    let vault = env.ata(&distribution, &env.reward_mint);
+    env.run(
+        instructions::sweep_expired_distribution(
+            &collection,
+            0,
+            &env.reward_mint,
+            &authority,
+            &authority_token_account,
+            &env.token_program,
+        ),
+        &authority,
+    )
+    .unwrap();
+
+    // The fee of the funding is harvested from the vault, the sweep's fee is withheld
+    // in the authority's account
+    assert!(env.bank.account(&vault).is_none());
+    assert_eq!(common::mint_withheld_amount(&env.bank, &env.reward_mint), 5);
+    assert_eq!(env.balance(&authority_token_account), 490);
+    assert_eq!(
+        common::withheld_amount(&env.bank, &authority_token_account),
+        5
+    );
+    let distribution: Distribution = env.fetch(&distribution);
+    assert_eq!(distribution.swept_tokens, 495);
+}
+
+#[test]
+fn user_commit_to_collection() {
+    let mut env = Env::new();
+    let collection_key = env.init_collection(0, 1000);
+    let user = env.new_user(100);
+    env.commit(&collection_key, &user, 60).unwrap();
+    env.commit(&collection_key, &user, 40).unwrap();
+
+    let collection: Collection = env.fetch(&collection_key);
+    assert_eq!(collection.lifetime_tokens_collected, 100);
+    assert_eq!(env.balance(&collection.vault), 100);
+    assert_eq!(env.balance(&env.ata(&user, &env.mint)), 0);
+    assert_eq!(
+        env.balance(&env.ata(&user, &collection.replacement_mint)),
+        100
+    );
+    let state: CollectionUserState =
+        env.fetch(&pda::find_collection_user_state(&collection_key, &user).0);
+    assert_eq!(state.deposited_amount, 100);
+}
+
+#[test]
+fn user_commit_to_burning_collection() {
+    let mut env = Env::new();
+    let collection_key = env
+        .init_collection_with(InitCollection {
+            counter: 0,
+            max_collectable_tokens: 1000,
+            burn_tokens: true,
+            transferable_claims: false,
+            refund_deadline: None,
+            allowlist_root: None,
+            max_per_user: None,
+            min_commit: 0,
+            commit_start: None,
+            commit_end: None,
+            commit_bonus: None,
+        })
+        .unwrap();
+    let user = env.new_user(100);
+    env.commit(&collection_key, &user, 100).unwrap();
+
+    let collection: Collection = env.fetch(&collection_key);
+    assert_eq!(collection.lifetime_tokens_collected, 100);
+    assert_eq!(env.balance(&collection.vault), 0);
+    let mint = spl_token::state::Mint::unpack(&env.bank.account(&env.mint).unwrap().data).unwrap();
+    assert_eq!(mint.supply, 0);
+}
+
+#[test]
+fn user_commit_with_transfer_fee() {
+    let mut env = Env::with_token_program(spl_token_2022::ID);
+    env.mint = create_transfer_fee_mint(&mut env.bank, &env.authority.clone(), 100);
+    let collection_key = env.init_collection(0, 1000);
+    let user = env.new_user(100);
+    env.commit(&collection_key, &user, 100).unwrap();
+
+    // 1% is withheld in the vault
+    let collection: Collection = env.fetch(&collection_key);
+    assert_eq!(collection.lifetime_tokens_collected, 99);
+    assert_eq!(common::withheld_amount(&env.bank, &collection.vault), 1);
+    assert_eq!(
+        env.balance(&env.ata(&user, &collection.replacement_mint)),
+        99
+    );
+    let state: CollectionUserState =
+        env.fetch(&pda::find_collection_user_state(&collection_key, &user).0);
+    assert_eq!(state.deposited_amount, 99);
+}
+
+#[test]
+fn user_commit_with_bonus() {
+    let mut env = Env::new();
+    let now = env.bank.now();
+    let collection_key = env
+        .init_collection_with(InitCollection {
+            counter: 0,
+            max_collectable_tokens: 1000,
+            burn_tokens: false,
+            transferable_claims: false,
+            refund_deadline: None,
+            allowlist_root: None,
+            max_per_user: None,
+            min_commit: 0,
+            commit_start: None,
+            commit_end: None,
+            commit_bonus: Some(CommitBonus {
+                start_ts: now + 10,
+                end_ts: now + 110,
+                start_multiplier_bps: 30_000,
+            }),
+        })
+        .unwrap();
+
+    // The full bonus until the start, half of it midway and none from the end
+    let users = [env.new_user(100), env.new_user(100), env.new_user(100)];
+    env.commit(&collection_key, &users[0], 100).unwrap();
+    env.bank.warp(60);
+    env.commit(&collection_key, &users[1], 100).unwrap();
+    env.bank.warp(50);
+    env.commit(&collection_key, &users[2], 100).unwrap();
+
+    let collection: Collection = env.fetch(&collection_key);
+    assert_eq!(collection.lifetime_tokens_collected, 600);
+    assert_eq!(env.balance(&collection.vault), 300);
+    for (user, credited) in users.iter().zip([300, 200, 100]) {
+        let state: CollectionUserState =
+            env.fetch(&pda::find_collection_user_state(&collection_key, user).0);
+        assert_eq!(state.deposited_amount, credited);
+        // Replacement tokens don't carry the bonus
+        assert_eq!(
+            env.balance(&env.ata(user, &collection.replacement_mint)),
+            100
+        );
+    }
+
+    // Shares are computed in credited units
+    env.init_distribution(&collection_key);
+    env.fund(&collection_key, 1000).unwrap();
+    for (user, claimed) in users.iter().zip([300, 200, 100]) {
+        env.claim(&collection_key, user).unwrap();
+        assert_eq!(env.balance(&env.ata(user, &env.reward_mint)), claimed);
+    }
+}
+
+#[test]
+fn user_refund_from_collection() {
+    let mut env = Env::new();
+    let collection_key = env
+        .init_collection_with(InitCollection {
+            counter: 0,
+            max_collectable_tokens: 1000,
+            burn_tokens: false,
+            transferable_claims: false,
+            refund_deadline: Some(env.bank.now() + 100),
+            allowlist_root: None,
+            max_per_user: None,
+            min_commit: 0,
+            commit_start: None,
+            commit_end: None,
+            commit_bonus: None,
+        })
+        .unwrap();
+    let user = env.new_user(100);
+    env.commit(&collection_key, &user, 100).unwrap();
+
+    env.run(
+        instructions::user_refund_from_collection(
+            &collection_key,
+            &env.mint,
+            &user,
+            &env.ata(&user, &env.mint),
+            &env.token_program,
+            40,
+        ),
+        &user,
+    )
+    .unwrap();
+
+    let collection: Collection = env.fetch(&collection_key);
+    assert_eq!(collection.lifetime_tokens_collected, 60);
+    assert_eq!(env.balance(&env.ata(&user, &env.mint)), 40);
+    assert_eq!(
+        env.balance(&env.ata(&user, &collection.replacement_mint)),
+        60
+    );
+    let state: CollectionUserState =
+        env.fetch(&pda::find_collection_user_state(&collection_key, &user).0);
+    assert_eq!(state.deposited_amount, 60);
+}
+
+#[test]
+fn redeem_replacement_tokens() {
+    let mut env = Env::new();
+    let collection = env
+        .init_collection_with(InitCollection {
+            counter: 0,
+            max_collectable_tokens: 1000,
+            burn_tokens: false,
+            transferable_claims: true,
+            refund_deadline: None,
+            allowlist_root: None,
+            max_per_user: None,
+            min_commit: 0,
+            commit_start: None,
+            commit_end: None,
+            commit_bonus: None,
+        })
+        .unwrap();
+    let user = env.new_user(100);
+    env.commit(&collection, &user, 100).unwrap();
+    env.init_distribution(&collection);
+    env.fund(&collection, 1000).unwrap();
+
+    // The buyer of the replacement tokens gets the entitlement
+    let buyer = env.bank.new_wallet();
+    transfer_replacement_tokens(&mut env, &collection, &user, &buyer, 30);
+    env.run(
+        instructions::redeem_replacement_tokens(&collection, &buyer, &env.token_program, 30),
+        &buyer,
+    )
+    .unwrap();
+
+    let state: CollectionUserState =
+        env.fetch(&pda::find_collection_user_state(&collection, &buyer).0);
+    assert_eq!(state.deposited_amount, 30);
+    env.claim(&collection, &buyer).unwrap();
+    assert_eq!(env.balance(&env.ata(&buyer, &env.reward_mint)), 30);
+
+    // Unredeemed replacement tokens don't entitle to anything
+    env.claim(&collection, &user).unwrap();
+    assert_eq!(env.balance(&env.ata(&user, &env.reward_mint)), 0);
+}
+
+#[test]
+fn user_claim_from_distribution() {
+    let mut env = Env::new();
+    let collection = env.init_collection(0, 1000);
+    let user = env.new_user(250);
+    env.commit(&collection, &user, 250).unwrap();
+    let distribution_key = env.init_distribution(&collection);
+    env.fund(&collection, 400).unwrap();
+
+    env.claim(&collection, &user).unwrap();
+    assert_eq!(env.balance(&env.ata(&user, &env.reward_mint)), 100);
+
+    // Claiming again without new tokens pays nothing, after funding the difference
+    env.claim(&collection, &user).unwrap();
+    assert_eq!(env.balance(&env.ata(&user, &env.reward_mint)), 100);
+    env.fund(&collection, 200).unwrap();
+    env.claim(&collection, &user).unwrap();
+    assert_eq!(env.balance(&env.ata(&user, &env.reward_mint)), 150);
+
+    let distribution: Distribution = env.fetch(&distribution_key);
+    assert_eq!(distribution.distributed_tokens, 150);
+    let state: DistributionUserState =
+        env.fetch(&pda::find_distribution_user_state(&distribution_key, &user).0);
+    assert_eq!(state.received_amount, 150);
+    let state: CollectionUserState =
+        env.fetch(&pda::find_collection_user_state(&collection, &user).0);
+    assert!(state.received_distribution);
+}
+
+#[test]
+fn user_claim_from_vesting_distribution() {
+    let mut env = Env::new();
+    let collection = env.init_collection(0, 100);
+    let user = env.new_user(100);
+    env.commit(&collection, &user, 100).unwrap();
+    let now = env.bank.now();
+    env.init_distribution_with(
+        &collection,
+        None,
+        Some(VestingSchedule {
+            start_ts: now,
+            cliff_ts: now + 20,
+            end_ts: now + 100,
+        }),
+    )
+    .unwrap();
+    env.fund(&collection, 1000).unwrap();
+
+    let user_token_account = env.ata(&user, &env.reward_mint);
+    env.bank.warp(10);
+    env.claim(&collection, &user).unwrap();
+    assert_eq!(env.balance(&user_token_account), 0);
+    env.bank.warp(15);
+    env.claim(&collection, &user).unwrap();
+    assert_eq!(env.balance(&user_token_account), 250);
+    env.bank.warp(1000);
+    env.claim(&collection, &user).unwrap();
+    assert_eq!(env.balance(&user_token_account), 1000);
+}
+
+#[test]
+fn claim_from_distribution_for_user() {
+    let mut env = Env::new();
+    let collection = env.init_collection(0, 1000);
+    let user = env.new_user(100);
+    env.commit(&collection, &user, 100).unwrap();
+    env.init_distribution(&collection);
+    env.fund(&collection, 500).unwrap();
+
+    let payer = env.bank.new_wallet();
+    let user_lamports = env.bank.lamports(&user);
+    env.run(
+        instructions::claim_from_distribution_for_user(
+            &collection,
+            0,
+            &env.reward_mint,
+            &user,
+            &payer,
+            &env.token_program,
+        ),
+        &payer,
+    )
+    .unwrap();
+
+    // The user's token account was created and paid for by the payer
+    assert_eq!(env.balance(&env.ata(&user, &env.reward_mint)), 50);
+    assert_eq!(env.bank.lamports(&user), user_lamports);
+}
+
+#[test]
+fn user_claim_from_distributions() {
+    let mut env = Env::new();
+    let collection = env.init_collection(0, 1000);
+    let user = env.new_user(100);
+    env.commit(&collection, &user, 100).unwrap();
+
+    let (authority, token_program) = (env.authority, env.token_program);
+    let other_mint = create_mint(&mut env.bank, &token_program, &authority);
+    let distributions = [(0, env.reward_mint), (1, other_mint)];
+    for ((id, mint), amount) in distributions.iter().zip([500, 2000]) {
+        env.mint_to(mint, &authority, amount);
+        env.mint_to(mint, &user, 0);
+        let instructions = [
+            instructions::init_distribution(
+                &collection,
+                *id,
+                &authority,
+                mint,
+                &token_program,
+                None,
+                None,
+            ),
+            instructions::add_distribution_tokens(
+                &pda::find_distribution(&collection, *id).0,
+                mint,
+                &authority,
+                &env.ata(&authority, mint),
+                &token_program,
+                amount,
+            ),
+        ];
+        env.bank.process(&instructions, &[authority]).unwrap();
+    }
+
+    env.run(
+        instructions::user_claim_from_distributions(
+            &collection,
+            &distributions,
+            &user,
+            &token_program,
+        ),
+        &user,
+    )
+    .unwrap();
+    assert_eq!(env.balance(&env.ata(&user, &env.reward_mint)), 50);
+    assert_eq!(env.balance(&env.ata(&user, &other_mint)), 200);
+    for (id, mint) in &distributions {
+        let distribution = pda::find_distribution(&collection, *id).0;
+        let state: DistributionUserState =
+            env.fetch(&pda::find_distribution_user_state(&distribution, &user).0);
+        assert_eq!(state.received_amount, env.balance(&env.ata(&user, mint)));
+    }
+}
+
+#[test]
+fn close_collection_user_state() {
+    let mut env = Env::new();
+    let collection = env
+        .init_collection_with(InitCollection {
+            counter: 0,
+            max_collectable_tokens: 1000,
+            burn_tokens: false,
+            transferable_claims: true,
+            refund_deadline: None,
+            allowlist_root: None,
+            max_per_user: None,
+            min_commit: 0,
+            commit_start: None,
+            commit_end: None,
+            commit_bonus: None,
+        })
+        .unwrap();
+    // With transferable claims, committing leaves nothing deposited in the state
+    let user = env.new_user(100);
+    env.commit(&collection, &user, 100).unwrap();
+
+    let user_state = pda::find_collection_user_state(&collection, &user).0;
+    let rent = env.bank.lamports(&user_state);
+    let user_lamports = env.bank.lamports(&user);
+    env.run(
+        instructions::close_collection_user_state(&collection, &user),
+        &user,
+    )
+    .unwrap();
+
+    assert!(env.bank.account(&user_state).is_none());
+    assert_eq!(env.bank.lamports(&user), user_lamports + rent);
+}
+
+#[test]
+fn close_distribution_user_state() {
+    let mut env = Env::new();
+    let collection = env.init_collection(0, 1000);
+    let user = env.new_user(100);
+    env.commit(&collection, &user, 100).unwrap();
+    let expires_at = env.bank.now() + 100;
+    let distribution = env
+        .init_distribution_with(&collection, Some(expires_at), None)
+        .unwrap();
+    env.fund(&collection, 500).unwrap();
+    env.claim(&collection, &user).unwrap();
+
+    env.bank.warp(100);
+    let user_state = pda::find_distribution_user_state(&distribution, &user).0;
+    let rent = env.bank.lamports(&user_state);
+    let user_lamports = env.bank.lamports(&user);
+    env.run(
+        instructions::close_distribution_user_state(&distribution, &user),
+        &user,
+    )
+    .unwrap();
+
+    assert!(env.bank.account(&user_state).is_none());
+    assert_eq!(env.bank.lamports(&user), user_lamports + rent);
+
+    // Recreating the state doesn't allow claiming again
+    assert_error(
+        env.claim(&collection, &user),
+        ErrorCode::DistributionExpired,
+    );
+    assert_eq!(env.balance(&env.ata(&user, &env.reward_mint)), 50);
+}
+
+#[test]
+fn close_collection() {
+    let mut env = Env::new();
+    let collection_key = env.init_collection(0, 1000);
+    let user = env.new_user(100);
+    env.commit(&collection_key, &user, 100).unwrap();
+    let collection: Collection = env.fetch(&collection_key);
+
+    let authority = env.authority;
+    let mint = env.mint;
+    env.mint_to(&mint, &authority, 0);
+    let instructions = [
+        instructions::withdraw_from_collection(
+            &collection_key,
+            &mint,
+            &authority,
+            &env.ata(&authority, &mint),
+            &env.token_program,
+        ),
+        instructions::close_collection(&collection_key, &mint, &authority, &env.token_program),
+    ];
+    let rent = env.bank.lamports(&collection_key) + env.bank.lamports(&collection.vault);
+    let authority_lamports = env.bank.lamports(&authority);
+    env.bank.process(&instructions, &[authority]).unwrap();
+
+    assert!(env.bank.account(&collection_key).is_none());
+    assert!(env.bank.account(&collection.vault).is_none());
+    assert_eq!(env.bank.lamports(&authority), authority_lamports + rent);
+    let replacement_mint = env.bank.account(&collection.replacement_mint).unwrap();
+    let replacement_mint = spl_token::state::Mint::unpack(&replacement_mint.data).unwrap();
+    assert!(replacement_mint.mint_authority.is_none());
+
+    // The collection can't come back, so its users may close their states
+    env.run(
+        instructions::close_collection_user_state(&collection_key, &user),
+        &user,
+    )
+    .unwrap();
+}
+
+#[test]
+fn close_collection_with_transfer_fee() {
+    let mut env = Env::with_token_program(spl_token_2022::ID);
+    let authority = env.authority;
+    env.mint = create_transfer_fee_mint(&mut env.bank, &authority, 100);
+    let added = create_transfer_fee_mint(&mut env.bank, &authority, 100);
+    let collection_key = env.init_collection(0, 1000);
+    env.run(
+        instructions::add_collection_mint(
+            &collection_key,
+            &added,
+            &authority,
+            &env.token_program,
+            1,
+            1,
+        ),
+        &authority,
+    )
+    .unwrap();
+    let user = env.new_user(100);
+    env.mint_to(&added, &user, 100);
+    env.commit(&collection_key, &user, 100).unwrap();
+    env.commit_added_mint(&collection_key, &added, &user, 100)
+        .unwrap();
+
+    // The vaults are empty after the withdrawals, but still hold the fees of the commits
+    let mint = env.mint;
+    env.mint_to(&mint, &authority, 0);
+    env.mint_to(&added, &authority, 0);
+    let instructions = [
+        instructions::withdraw_added_mint_from_collection(
+            &collection_key,
+            &added,
+            &authority,
+            &env.ata(&authority, &added),
+            &env.token_program,
+        ),
+        instructions::close_collection_mint(
+            &collection_key,
+            &added,
+            &authority,
+            &env.token_program,
+        ),
+        instructions::withdraw_from_collection(
+            &collection_key,
+            &mint,
+            &authority,
+            &env.ata(&authority, &mint),
+            &env.token_program,
+        ),
+        instructions::close_collection(&collection_key, &mint, &authority, &env.token_program),
+    ];
+    env.bank.process(&instructions, &[authority]).unwrap();
+
+    assert!(env.bank.account(&collection_key).is_none());
+    assert!(env.bank.account(&env.ata(&collection_key, &mint)).is_none());
+    assert!(env
+        .bank
+        .account(&env.ata(&collection_key, &added))
+        .is_none());
+    assert_eq!(common::mint_withheld_amount(&env.bank, &mint), 1);
+    assert_eq!(common::mint_withheld_amount(&env.bank, &added), 1);
+}
+
+#[test]
+fn close_distribution() {
+    let mut env = Env::new();
+    let collection_key = env.init_collection(0, 1000);
+    let user = env.new_user(100);
+    env.commit(&collection_key, &user, 100).unwrap();
+    let authority = env.authority;
+    env.run(
+        instructions::finalize_collection(&collection_key, &authority),
+        &authority,
+    )
+    .unwrap();
+    let distribution = env.init_distribution(&collection_key);
+    env.fund(&collection_key, 500).unwrap();
+    env.claim(&collection_key, &user).unwrap();
+
+    let vault = env.ata(&distribution, &env.reward_mint);
+    let rent = env.bank.lamports(&distribution) + env.bank.lamports(&vault);
+    let authority_lamports = env.bank.lamports(&authority);
+    env.run(
+        instructions::close_distribution(
+            &collection_key,
+            0,
+            &env.reward_mint,
+            &authority,
+            &env.token_program,
+        ),
+        &authority,
+    )
+    .unwrap();
+
+    assert!(env.bank.account(&distribution).is_none());
+    assert!(env.bank.account(&vault).is_none());
+    assert_eq!(env.bank.lamports(&authority), authority_lamports + rent);
+    let collection: Collection = env.fetch(&collection_key);
+    assert_eq!(collection.open_distributions, 0);
+
+    // Users may close their states of the closed distribution
+    env.run(
+        instructions::close_distribution_user_state(&distribution, &user),
+        &user,
+    )
+    .unwrap();
+}
+
+#[test]
+fn close_swept_distribution() {
+    let mut env = Env::new();
+    let collection = env.init_collection(0, 1000);
+    let expires_at = env.bank.now() + 100;
+    let distribution = env
+        .init_distribution_with(&collection, Some(expires_at), None)
+        .unwrap();
+    env.fund(&collection, 500).unwrap();
+    env.bank.warp(100);
+
+    let authority = env.authority;
+    let instructions = [
+        instructions::sweep_expired_distribution(
+            &collection,
+            0,
+            &env.reward_mint,
+            &authority,
+            &env.ata(&authority, &env.reward_mint),
+            &env.token_program,
+        ),
+        instructions::close_distribution(
+            &collection,
+            0,
+            &env.reward_mint,
+            &authority,
+            &env.token_program,
+        ),
+    ];
+    env.bank.process(&instructions, &[authority]).unwrap();
+
+    assert!(env.bank.account(&distribution).is_none());
+    assert_eq!(env.balance(&env.ata(&authority, &env.reward_mint)), 500);
+}
+
+#[test]
+fn close_distribution_with_transfer_fee() {
+    let mut env = Env::with_token_program(spl_token_2022::ID);
+    let authority = env.authority;
+    env.reward_mint = create_transfer_fee_mint(&mut env.bank, &authority, 100);
+    let collection = env.init_collection(0, 1000);
+    let user = env.new_user(100);
+    env.commit(&collection, &user, 100).unwrap();
+    env.run(
+        instructions::finalize_collection(&collection, &authority),
+        &authority,
+    )
+    .unwrap();
+    let distribution = env.init_distribution(&collection);
+    env.fund(&collection, 500).unwrap();
+    env.claim(&collection, &user).unwrap();
+
+    // The claim emptied the vault, which still holds the fee of the funding
+    let vault = env.ata(&distribution, &env.reward_mint);
+    assert_eq!(env.balance(&vault), 0);
+    assert_eq!(common::withheld_amount(&env.bank, &vault), 5);
+    env.run(
+        instructions::close_distribution(
+            &collection,
+            0,
+            &env.reward_mint,
+            &authority,
+            &env.token_program,
+        ),
+        &authority,
+    )
+    .unwrap();
+
+    assert!(env.bank.account(&distribution).is_none());
+    assert!(env.bank.account(&vault).is_none());
+    assert_eq!(common::mint_withheld_amount(&env.bank, &env.reward_mint), 5);
+}
@@ -0,0 +1,296 @@
+//! Randomized sequences of commits, fundings, max decreases, collection closing and
+//! claims, checking the share math and token conservation after every step.
+
+mod common;
+
+use anchor_lang::solana_program::pubkey::Pubkey;
+use common::Env;
+use multidistribute::{Collection, CollectionUserState, Distribution, DistributionUserState};
+use multidistribute_client::{instructions, math, pda};
+use proptest::prelude::*;
+
+const USERS: usize = 4;
+
+#[derive(Clone, Debug)]
+enum Op {
+    Commit {
+        user: usize,
+        amount: u64,
+    },
+    Fund {
+        amount: u64,
+    },
+    /// Decreases the maximum by `fraction` per mille of the uncollected tokens
+    DecreaseMax {
+        fraction: u64,
+    },
+    Finalize,
+    CloseCommits,
+    ClawBack,
+    Claim {
+        user: usize,
+    },
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        4 => (0..USERS, 1..=400_000u64).prop_map(|(user, amount)| Op::Commit { user, amount }),
+        3 => (1..=1_000_000_000_000u64).prop_map(|amount| Op::Fund { amount }),
+        1 => (0..=1000u64).prop_map(|fraction| Op::DecreaseMax { fraction }),
+        1 => Just(Op::Finalize),
+        1 => Just(Op::CloseCommits),
+        1 => Just(Op::ClawBack),
+        4 => (0..USERS).prop_map(|user| Op::Claim { user }),
+    ]
+}
+
+struct Harness {
+    env: Env,
+    collection: Pubkey,
+    distribution: Pubkey,
+    users: Vec<Pubkey>,
+    /// Expected deposit of every user
+    deposits: Vec<u64>,
+}
+
+impl Harness {
+    fn new(max_collectable_tokens: u64) -> Self {
+        let mut env = Env::new();
+        let collection = env.init_collection(0, max_collectable_tokens);
+        let distribution = env.init_distribution(&collection);
+        // Clawbacks go to the authority's token account
+        let (authority, reward_mint) = (env.authority, env.reward_mint);
+        env.mint_to(&reward_mint, &authority, 0);
+        let users = (0..USERS)
+            .map(|_| env.new_user(max_collectable_tokens))
+            .collect();
+        Self {
+            env,
+            collection,
+            distribution,
+            users,
+            deposits: vec![0; USERS],
+        }
+    }
+
+    fn collection(&self) -> Collection {
+        self.env.fetch(&self.collection)
+    }
+
+    fn distribution(&self) -> Distribution {
+        self.env.fetch(&self.distribution)
+    }
+
+    fn collection_user_state(&self, user: usize) -> CollectionUserState {
+        let key = pda::find_collection_user_state(&self.collection, &self.users[user]).0;
+        match self.env.bank.account(&key) {
+            Some(_) => self.env.fetch(&key),
+            None => CollectionUserState {
+                deposited_amount: 0,
+                received_distribution: false,
+            },
+        }
+    }
+
+    fn received_amount(&self, user: usize) -> Option<DistributionUserState> {
+        let key = pda::find_distribution_user_state(&self.distribution, &self.users[user]).0;
+        self.env.bank.account(&key).map(|_| self.env.fetch(&key))
+    }
+
+    fn claimed(&self, user: usize) -> u64 {
+        self.env
+            .balance(&self.env.ata(&self.users[user], &self.env.reward_mint))
+    }
+
+    fn apply(&mut self, op: &Op) {
+        let collection = self.collection();
+        let authority = self.env.authority;
+        match *op {
+            Op::Commit { user, amount } => {
+                let accepted = !collection.finalized
+                    && !collection.commits_closed
+                    && collection.lifetime_tokens_collected + amount
+                        <= collection.max_collectable_tokens;
+                let result = self.env.commit(&self.collection, &self.users[user], amount);
+                assert_eq!(result.is_ok(), accepted, "{op:?}: {result:?}");
+                if accepted {
+                    self.deposits[user] += amount;
+                }
+            }
+            Op::Fund { amount } => self.env.fund(&self.collection, amount).unwrap(),
+            Op::DecreaseMax { fraction } => {
+                let uncollected =
+                    collection.max_collectable_tokens - collection.lifetime_tokens_collected;
+                let max = collection.max_collectable_tokens - uncollected * fraction / 1000;
+                let accepted = !collection.finalized
+                    && !collection.commits_closed
+                    && max > 0
+                    && max < collection.max_collectable_tokens;
+                let result = self.env.run(
+                    instructions::decrease_collection_max_collectable_tokens(
+                        &self.collection,
+                        &authority,
+                        max,
+                    ),
+                    &authority,
+                );
+                assert_eq!(result.is_ok(), accepted, "{op:?}: {result:?}");
+            }
+            Op::Finalize => {
+                let accepted = !collection.finalized
+                    && !collection.commits_closed
+                    && collection.lifetime_tokens_collected > 0;
+                let result = self.env.run(
+                    instructions::finalize_collection(&self.collection, &authority),
+                    &authority,
+                );
+                assert_eq!(result.is_ok(), accepted, "{op:?}: {result:?}");
+            }
+            Op::CloseCommits => {
+                let accepted = !collection.finalized && !collection.commits_closed;
+                let result = self.env.run(
+                    instructions::close_collection_commits(&self.collection, &authority),
+                    &authority,
+                );
+                assert_eq!(result.is_ok(), accepted, "{op:?}: {result:?}");
+            }
+            Op::ClawBack => {
+                let result = self.env.run(
+                    instructions::clawback_distribution_remainder(
+                        &self.collection,
+                        0,
+                        &self.env.reward_mint,
+                        &authority,
+                        &self.env.ata(&authority, &self.env.reward_mint),
+                        &self.env.token_program,
+                    ),
+                    &authority,
+                );
+                assert_eq!(
+                    result.is_ok(),
+                    collection.commits_closed,
+                    "{op:?}: {result:?}"
+                );
+            }
+            Op::Claim { user } => {
+                // Only depositors have a user state to claim with
+                let key = pda::find_collection_user_state(&self.collection, &self.users[user]).0;
+                if self.env.bank.account(&key).is_none() {
+                    let result = self.env.claim(&self.collection, &self.users[user]);
+                    common::assert_error(
+                        result,
+                        anchor_lang::error::ErrorCode::AccountNotInitialized,
+                    );
+                    return self.check();
+                }
+                // The client's claim math predicts the payout
+                let expected = math::claimable_amount(
+                    &collection,
+                    &self.distribution(),
+                    &self.collection_user_state(user),
+                    self.received_amount(user).as_ref(),
+                    self.env.bank.now(),
+                )
+                .unwrap();
+                let before = self.claimed(user);
+                self.env.claim(&self.collection, &self.users[user]).unwrap();
+                assert_eq!(self.claimed(user) - before, expected, "{op:?}");
+            }
+        }
+        self.check();
+    }
+
+    fn check(&self) {
+        let collection = self.collection();
+        let distribution = self.distribution();
+
+        assert_eq!(
+            collection.lifetime_tokens_collected,
+            self.deposits.iter().sum::<u64>()
+        );
+        assert!(collection.lifetime_tokens_collected <= collection.max_collectable_tokens);
+        assert_eq!(
+            self.env.balance(&collection.vault),
+            collection.lifetime_tokens_collected
+        );
+
+        // Tokens are conserved
+        assert!(
+            distribution.distributed_tokens + distribution.clawed_back_tokens
+                <= distribution.lifetime_deposited_tokens
+        );
+        assert_eq!(
+            self.env.balance(&distribution.vault),
+            distribution.lifetime_deposited_tokens
+                - distribution.distributed_tokens
+                - distribution.clawed_back_tokens
+        );
+
+        let mut distributed = 0;
+        for user in 0..USERS {
+            assert_eq!(
+                self.collection_user_state(user).deposited_amount,
+                self.deposits[user]
+            );
+
+            // Nobody receives more than their pro-rata share
+            let share = math::user_share(
+                self.deposits[user],
+                distribution.lifetime_deposited_tokens,
+                collection.share_denominator(),
+            )
+            .unwrap();
+            let claimed = self.claimed(user);
+            assert!(claimed <= share, "user {user} claimed {claimed} of {share}");
+            let received = self
+                .received_amount(user)
+                .map_or(0, |state| state.received_amount);
+            assert_eq!(received, claimed);
+            distributed += claimed;
+        }
+        assert_eq!(distributed, distribution.distributed_tokens);
+
+        // What was clawed back never belongs to current depositors
+        let owed: u64 = (0..USERS)
+            .map(|user| {
+                math::user_share(
+                    self.deposits[user],
+                    distribution.lifetime_deposited_tokens,
+                    collection.share_denominator(),
+                )
+                .unwrap()
+            })
+            .sum();
+        assert!(owed <= distribution.lifetime_deposited_tokens - distribution.clawed_back_tokens);
+    }
+
+    /// Claims everything and checks that at most rounding dust stays behind once the
+    /// collection is finalized
+    fn drain(&mut self) {
+        for user in 0..USERS {
+            self.apply(&Op::Claim { user });
+        }
+        let collection = self.collection();
+        let distribution = self.distribution();
+        let left = self.env.balance(&distribution.vault);
+        if collection.finalized {
+            assert!(left < USERS as u64, "{left} tokens left after finalization");
+        }
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn share_math_and_conservation(
+        max_collectable_tokens in 1..=1_000_000u64,
+        ops in prop::collection::vec(op(), 1..40),
+    ) {
+        let mut harness = Harness::new(max_collectable_tokens);
+        for op in &ops {
+            harness.apply(op);
+        }
+        harness.drain();
+    }
+}
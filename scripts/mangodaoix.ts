@@ -26,6 +26,12 @@ async function main(): Promise<void> {
   const mngoMint = new PublicKey("MangoCzJ36AjZyKwVj3VnYU4GTonjfVEnJmvvWaxLac");
   const usdtMint = new PublicKey("Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB");
 
+  // Authority the program signs its event CPIs with
+  const [eventAuthority] = await PublicKey.findProgramAddress(
+    [Buffer.from("__event_authority")],
+    program.programId
+  );
+
   // Initialize collection
   console.log("Initializing collection...");
   const MAX_TOKENS = new anchor.BN("1117467621000000");
@@ -59,7 +65,7 @@ async function main(): Promise<void> {
   );
 
   const ix = await program.methods
-    .initCollection(COUNTER, MAX_TOKENS, true)
+    .initCollection(COUNTER, MAX_TOKENS, true, false, null, null, null, new anchor.BN(0), null, null, null)
     .accounts({
       collection,
       mint: mngoMint,
@@ -70,6 +76,8 @@ async function main(): Promise<void> {
       tokenProgram: TOKEN_PROGRAM_ID,
       associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
       rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      eventAuthority,
+      program: program.programId,
     })
     .instruction();
   console.log(await serializeInstructionToBase64(ix));
@@ -78,12 +86,16 @@ async function main(): Promise<void> {
   // Initialize distribution
   console.log("Initializing distribution...");
 
-  // Get distribution address
+  // Get distribution address, numbered by the collection's distribution count.
+  // The collection is only created when the proposal executes, so until then
+  // this is its first distribution.
+  const collectionAccount = await program.account.collection.fetchNullable(collection);
+  const distributionCount = collectionAccount?.distributionCount ?? new anchor.BN(0);
   const [distribution] = await PublicKey.findProgramAddress(
     [
       Buffer.from("distribution"),
       collection.toBuffer(),
-      usdtMint.toBuffer(),
+      distributionCount.toArrayLike(Buffer, "le", 8),
     ],
     program.programId
   );
@@ -96,7 +108,7 @@ async function main(): Promise<void> {
   );
 
   const ix2 = await program.methods
-    .initDistribution()
+    .initDistribution(null, null)
     .accounts({
       distribution,
       collection,
@@ -106,6 +118,8 @@ async function main(): Promise<void> {
       systemProgram: anchor.web3.SystemProgram.programId,
       tokenProgram: TOKEN_PROGRAM_ID,
       associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+      eventAuthority,
+      program: program.programId,
     })
     .instruction();
   console.log(await serializeInstructionToBase64(ix2));
@@ -116,8 +130,11 @@ async function main(): Promise<void> {
     .accounts({
       distribution,
       vault: distributionVault,
+      mint: usdtMint,
       authorityTokenAccount: new PublicKey("5mNvRxJsBU7zAGmEjjTSvW2xCrB9PSTnZ9ZpryVv4czT"),
       authority: daoWallet,
+      eventAuthority,
+      program: program.programId,
     })
     .instruction();
   console.log(await serializeInstructionToBase64(ix3));
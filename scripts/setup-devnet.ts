@@ -39,6 +39,12 @@ async function main() {
 
   console.log("Setting up with wallet:", wallet.publicKey.toString());
 
+  // Authority the program signs its event CPIs with
+  const [eventAuthority] = await PublicKey.findProgramAddress(
+    [Buffer.from("__event_authority")],
+    program.programId
+  );
+
   // Get or create collection mint
   let mint1: PublicKey;
   const collectionMintStr = process.env.COLLECTION_MINT;
@@ -144,7 +150,7 @@ async function main() {
   );
 
   await program.methods
-    .initCollection(COUNTER, MAX_TOKENS, false)
+    .initCollection(COUNTER, MAX_TOKENS, false, false, null, null, null, new anchor.BN(0), null, null, null)
     .accounts({
       collection,
       mint: mint1,
@@ -155,6 +161,8 @@ async function main() {
       tokenProgram: TOKEN_PROGRAM_ID,
       associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
       rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      eventAuthority,
+      program: program.programId,
     })
     .rpc();
 
@@ -163,12 +171,13 @@ async function main() {
   // Initialize distribution
   console.log("Initializing distribution...");
 
-  // Get distribution address
+  // Get distribution address, numbered by the collection's distribution count
+  const collectionAccount = await program.account.collection.fetch(collection);
   const [distribution] = await PublicKey.findProgramAddress(
     [
       Buffer.from("distribution"),
       collection.toBuffer(),
-      mint2.toBuffer(),
+      collectionAccount.distributionCount.toArrayLike(Buffer, "le", 8),
     ],
     program.programId
   );
@@ -181,7 +190,7 @@ async function main() {
   );
 
   await program.methods
-    .initDistribution()
+    .initDistribution(null, null)
     .accounts({
       distribution,
       collection,
@@ -191,6 +200,8 @@ async function main() {
       systemProgram: anchor.web3.SystemProgram.programId,
       tokenProgram: TOKEN_PROGRAM_ID,
       associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+      eventAuthority,
+      program: program.programId,
     })
     .rpc();
 
@@ -203,8 +214,11 @@ async function main() {
     .accounts({
       distribution,
       vault: distributionVault,
+      mint: mint2,
       authorityTokenAccount: ata2,
       authority: wallet.publicKey,
+      eventAuthority,
+      program: program.programId,
     })
     .rpc();
 
@@ -3,15 +3,23 @@ import { Program } from "@project-serum/anchor";
 import { Multidistribute } from "../target/types/multidistribute";
 import {
   TOKEN_PROGRAM_ID,
+  TOKEN_2022_PROGRAM_ID,
   ASSOCIATED_TOKEN_PROGRAM_ID,
+  ExtensionType,
   createMint,
   createAccount,
   mintTo,
   getAccount,
   getAssociatedTokenAddress,
   getMint,
+  getMintLen,
+  getOrCreateAssociatedTokenAccount,
+  transfer,
+  createInitializeMintInstruction,
+  createInitializeTransferFeeConfigInstruction,
 } from "@solana/spl-token";
-import { PublicKey } from "@solana/web3.js";
+import { PublicKey, SystemProgram, Transaction } from "@solana/web3.js";
+import { keccak_256 } from "@noble/hashes/sha3";
 import { assert } from "chai";
 
 describe("multidistribute", () => {
@@ -38,6 +46,7 @@ describe("multidistribute", () => {
   let distribution2Vault: PublicKey;
   let distribution1UserState: PublicKey;
   let distribution2UserState: PublicKey;
+  let eventAuthority: PublicKey;
 
   const user = anchor.web3.Keypair.generate();
   const authority = provider.wallet;
@@ -137,6 +146,11 @@ describe("multidistribute", () => {
     );
 
     // Derive PDAs
+    [eventAuthority] = await PublicKey.findProgramAddress(
+      [Buffer.from("__event_authority")],
+      program.programId
+    );
+
     [collection] = await PublicKey.findProgramAddress(
       [
         Buffer.from("collection"),
@@ -179,7 +193,7 @@ describe("multidistribute", () => {
       [
         Buffer.from("distribution"),
         collection.toBuffer(),
-        mint1.toBuffer(),
+        new anchor.BN(0).toArrayLike(Buffer, "le", 8),
       ],
       program.programId
     );
@@ -194,7 +208,7 @@ describe("multidistribute", () => {
       [
         Buffer.from("distribution"),
         collection.toBuffer(),
-        mint2.toBuffer(),
+        new anchor.BN(1).toArrayLike(Buffer, "le", 8),
       ],
       program.programId
     );
@@ -226,7 +240,7 @@ describe("multidistribute", () => {
 
   it("Creates a collection", async () => {
     await program.methods
-      .initCollection(COUNTER, MAX_TOKENS, false)
+      .initCollection(COUNTER, MAX_TOKENS, false, false, null, null, null, new anchor.BN(0), null, null, null)
       .accounts({
         collection,
         mint: mint1,
@@ -237,6 +251,8 @@ describe("multidistribute", () => {
         tokenProgram: TOKEN_PROGRAM_ID,
         associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
         rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        eventAuthority,
+        program: program.programId,
       })
       .rpc();
 
@@ -249,7 +265,7 @@ describe("multidistribute", () => {
   it("Creates distributions", async () => {
     // Create first distribution
     await program.methods
-      .initDistribution()
+      .initDistribution(null, null)
       .accounts({
         distribution: distribution1,
         collection,
@@ -259,6 +275,8 @@ describe("multidistribute", () => {
         systemProgram: anchor.web3.SystemProgram.programId,
         tokenProgram: TOKEN_PROGRAM_ID,
         associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        eventAuthority,
+        program: program.programId,
       })
       .rpc();
 
@@ -268,15 +286,18 @@ describe("multidistribute", () => {
       .accounts({
         distribution: distribution1,
         vault: distribution1Vault,
+        mint: mint1,
         authorityTokenAccount: authorityTokenAccount1,
         authority: authority.publicKey,
         tokenProgram: TOKEN_PROGRAM_ID,
+        eventAuthority,
+        program: program.programId,
       })
       .rpc();
 
     // Create second distribution
     await program.methods
-      .initDistribution()
+      .initDistribution(null, null)
       .accounts({
         distribution: distribution2,
         collection,
@@ -286,6 +307,8 @@ describe("multidistribute", () => {
         systemProgram: anchor.web3.SystemProgram.programId,
         tokenProgram: TOKEN_PROGRAM_ID,
         associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        eventAuthority,
+        program: program.programId,
       })
       .rpc();
 
@@ -295,9 +318,12 @@ describe("multidistribute", () => {
       .accounts({
         distribution: distribution2,
         vault: distribution2Vault,
+        mint: mint2,
         authorityTokenAccount: authorityTokenAccount2,
         authority: authority.publicKey,
         tokenProgram: TOKEN_PROGRAM_ID,
+        eventAuthority,
+        program: program.programId,
       })
       .rpc();
 
@@ -306,6 +332,11 @@ describe("multidistribute", () => {
     
     assert.equal(distribution1Account.lifetimeDepositedTokens.toString(), "100");
     assert.equal(distribution2Account.lifetimeDepositedTokens.toString(), "200");
+    assert.equal(distribution1Account.id.toString(), "0");
+    assert.equal(distribution2Account.id.toString(), "1");
+
+    const collectionAccount = await program.account.collection.fetch(collection);
+    assert.equal(collectionAccount.distributionCount.toString(), "2");
   });
 
   it("Deposits tokens and receives distributions", async () => {
@@ -321,9 +352,10 @@ describe("multidistribute", () => {
 
     // First commit to collection
     await program.methods
-      .userCommitToCollection(new anchor.BN(500))
+      .userCommitToCollection(new anchor.BN(500), null)
       .accounts({
         collection,
+        collectionMint: null,
         userState,
         mint: mint1,
         userTokenAccount: userTokenAccount1,
@@ -335,6 +367,8 @@ describe("multidistribute", () => {
         systemProgram: anchor.web3.SystemProgram.programId,
         associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
         rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        eventAuthority,
+        program: program.programId,
       })
       .signers([user])
       .rpc();
@@ -377,10 +411,13 @@ describe("multidistribute", () => {
         collectionUserState: userState,
         distributionUserState: distribution1UserState,
         distributionVault: distribution1Vault,
+        distributionMint: mint1,
         userTokenAccount: userTokenAccount1,
         user: user.publicKey,
         tokenProgram: TOKEN_PROGRAM_ID,
         systemProgram: anchor.web3.SystemProgram.programId,
+        eventAuthority,
+        program: program.programId,
       })
       .signers([user])
       .rpc();
@@ -422,9 +459,10 @@ describe("multidistribute", () => {
 
     // Second commit to collection
     await program.methods
-      .userCommitToCollection(new anchor.BN(300))
+      .userCommitToCollection(new anchor.BN(300), null)
       .accounts({
         collection,
+        collectionMint: null,
         userState,
         mint: mint1,
         userTokenAccount: userTokenAccount1,
@@ -436,6 +474,8 @@ describe("multidistribute", () => {
         systemProgram: anchor.web3.SystemProgram.programId,
         associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
         rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        eventAuthority,
+        program: program.programId,
       })
       .signers([user])
       .rpc();
@@ -478,10 +518,13 @@ describe("multidistribute", () => {
         collectionUserState: userState,
         distributionUserState: distribution2UserState,
         distributionVault: distribution2Vault,
+        distributionMint: mint2,
         userTokenAccount: userTokenAccount2,
         user: user.publicKey,
         tokenProgram: TOKEN_PROGRAM_ID,
         systemProgram: anchor.web3.SystemProgram.programId,
+        eventAuthority,
+        program: program.programId,
       })
       .signers([user])
       .rpc();
@@ -553,7 +596,7 @@ describe("multidistribute", () => {
     );
 
     await program.methods
-      .initCollection(new anchor.BN(2), MAX_TOKENS, true)
+      .initCollection(new anchor.BN(2), MAX_TOKENS, true, false, null, null, null, new anchor.BN(0), null, null, null)
       .accounts({
         collection: burnCollection,
         mint: mint1,
@@ -564,6 +607,8 @@ describe("multidistribute", () => {
         tokenProgram: TOKEN_PROGRAM_ID,
         associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
         rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        eventAuthority,
+        program: program.programId,
       })
       .rpc();
 
@@ -580,9 +625,10 @@ describe("multidistribute", () => {
 
     // Commit tokens to burn collection
     await program.methods
-      .userCommitToCollection(new anchor.BN(300))
+      .userCommitToCollection(new anchor.BN(300), null)
       .accounts({
         collection: burnCollection,
+        collectionMint: null,
         userState: burnUserState,
         mint: mint1,
         userTokenAccount: userTokenAccount1,
@@ -594,6 +640,8 @@ describe("multidistribute", () => {
         systemProgram: anchor.web3.SystemProgram.programId,
         associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
         rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        eventAuthority,
+        program: program.programId,
       })
       .signers([user])
       .rpc();
@@ -647,10 +695,14 @@ describe("multidistribute", () => {
       .withdrawFromCollection()
       .accounts({
         collection,
+        collectionMint: null,
         vault: collectionVault,
+        mint: mint1,
         authorityTokenAccount: authorityTokenAccount1,
         authority: authority.publicKey,
         tokenProgram: TOKEN_PROGRAM_ID,
+        eventAuthority,
+        program: program.programId,
       })
       .rpc();
 
@@ -671,4 +723,2103 @@ describe("multidistribute", () => {
       authorityAccount1BeforeWithdraw.amount + BigInt(800)
     );
   });
+
+  it("Finalizes the collection and pays out against the collected total", async () => {
+    await program.methods
+      .finalizeCollection()
+      .accounts({
+        collection,
+        authority: authority.publicKey,
+        eventAuthority,
+        program: program.programId,
+      })
+      .rpc();
+
+    const collectionAccount = await program.account.collection.fetch(collection);
+    assert.isTrue(collectionAccount.finalized);
+
+    const userAccount1BeforeClaim = await getAccount(
+      provider.connection,
+      userTokenAccount1
+    );
+
+    // The user holds all 800 collected tokens, so after finalization they are
+    // entitled to the whole of distribution1 (100), of which 50 were claimed
+    await program.methods
+      .userClaimFromDistribution()
+      .accounts({
+        collection,
+        distribution: distribution1,
+        collectionUserState: userState,
+        distributionUserState: distribution1UserState,
+        distributionVault: distribution1Vault,
+        distributionMint: mint1,
+        userTokenAccount: userTokenAccount1,
+        user: user.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        eventAuthority,
+        program: program.programId,
+      })
+      .signers([user])
+      .rpc();
+
+    const userAccount1AfterClaim = await getAccount(
+      provider.connection,
+      userTokenAccount1
+    );
+    assert.equal(
+      userAccount1BeforeClaim.amount + BigInt(50),
+      userAccount1AfterClaim.amount
+    );
+
+    const distribution1Account = await program.account.distribution.fetch(distribution1);
+    assert.equal(
+      distribution1Account.distributedTokens.toString(),
+      distribution1Account.lifetimeDepositedTokens.toString()
+    );
+
+    // Further commits are rejected
+    try {
+      await program.methods
+        .userCommitToCollection(new anchor.BN(100), null)
+        .accounts({
+          collection,
+          collectionMint: null,
+          userState,
+          mint: mint1,
+          userTokenAccount: userTokenAccount1,
+          vault: collectionVault,
+          replacementMint,
+          userReplacementTokenAccount,
+          user: user.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          eventAuthority,
+          program: program.programId,
+        })
+        .signers([user])
+        .rpc();
+      assert.fail("commit to a finalized collection should fail");
+    } catch (err) {
+      assert.equal((err as anchor.AnchorError).error.errorCode.code, "CollectionFinalized");
+    }
+  });
+
+  it("Claws back the unclaimable remainder of a closed collection", async () => {
+    // The burn collection holds 300 of its 1000 maximum tokens
+    const [burnCollection] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from("collection"),
+        authority.publicKey.toBuffer(),
+        mint1.toBuffer(),
+        new anchor.BN(2).toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+
+    const [burnDistribution] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from("distribution"),
+        burnCollection.toBuffer(),
+        new anchor.BN(0).toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+
+    const burnDistributionVault = await getAssociatedTokenAddress(
+      mint2,
+      burnDistribution,
+      true
+    );
+
+    await program.methods
+      .initDistribution(null, null)
+      .accounts({
+        distribution: burnDistribution,
+        collection: burnCollection,
+        mint: mint2,
+        vault: burnDistributionVault,
+        authority: authority.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        eventAuthority,
+        program: program.programId,
+      })
+      .rpc();
+
+    await program.methods
+      .addDistributionTokens(new anchor.BN(100))
+      .accounts({
+        distribution: burnDistribution,
+        vault: burnDistributionVault,
+        mint: mint2,
+        authorityTokenAccount: authorityTokenAccount2,
+        authority: authority.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        eventAuthority,
+        program: program.programId,
+      })
+      .rpc();
+
+    // Nothing can be clawed back while commits are still possible
+    try {
+      await program.methods
+        .clawbackDistributionRemainder()
+        .accounts({
+          collection: burnCollection,
+          distribution: burnDistribution,
+          vault: burnDistributionVault,
+          mint: mint2,
+          authorityTokenAccount: authorityTokenAccount2,
+          authority: authority.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          eventAuthority,
+          program: program.programId,
+        })
+        .rpc();
+      assert.fail("clawback from an open collection should fail");
+    } catch (err) {
+      assert.equal((err as anchor.AnchorError).error.errorCode.code, "CommitsNotClosed");
+    }
+
+    await program.methods
+      .closeCollectionCommits()
+      .accounts({
+        collection: burnCollection,
+        authority: authority.publicKey,
+        eventAuthority,
+        program: program.programId,
+      })
+      .rpc();
+
+    const authorityAccount2BeforeClawback = await getAccount(
+      provider.connection,
+      authorityTokenAccount2
+    );
+
+    await program.methods
+      .clawbackDistributionRemainder()
+      .accounts({
+        collection: burnCollection,
+        distribution: burnDistribution,
+        vault: burnDistributionVault,
+        mint: mint2,
+        authorityTokenAccount: authorityTokenAccount2,
+        authority: authority.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        eventAuthority,
+        program: program.programId,
+      })
+      .rpc();
+
+    // Depositors can claim at most 300 * 100 / 1000 = 30, the other 70 are returned
+    const authorityAccount2AfterClawback = await getAccount(
+      provider.connection,
+      authorityTokenAccount2
+    );
+    const burnDistributionVaultAfterClawback = await getAccount(
+      provider.connection,
+      burnDistributionVault
+    );
+    assert.equal(
+      authorityAccount2BeforeClawback.amount + BigInt(70),
+      authorityAccount2AfterClawback.amount
+    );
+    assert.equal(burnDistributionVaultAfterClawback.amount, BigInt(30));
+
+    const burnDistributionAccount = await program.account.distribution.fetch(burnDistribution);
+    assert.equal(burnDistributionAccount.clawedBackTokens.toString(), "70");
+  });
+
+  it("Rejects claims after expiry and sweeps the distribution", async () => {
+    // A fresh collection, so the distribution PDA for mint2 is free
+    const expiringCounter = new anchor.BN(3);
+    const [expiringCollection] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from("collection"),
+        authority.publicKey.toBuffer(),
+        mint1.toBuffer(),
+        expiringCounter.toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    const [expiringReplacementMint] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from("replacement_mint"),
+        expiringCollection.toBuffer(),
+      ],
+      program.programId
+    );
+    const [expiringUserState] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from("user_state"),
+        expiringCollection.toBuffer(),
+        user.publicKey.toBuffer(),
+      ],
+      program.programId
+    );
+    const [expiringDistribution] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from("distribution"),
+        expiringCollection.toBuffer(),
+        new anchor.BN(0).toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    const [expiringDistributionUserState] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from("distribution_user_state"),
+        expiringDistribution.toBuffer(),
+        user.publicKey.toBuffer(),
+      ],
+      program.programId
+    );
+    const expiringDistributionVault = await getAssociatedTokenAddress(
+      mint2,
+      expiringDistribution,
+      true
+    );
+
+    await program.methods
+      .initCollection(expiringCounter, MAX_TOKENS, false, false, null, null, null, new anchor.BN(0), null, null, null)
+      .accounts({
+        collection: expiringCollection,
+        mint: mint1,
+        vault: await getAssociatedTokenAddress(mint1, expiringCollection, true),
+        replacementMint: expiringReplacementMint,
+        authority: authority.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        eventAuthority,
+        program: program.programId,
+      })
+      .rpc();
+
+    await program.methods
+      .userCommitToCollection(new anchor.BN(100), null)
+      .accounts({
+        collection: expiringCollection,
+        collectionMint: null,
+        userState: expiringUserState,
+        mint: mint1,
+        userTokenAccount: userTokenAccount1,
+        vault: await getAssociatedTokenAddress(mint1, expiringCollection, true),
+        replacementMint: expiringReplacementMint,
+        userReplacementTokenAccount: await getAssociatedTokenAddress(
+          expiringReplacementMint,
+          user.publicKey
+        ),
+        user: user.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        eventAuthority,
+        program: program.programId,
+      })
+      .signers([user])
+      .rpc();
+
+    const expiresAt = new anchor.BN(Math.floor(Date.now() / 1000) + 5);
+    await program.methods
+      .initDistribution(expiresAt, null)
+      .accounts({
+        distribution: expiringDistribution,
+        collection: expiringCollection,
+        mint: mint2,
+        vault: expiringDistributionVault,
+        authority: authority.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        eventAuthority,
+        program: program.programId,
+      })
+      .rpc();
+
+    await program.methods
+      .addDistributionTokens(new anchor.BN(100))
+      .accounts({
+        distribution: expiringDistribution,
+        vault: expiringDistributionVault,
+        mint: mint2,
+        authorityTokenAccount: authorityTokenAccount2,
+        authority: authority.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        eventAuthority,
+        program: program.programId,
+      })
+      .rpc();
+
+    // Wait until the validator clock passes the expiry
+    await new Promise((resolve) => setTimeout(resolve, 8000));
+
+    try {
+      await program.methods
+        .userClaimFromDistribution()
+        .accounts({
+          collection: expiringCollection,
+          distribution: expiringDistribution,
+          collectionUserState: expiringUserState,
+          distributionUserState: expiringDistributionUserState,
+          distributionVault: expiringDistributionVault,
+          distributionMint: mint2,
+          userTokenAccount: userTokenAccount2,
+          user: user.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          eventAuthority,
+          program: program.programId,
+        })
+        .signers([user])
+        .rpc();
+      assert.fail("claim from an expired distribution should fail");
+    } catch (err) {
+      assert.equal((err as anchor.AnchorError).error.errorCode.code, "DistributionExpired");
+    }
+
+    const authorityAccount2BeforeSweep = await getAccount(
+      provider.connection,
+      authorityTokenAccount2
+    );
+
+    await program.methods
+      .sweepExpiredDistribution()
+      .accounts({
+        collection: expiringCollection,
+        distribution: expiringDistribution,
+        vault: expiringDistributionVault,
+        mint: mint2,
+        authorityTokenAccount: authorityTokenAccount2,
+        authority: authority.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        eventAuthority,
+        program: program.programId,
+      })
+      .rpc();
+
+    const authorityAccount2AfterSweep = await getAccount(
+      provider.connection,
+      authorityTokenAccount2
+    );
+    assert.equal(
+      authorityAccount2BeforeSweep.amount + BigInt(100),
+      authorityAccount2AfterSweep.amount
+    );
+    assert.isNull(await provider.connection.getAccountInfo(expiringDistributionVault));
+
+    const expiringDistributionAccount = await program.account.distribution.fetch(
+      expiringDistribution
+    );
+    assert.isTrue(expiringDistributionAccount.swept);
+    assert.equal(expiringDistributionAccount.sweptTokens.toString(), "100");
+
+    // Wind the collection down
+    await program.methods
+      .closeDistribution()
+      .accounts({
+        collection: expiringCollection,
+        distribution: expiringDistribution,
+        vault: expiringDistributionVault,
+        mint: mint2,
+        authority: authority.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        eventAuthority,
+        program: program.programId,
+      })
+      .rpc();
+    assert.isNull(await provider.connection.getAccountInfo(expiringDistribution));
+
+    const expiringVault = await getAssociatedTokenAddress(mint1, expiringCollection, true);
+    await program.methods
+      .withdrawFromCollection()
+      .accounts({
+        collection: expiringCollection,
+        collectionMint: null,
+        vault: expiringVault,
+        mint: mint1,
+        authorityTokenAccount: authorityTokenAccount1,
+        authority: authority.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        eventAuthority,
+        program: program.programId,
+      })
+      .rpc();
+    await program.methods
+      .closeCollection()
+      .accounts({
+        collection: expiringCollection,
+        vault: expiringVault,
+        mint: mint1,
+        replacementMint: expiringReplacementMint,
+        authority: authority.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        eventAuthority,
+        program: program.programId,
+      })
+      .rpc();
+    assert.isNull(await provider.connection.getAccountInfo(expiringCollection));
+    assert.isNull(await provider.connection.getAccountInfo(expiringVault));
+  });
+
+  it("Pays nothing from a vesting distribution before its cliff", async () => {
+    // Reuse the collection from the expiry test, where the user holds 100 tokens
+    const [vestingCollection] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from("collection"),
+        authority.publicKey.toBuffer(),
+        mint1.toBuffer(),
+        new anchor.BN(3).toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    const [vestingUserState] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from("user_state"),
+        vestingCollection.toBuffer(),
+        user.publicKey.toBuffer(),
+      ],
+      program.programId
+    );
+    const [vestingDistribution] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from("distribution"),
+        vestingCollection.toBuffer(),
+        new anchor.BN(0).toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    const [vestingDistributionUserState] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from("distribution_user_state"),
+        vestingDistribution.toBuffer(),
+        user.publicKey.toBuffer(),
+      ],
+      program.programId
+    );
+    const vestingDistributionVault = await getAssociatedTokenAddress(
+      mint1,
+      vestingDistribution,
+      true
+    );
+
+    const now = Math.floor(Date.now() / 1000);
+    await program.methods
+      .initDistribution(null, {
+        startTs: new anchor.BN(now),
+        cliffTs: new anchor.BN(now + 3600),
+        endTs: new anchor.BN(now + 7200),
+      })
+      .accounts({
+        distribution: vestingDistribution,
+        collection: vestingCollection,
+        mint: mint1,
+        vault: vestingDistributionVault,
+        authority: authority.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        eventAuthority,
+        program: program.programId,
+      })
+      .rpc();
+
+    await program.methods
+      .addDistributionTokens(new anchor.BN(100))
+      .accounts({
+        distribution: vestingDistribution,
+        vault: vestingDistributionVault,
+        mint: mint1,
+        authorityTokenAccount: authorityTokenAccount1,
+        authority: authority.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        eventAuthority,
+        program: program.programId,
+      })
+      .rpc();
+
+    await program.methods
+      .userClaimFromDistribution()
+      .accounts({
+        collection: vestingCollection,
+        distribution: vestingDistribution,
+        collectionUserState: vestingUserState,
+        distributionUserState: vestingDistributionUserState,
+        distributionVault: vestingDistributionVault,
+        distributionMint: mint1,
+        userTokenAccount: userTokenAccount1,
+        user: user.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        eventAuthority,
+        program: program.programId,
+      })
+      .signers([user])
+      .rpc();
+
+    const vestingDistributionUserStateAccount = await program.account.distributionUserState.fetch(
+      vestingDistributionUserState
+    );
+    assert.equal(vestingDistributionUserStateAccount.receivedAmount.toString(), "0");
+  });
+
+  it("Entitles the holder of replacement tokens when claims are transferable", async () => {
+    const transferableCounter = new anchor.BN(4);
+    const [transferableCollection] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from("collection"),
+        authority.publicKey.toBuffer(),
+        mint1.toBuffer(),
+        transferableCounter.toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    const transferableVault = await getAssociatedTokenAddress(
+      mint1,
+      transferableCollection,
+      true
+    );
+    const [transferableReplacementMint] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from("replacement_mint"),
+        transferableCollection.toBuffer(),
+      ],
+      program.programId
+    );
+    const [transferableUserState] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from("user_state"),
+        transferableCollection.toBuffer(),
+        user.publicKey.toBuffer(),
+      ],
+      program.programId
+    );
+    const [transferableAuthorityState] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from("user_state"),
+        transferableCollection.toBuffer(),
+        authority.publicKey.toBuffer(),
+      ],
+      program.programId
+    );
+    const userTransferableReplacementTokenAccount = await getAssociatedTokenAddress(
+      transferableReplacementMint,
+      user.publicKey
+    );
+
+    await program.methods
+      .initCollection(transferableCounter, MAX_TOKENS, false, true, null, null, null, new anchor.BN(0), null, null, null)
+      .accounts({
+        collection: transferableCollection,
+        mint: mint1,
+        vault: transferableVault,
+        replacementMint: transferableReplacementMint,
+        authority: authority.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        eventAuthority,
+        program: program.programId,
+      })
+      .rpc();
+
+    await program.methods
+      .userCommitToCollection(new anchor.BN(100), null)
+      .accounts({
+        collection: transferableCollection,
+        collectionMint: null,
+        userState: transferableUserState,
+        mint: mint1,
+        userTokenAccount: userTokenAccount1,
+        vault: transferableVault,
+        replacementMint: transferableReplacementMint,
+        userReplacementTokenAccount: userTransferableReplacementTokenAccount,
+        user: user.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        eventAuthority,
+        program: program.programId,
+      })
+      .signers([user])
+      .rpc();
+
+    // The deposit is carried by the replacement tokens, not the depositor
+    let userStateAccount = await program.account.collectionUserState.fetch(
+      transferableUserState
+    );
+    assert.equal(userStateAccount.depositedAmount.toString(), "0");
+
+    // Sell 40 replacement tokens to the authority
+    const authorityTransferableReplacementTokenAccount = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      authority.payer,
+      transferableReplacementMint,
+      authority.publicKey
+    );
+    await transfer(
+      provider.connection,
+      authority.payer,
+      userTransferableReplacementTokenAccount,
+      authorityTransferableReplacementTokenAccount.address,
+      user,
+      40
+    );
+
+    await program.methods
+      .redeemReplacementTokens(new anchor.BN(60))
+      .accounts({
+        collection: transferableCollection,
+        userState: transferableUserState,
+        replacementMint: transferableReplacementMint,
+        userReplacementTokenAccount: userTransferableReplacementTokenAccount,
+        user: user.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        eventAuthority,
+        program: program.programId,
+      })
+      .signers([user])
+      .rpc();
+
+    await program.methods
+      .redeemReplacementTokens(new anchor.BN(40))
+      .accounts({
+        collection: transferableCollection,
+        userState: transferableAuthorityState,
+        replacementMint: transferableReplacementMint,
+        userReplacementTokenAccount: authorityTransferableReplacementTokenAccount.address,
+        user: authority.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        eventAuthority,
+        program: program.programId,
+      })
+      .rpc();
+
+    userStateAccount = await program.account.collectionUserState.fetch(transferableUserState);
+    const authorityStateAccount = await program.account.collectionUserState.fetch(
+      transferableAuthorityState
+    );
+    assert.equal(userStateAccount.depositedAmount.toString(), "60");
+    assert.equal(authorityStateAccount.depositedAmount.toString(), "40");
+
+    const replacementSupply = (await getMint(provider.connection, transferableReplacementMint)).supply;
+    assert.equal(replacementSupply, BigInt(0));
+  });
+
+  it("Refunds commits during the refund window", async () => {
+    const refundCounter = new anchor.BN(5);
+    const [refundCollection] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from("collection"),
+        authority.publicKey.toBuffer(),
+        mint1.toBuffer(),
+        refundCounter.toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    const refundVault = await getAssociatedTokenAddress(mint1, refundCollection, true);
+    const [refundReplacementMint] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from("replacement_mint"),
+        refundCollection.toBuffer(),
+      ],
+      program.programId
+    );
+    const [refundUserState] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from("user_state"),
+        refundCollection.toBuffer(),
+        user.publicKey.toBuffer(),
+      ],
+      program.programId
+    );
+    const userRefundReplacementTokenAccount = await getAssociatedTokenAddress(
+      refundReplacementMint,
+      user.publicKey
+    );
+
+    const refundDeadline = new anchor.BN(Math.floor(Date.now() / 1000) + 3600);
+    await program.methods
+      .initCollection(refundCounter, MAX_TOKENS, false, false, refundDeadline, null, null, new anchor.BN(0), null, null, null)
+      .accounts({
+        collection: refundCollection,
+        mint: mint1,
+        vault: refundVault,
+        replacementMint: refundReplacementMint,
+        authority: authority.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        eventAuthority,
+        program: program.programId,
+      })
+      .rpc();
+
+    await program.methods
+      .userCommitToCollection(new anchor.BN(100), null)
+      .accounts({
+        collection: refundCollection,
+        collectionMint: null,
+        userState: refundUserState,
+        mint: mint1,
+        userTokenAccount: userTokenAccount1,
+        vault: refundVault,
+        replacementMint: refundReplacementMint,
+        userReplacementTokenAccount: userRefundReplacementTokenAccount,
+        user: user.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        eventAuthority,
+        program: program.programId,
+      })
+      .signers([user])
+      .rpc();
+
+    const userAccount1BeforeRefund = await getAccount(
+      provider.connection,
+      userTokenAccount1
+    );
+
+    await program.methods
+      .userRefundFromCollection(new anchor.BN(40))
+      .accounts({
+        collection: refundCollection,
+        userState: refundUserState,
+        userTokenAccount: userTokenAccount1,
+        vault: refundVault,
+        mint: mint1,
+        replacementMint: refundReplacementMint,
+        userReplacementTokenAccount: userRefundReplacementTokenAccount,
+        user: user.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        eventAuthority,
+        program: program.programId,
+      })
+      .signers([user])
+      .rpc();
+
+    const userAccount1AfterRefund = await getAccount(
+      provider.connection,
+      userTokenAccount1
+    );
+    assert.equal(
+      userAccount1BeforeRefund.amount + BigInt(40),
+      userAccount1AfterRefund.amount
+    );
+
+    const refundCollectionAccount = await program.account.collection.fetch(refundCollection);
+    const refundUserStateAccount = await program.account.collectionUserState.fetch(
+      refundUserState
+    );
+    assert.equal(refundCollectionAccount.lifetimeTokensCollected.toString(), "60");
+    assert.equal(refundUserStateAccount.depositedAmount.toString(), "60");
+    assert.equal(
+      (await getAccount(provider.connection, userRefundReplacementTokenAccount)).amount,
+      BigInt(60)
+    );
+
+    // The vault must stay available for refunds while the window is open
+    try {
+      await program.methods
+        .withdrawFromCollection()
+        .accounts({
+          collection: refundCollection,
+          collectionMint: null,
+          vault: refundVault,
+          mint: mint1,
+          authorityTokenAccount: authorityTokenAccount1,
+          authority: authority.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          eventAuthority,
+          program: program.programId,
+        })
+        .rpc();
+      assert.fail("withdrawal during the refund window should fail");
+    } catch (err) {
+      assert.equal((err as anchor.AnchorError).error.errorCode.code, "RefundWindowOpen");
+    }
+  });
+
+  it("Only accepts commits from allowlisted users", async () => {
+    // Allowlist leaves are keccak(user || max amount), nodes hash their children sorted
+    const leaf = (key: PublicKey, maxAmount: number) =>
+      Buffer.from(
+        keccak_256(
+          Buffer.concat([key.toBuffer(), new anchor.BN(maxAmount).toArrayLike(Buffer, "le", 8)])
+        )
+      );
+    const userLeaf = leaf(user.publicKey, 50);
+    const authorityLeaf = leaf(authority.publicKey, 1000);
+    const allowlistRoot = Array.from(
+      keccak_256(Buffer.concat([userLeaf, authorityLeaf].sort(Buffer.compare)))
+    );
+    const userProof = { maxAmount: new anchor.BN(50), proof: [Array.from(authorityLeaf)] };
+
+    const allowlistCounter = new anchor.BN(7);
+    const [allowlistCollection] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from("collection"),
+        authority.publicKey.toBuffer(),
+        mint1.toBuffer(),
+        allowlistCounter.toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    const allowlistVault = await getAssociatedTokenAddress(mint1, allowlistCollection, true);
+    const [allowlistReplacementMint] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from("replacement_mint"),
+        allowlistCollection.toBuffer(),
+      ],
+      program.programId
+    );
+    const [allowlistUserState] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from("user_state"),
+        allowlistCollection.toBuffer(),
+        user.publicKey.toBuffer(),
+      ],
+      program.programId
+    );
+
+    const userAllowlistReplacementTokenAccount = await getAssociatedTokenAddress(
+      allowlistReplacementMint,
+      user.publicKey
+    );
+
+    await program.methods
+      .initCollection(allowlistCounter, MAX_TOKENS, false, false, null, allowlistRoot, null, new anchor.BN(0), null, null, null)
+      .accounts({
+        collection: allowlistCollection,
+        mint: mint1,
+        vault: allowlistVault,
+        replacementMint: allowlistReplacementMint,
+        authority: authority.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        eventAuthority,
+        program: program.programId,
+      })
+      .rpc();
+
+    const commit = (amount: number, proof: typeof userProof | null) =>
+      program.methods
+        .userCommitToCollection(new anchor.BN(amount), proof)
+        .accounts({
+          collection: allowlistCollection,
+          collectionMint: null,
+          userState: allowlistUserState,
+          mint: mint1,
+          userTokenAccount: userTokenAccount1,
+          vault: allowlistVault,
+          replacementMint: allowlistReplacementMint,
+          userReplacementTokenAccount: userAllowlistReplacementTokenAccount,
+          user: user.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          eventAuthority,
+          program: program.programId,
+        })
+        .signers([user])
+        .rpc();
+
+    try {
+      await commit(30, null);
+      assert.fail("commit without a proof should fail");
+    } catch (err) {
+      assert.equal((err as anchor.AnchorError).error.errorCode.code, "NotAllowlisted");
+    }
+
+    // The user may commit up to the allowlisted 50 tokens in total
+    await commit(30, userProof);
+    try {
+      await commit(30, userProof);
+      assert.fail("commit beyond the allowlisted amount should fail");
+    } catch (err) {
+      assert.equal(
+        (err as anchor.AnchorError).error.errorCode.code,
+        "AllowlistAmountExceeded"
+      );
+    }
+
+    // Without the allowlist anyone can commit any amount
+    await program.methods
+      .setCollectionAllowlist(null)
+      .accounts({
+        collection: allowlistCollection,
+        authority: authority.publicKey,
+        eventAuthority,
+        program: program.programId,
+      })
+      .rpc();
+    await commit(30, null);
+
+    const allowlistUserStateAccount = await program.account.collectionUserState.fetch(
+      allowlistUserState
+    );
+    assert.equal(allowlistUserStateAccount.depositedAmount.toString(), "60");
+  });
+
+  it("Enforces the per-user cap and minimum commit", async () => {
+    const limitedCounter = new anchor.BN(8);
+    const [limitedCollection] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from("collection"),
+        authority.publicKey.toBuffer(),
+        mint1.toBuffer(),
+        limitedCounter.toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    const limitedVault = await getAssociatedTokenAddress(mint1, limitedCollection, true);
+    const [limitedReplacementMint] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from("replacement_mint"),
+        limitedCollection.toBuffer(),
+      ],
+      program.programId
+    );
+    const [limitedUserState] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from("user_state"),
+        limitedCollection.toBuffer(),
+        user.publicKey.toBuffer(),
+      ],
+      program.programId
+    );
+    const userLimitedReplacementTokenAccount = await getAssociatedTokenAddress(
+      limitedReplacementMint,
+      user.publicKey
+    );
+
+    await program.methods
+      .initCollection(
+        limitedCounter,
+        MAX_TOKENS,
+        false,
+        false,
+        null,
+        null,
+        new anchor.BN(50),
+        new anchor.BN(10),
+        null,
+        null,
+        null
+      )
+      .accounts({
+        collection: limitedCollection,
+        mint: mint1,
+        vault: limitedVault,
+        replacementMint: limitedReplacementMint,
+        authority: authority.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        eventAuthority,
+        program: program.programId,
+      })
+      .rpc();
+
+    const commit = (amount: number) =>
+      program.methods
+        .userCommitToCollection(new anchor.BN(amount), null)
+        .accounts({
+          collection: limitedCollection,
+          collectionMint: null,
+          userState: limitedUserState,
+          mint: mint1,
+          userTokenAccount: userTokenAccount1,
+          vault: limitedVault,
+          replacementMint: limitedReplacementMint,
+          userReplacementTokenAccount: userLimitedReplacementTokenAccount,
+          user: user.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          eventAuthority,
+          program: program.programId,
+        })
+        .signers([user])
+        .rpc();
+
+    try {
+      await commit(5);
+      assert.fail("commit below the minimum should fail");
+    } catch (err) {
+      assert.equal((err as anchor.AnchorError).error.errorCode.code, "CommitBelowMinimum");
+    }
+
+    await commit(40);
+    try {
+      await commit(20);
+      assert.fail("commit beyond the per-user cap should fail");
+    } catch (err) {
+      assert.equal((err as anchor.AnchorError).error.errorCode.code, "MaxPerUserExceeded");
+    }
+
+    // Loosening the minimum lets the user fill the rest of their cap
+    await program.methods
+      .decreaseCollectionMinCommit(new anchor.BN(0))
+      .accounts({
+        collection: limitedCollection,
+        authority: authority.publicKey,
+        eventAuthority,
+        program: program.programId,
+      })
+      .rpc();
+    await commit(10);
+
+    const limitedUserStateAccount = await program.account.collectionUserState.fetch(
+      limitedUserState
+    );
+    assert.equal(limitedUserStateAccount.depositedAmount.toString(), "50");
+  });
+
+  it("Only accepts commits within the commit window", async () => {
+    const windowCounter = new anchor.BN(9);
+    const [windowCollection] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from("collection"),
+        authority.publicKey.toBuffer(),
+        mint1.toBuffer(),
+        windowCounter.toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    const windowVault = await getAssociatedTokenAddress(mint1, windowCollection, true);
+    const [windowReplacementMint] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from("replacement_mint"),
+        windowCollection.toBuffer(),
+      ],
+      program.programId
+    );
+    const [windowUserState] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from("user_state"),
+        windowCollection.toBuffer(),
+        user.publicKey.toBuffer(),
+      ],
+      program.programId
+    );
+
+    const commitEnd = new anchor.BN(Math.floor(Date.now() / 1000) + 3600);
+    await program.methods
+      .initCollection(
+        windowCounter,
+        MAX_TOKENS,
+        false,
+        false,
+        null,
+        null,
+        null,
+        new anchor.BN(0),
+        null,
+        commitEnd,
+        null
+      )
+      .accounts({
+        collection: windowCollection,
+        mint: mint1,
+        vault: windowVault,
+        replacementMint: windowReplacementMint,
+        authority: authority.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        eventAuthority,
+        program: program.programId,
+      })
+      .rpc();
+
+    const commit = async () =>
+      program.methods
+        .userCommitToCollection(new anchor.BN(10), null)
+        .accounts({
+          collection: windowCollection,
+          collectionMint: null,
+          userState: windowUserState,
+          mint: mint1,
+          userTokenAccount: userTokenAccount1,
+          vault: windowVault,
+          replacementMint: windowReplacementMint,
+          userReplacementTokenAccount: await getAssociatedTokenAddress(
+            windowReplacementMint,
+            user.publicKey
+          ),
+          user: user.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          eventAuthority,
+          program: program.programId,
+        })
+        .signers([user])
+        .rpc();
+
+    await commit();
+
+    // A past end closes the window right away
+    await program.methods
+      .setCollectionCommitEnd(new anchor.BN(0))
+      .accounts({
+        collection: windowCollection,
+        authority: authority.publicKey,
+        eventAuthority,
+        program: program.programId,
+      })
+      .rpc();
+    try {
+      await commit();
+      assert.fail("commit after the window should fail");
+    } catch (err) {
+      assert.equal((err as anchor.AnchorError).error.errorCode.code, "CommitWindowEnded");
+    }
+
+    const windowCollectionAccount = await program.account.collection.fetch(windowCollection);
+    assert.equal(windowCollectionAccount.lifetimeTokensCollected.toString(), "10");
+    assert.isTrue(windowCollectionAccount.commitEnd.toNumber() < commitEnd.toNumber());
+  });
+
+  it("Credits added mints in weighted units", async () => {
+    const weightedCounter = new anchor.BN(10);
+    const [weightedCollection] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from("collection"),
+        authority.publicKey.toBuffer(),
+        mint1.toBuffer(),
+        weightedCounter.toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    const [weightedReplacementMint] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from("replacement_mint"),
+        weightedCollection.toBuffer(),
+      ],
+      program.programId
+    );
+    const [weightedUserState] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from("user_state"),
+        weightedCollection.toBuffer(),
+        user.publicKey.toBuffer(),
+      ],
+      program.programId
+    );
+    const [collectionMint] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from("collection_mint"),
+        weightedCollection.toBuffer(),
+        mint2.toBuffer(),
+      ],
+      program.programId
+    );
+    const addedVault = await getAssociatedTokenAddress(mint2, weightedCollection, true);
+
+    await program.methods
+      .initCollection(
+        weightedCounter,
+        MAX_TOKENS,
+        false,
+        false,
+        null,
+        null,
+        null,
+        new anchor.BN(0),
+        null,
+        null,
+        null
+      )
+      .accounts({
+        collection: weightedCollection,
+        mint: mint1,
+        vault: await getAssociatedTokenAddress(mint1, weightedCollection, true),
+        replacementMint: weightedReplacementMint,
+        authority: authority.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        eventAuthority,
+        program: program.programId,
+      })
+      .rpc();
+
+    // Every token of mint2 is worth 1.5 tokens of mint1
+    await program.methods
+      .addCollectionMint(new anchor.BN(3), new anchor.BN(2))
+      .accounts({
+        collection: weightedCollection,
+        collectionMint,
+        mint: mint2,
+        vault: addedVault,
+        replacementMint: weightedReplacementMint,
+        authority: authority.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        eventAuthority,
+        program: program.programId,
+      })
+      .rpc();
+
+    await mintTo(
+      provider.connection,
+      authority.payer,
+      mint2,
+      userTokenAccount2,
+      authority.publicKey,
+      100
+    );
+    const userReplacement = await getAssociatedTokenAddress(
+      weightedReplacementMint,
+      user.publicKey
+    );
+    await program.methods
+      .userCommitToCollection(new anchor.BN(100), null)
+      .accounts({
+        collection: weightedCollection,
+        collectionMint,
+        userState: weightedUserState,
+        mint: mint2,
+        userTokenAccount: userTokenAccount2,
+        vault: addedVault,
+        replacementMint: weightedReplacementMint,
+        userReplacementTokenAccount: userReplacement,
+        user: user.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        eventAuthority,
+        program: program.programId,
+      })
+      .signers([user])
+      .rpc();
+
+    const weightedUserStateAccount = await program.account.collectionUserState.fetch(
+      weightedUserState
+    );
+    assert.equal(weightedUserStateAccount.depositedAmount.toString(), "150");
+    const weightedCollectionAccount = await program.account.collection.fetch(
+      weightedCollection
+    );
+    assert.equal(weightedCollectionAccount.lifetimeTokensCollected.toString(), "150");
+    assert.equal(weightedCollectionAccount.openCollectionMints.toString(), "1");
+    const replacementAccount = await getAccount(provider.connection, userReplacement);
+    assert.equal(replacementAccount.amount.toString(), "150");
+    const addedVaultAccount = await getAccount(provider.connection, addedVault);
+    assert.equal(addedVaultAccount.amount.toString(), "100");
+    const collectionMintAccount = await program.account.collectionMint.fetch(collectionMint);
+    assert.equal(collectionMintAccount.lifetimeTokensCollected.toString(), "100");
+  });
+
+  it("Credits early commits with the commit bonus", async () => {
+    const bonusCounter = new anchor.BN(11);
+    const [bonusCollection] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from("collection"),
+        authority.publicKey.toBuffer(),
+        mint1.toBuffer(),
+        bonusCounter.toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    const bonusVault = await getAssociatedTokenAddress(mint1, bonusCollection, true);
+    const [bonusReplacementMint] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from("replacement_mint"),
+        bonusCollection.toBuffer(),
+      ],
+      program.programId
+    );
+    const [bonusUserState] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from("user_state"),
+        bonusCollection.toBuffer(),
+        user.publicKey.toBuffer(),
+      ],
+      program.programId
+    );
+
+    // Commits before the bonus starts decaying count double
+    const now = Math.floor(Date.now() / 1000);
+    await program.methods
+      .initCollection(
+        bonusCounter,
+        MAX_TOKENS,
+        false,
+        false,
+        null,
+        null,
+        null,
+        new anchor.BN(0),
+        null,
+        null,
+        {
+          startTs: new anchor.BN(now + 3600),
+          endTs: new anchor.BN(now + 7200),
+          startMultiplierBps: new anchor.BN(20000),
+        }
+      )
+      .accounts({
+        collection: bonusCollection,
+        mint: mint1,
+        vault: bonusVault,
+        replacementMint: bonusReplacementMint,
+        authority: authority.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        eventAuthority,
+        program: program.programId,
+      })
+      .rpc();
+
+    const userBonusReplacementTokenAccount = await getAssociatedTokenAddress(
+      bonusReplacementMint,
+      user.publicKey
+    );
+    await program.methods
+      .userCommitToCollection(new anchor.BN(100), null)
+      .accounts({
+        collection: bonusCollection,
+        collectionMint: null,
+        userState: bonusUserState,
+        mint: mint1,
+        userTokenAccount: userTokenAccount1,
+        vault: bonusVault,
+        replacementMint: bonusReplacementMint,
+        userReplacementTokenAccount: userBonusReplacementTokenAccount,
+        user: user.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        eventAuthority,
+        program: program.programId,
+      })
+      .signers([user])
+      .rpc();
+
+    const bonusUserStateAccount = await program.account.collectionUserState.fetch(
+      bonusUserState
+    );
+    assert.equal(bonusUserStateAccount.depositedAmount.toString(), "200");
+    const bonusCollectionAccount = await program.account.collection.fetch(bonusCollection);
+    assert.equal(bonusCollectionAccount.lifetimeTokensCollected.toString(), "200");
+    // Replacement tokens are still minted 1:1
+    const replacementAccount = await getAccount(
+      provider.connection,
+      userBonusReplacementTokenAccount
+    );
+    assert.equal(replacementAccount.amount.toString(), "100");
+  });
+
+  it("Closes user states to reclaim their rent", async () => {
+    // The refund collection of the previous test, where the user has 60 tokens left
+    const refundCounter = new anchor.BN(5);
+    const [refundCollection] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from("collection"),
+        authority.publicKey.toBuffer(),
+        mint1.toBuffer(),
+        refundCounter.toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    const [refundReplacementMint] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from("replacement_mint"),
+        refundCollection.toBuffer(),
+      ],
+      program.programId
+    );
+    const [refundUserState] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from("user_state"),
+        refundCollection.toBuffer(),
+        user.publicKey.toBuffer(),
+      ],
+      program.programId
+    );
+    const closeAccounts = {
+      collection: refundCollection,
+      userState: refundUserState,
+      user: user.publicKey,
+      eventAuthority,
+      program: program.programId,
+    };
+
+    try {
+      await program.methods
+        .closeCollectionUserState()
+        .accounts(closeAccounts)
+        .signers([user])
+        .rpc();
+      assert.fail("closing a state with deposits should fail");
+    } catch (err) {
+      assert.equal((err as anchor.AnchorError).error.errorCode.code, "DepositOutstanding");
+    }
+
+    await program.methods
+      .userRefundFromCollection(new anchor.BN(60))
+      .accounts({
+        collection: refundCollection,
+        userState: refundUserState,
+        userTokenAccount: userTokenAccount1,
+        vault: await getAssociatedTokenAddress(mint1, refundCollection, true),
+        mint: mint1,
+        replacementMint: refundReplacementMint,
+        userReplacementTokenAccount: await getAssociatedTokenAddress(
+          refundReplacementMint,
+          user.publicKey
+        ),
+        user: user.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        eventAuthority,
+        program: program.programId,
+      })
+      .signers([user])
+      .rpc();
+
+    await program.methods
+      .closeCollectionUserState()
+      .accounts(closeAccounts)
+      .signers([user])
+      .rpc();
+    assert.isNull(await provider.connection.getAccountInfo(refundUserState));
+
+    // The user received tokens from this distribution, which never expires
+    try {
+      await program.methods
+        .closeDistributionUserState()
+        .accounts({
+          distribution: distribution1,
+          distributionUserState: distribution1UserState,
+          user: user.publicKey,
+          eventAuthority,
+          program: program.programId,
+        })
+        .signers([user])
+        .rpc();
+      assert.fail("closing a state of a running distribution should fail");
+    } catch (err) {
+      assert.equal(
+        (err as anchor.AnchorError).error.errorCode.code,
+        "DistributionUserStateInUse"
+      );
+    }
+  });
+
+  it("Transfers the collection authority in two steps", async () => {
+    await program.methods
+      .proposeAuthority(user.publicKey)
+      .accounts({
+        collection,
+        authority: authority.publicKey,
+        eventAuthority,
+        program: program.programId,
+      })
+      .rpc();
+
+    let collectionAccount = await program.account.collection.fetch(collection);
+    assert.equal(collectionAccount.pendingAuthority.toString(), user.publicKey.toString());
+
+    await program.methods
+      .acceptAuthority()
+      .accounts({
+        collection,
+        newAuthority: user.publicKey,
+        eventAuthority,
+        program: program.programId,
+      })
+      .signers([user])
+      .rpc();
+
+    collectionAccount = await program.account.collection.fetch(collection);
+    assert.equal(collectionAccount.authority.toString(), user.publicKey.toString());
+    assert.equal(collectionAccount.creator.toString(), authority.publicKey.toString());
+    assert.isNull(collectionAccount.pendingAuthority);
+
+    // The previous authority has lost control
+    try {
+      await program.methods
+        .proposeAuthority(authority.publicKey)
+        .accounts({
+          collection,
+          authority: authority.publicKey,
+          eventAuthority,
+          program: program.programId,
+        })
+        .rpc();
+      assert.fail("proposal by the previous authority should fail");
+    } catch (err) {
+      assert.equal((err as anchor.AnchorError).error.errorCode.code, "ConstraintHasOne");
+    }
+
+    // Hand control back
+    await program.methods
+      .proposeAuthority(authority.publicKey)
+      .accounts({
+        collection,
+        authority: user.publicKey,
+        eventAuthority,
+        program: program.programId,
+      })
+      .signers([user])
+      .rpc();
+    await program.methods
+      .acceptAuthority()
+      .accounts({
+        collection,
+        newAuthority: authority.publicKey,
+        eventAuthority,
+        program: program.programId,
+      })
+      .rpc();
+
+    collectionAccount = await program.account.collection.fetch(collection);
+    assert.equal(collectionAccount.authority.toString(), authority.publicKey.toString());
+  });
+
+  it("Pauses claims from a distribution", async () => {
+    // Let the user act as guardian
+    await program.methods
+      .setCollectionGuardian(user.publicKey)
+      .accounts({
+        collection,
+        authority: authority.publicKey,
+        eventAuthority,
+        program: program.programId,
+      })
+      .rpc();
+
+    await program.methods
+      .setDistributionPaused(true)
+      .accounts({
+        collection,
+        distribution: distribution2,
+        signer: user.publicKey,
+        eventAuthority,
+        program: program.programId,
+      })
+      .signers([user])
+      .rpc();
+
+    try {
+      await program.methods
+        .userClaimFromDistribution()
+        .accounts({
+          collection,
+          distribution: distribution2,
+          collectionUserState: userState,
+          distributionUserState: distribution2UserState,
+          distributionVault: distribution2Vault,
+          distributionMint: mint2,
+          userTokenAccount: userTokenAccount2,
+          user: user.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          eventAuthority,
+          program: program.programId,
+        })
+        .signers([user])
+        .rpc();
+      assert.fail("claim from a paused distribution should fail");
+    } catch (err) {
+      assert.equal((err as anchor.AnchorError).error.errorCode.code, "Paused");
+    }
+
+    await program.methods
+      .setDistributionPaused(false)
+      .accounts({
+        collection,
+        distribution: distribution2,
+        signer: authority.publicKey,
+        eventAuthority,
+        program: program.programId,
+      })
+      .rpc();
+
+    const distribution2Account = await program.account.distribution.fetch(distribution2);
+    assert.isFalse(distribution2Account.paused);
+  });
+
+  it("Claims on behalf of a user", async () => {
+    // The user holds 50 of the 1000 maximum tokens of the limited collection. Claims
+    // on a user's behalf wait for the refund window, which this collection hasn't.
+    const [limitedCollection] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from("collection"),
+        authority.publicKey.toBuffer(),
+        mint1.toBuffer(),
+        new anchor.BN(8).toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    const [limitedUserState] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from("user_state"),
+        limitedCollection.toBuffer(),
+        user.publicKey.toBuffer(),
+      ],
+      program.programId
+    );
+    const [crankDistribution] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from("distribution"),
+        limitedCollection.toBuffer(),
+        new anchor.BN(0).toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    const [crankDistributionUserState] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from("distribution_user_state"),
+        crankDistribution.toBuffer(),
+        user.publicKey.toBuffer(),
+      ],
+      program.programId
+    );
+    const crankDistributionVault = await getAssociatedTokenAddress(
+      mint2,
+      crankDistribution,
+      true
+    );
+
+    await program.methods
+      .initDistribution(null, null)
+      .accounts({
+        distribution: crankDistribution,
+        collection: limitedCollection,
+        mint: mint2,
+        vault: crankDistributionVault,
+        authority: authority.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        eventAuthority,
+        program: program.programId,
+      })
+      .rpc();
+
+    await program.methods
+      .addDistributionTokens(new anchor.BN(100))
+      .accounts({
+        distribution: crankDistribution,
+        vault: crankDistributionVault,
+        mint: mint2,
+        authorityTokenAccount: authorityTokenAccount2,
+        authority: authority.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        eventAuthority,
+        program: program.programId,
+      })
+      .rpc();
+
+    const userAccount2BeforeClaim = await getAccount(
+      provider.connection,
+      userTokenAccount2
+    );
+
+    // The authority pays, the user doesn't sign
+    await program.methods
+      .claimFromDistributionForUser()
+      .accounts({
+        collection: limitedCollection,
+        distribution: crankDistribution,
+        collectionUserState: limitedUserState,
+        distributionUserState: crankDistributionUserState,
+        distributionVault: crankDistributionVault,
+        userTokenAccount: userTokenAccount2,
+        distributionMint: mint2,
+        user: user.publicKey,
+        payer: authority.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        eventAuthority,
+        program: program.programId,
+      })
+      .rpc();
+
+    const userAccount2AfterClaim = await getAccount(
+      provider.connection,
+      userTokenAccount2
+    );
+    assert.equal(
+      userAccount2BeforeClaim.amount + BigInt(5),
+      userAccount2AfterClaim.amount
+    );
+
+    const crankDistributionUserStateAccount = await program.account.distributionUserState.fetch(
+      crankDistributionUserState
+    );
+    assert.equal(crankDistributionUserStateAccount.receivedAmount.toString(), "5");
+  });
+
+  it("Claims from several distributions at once", async () => {
+    // The user holds 60 of the 1000 maximum tokens of the refund collection
+    const [refundCollection] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from("collection"),
+        authority.publicKey.toBuffer(),
+        mint1.toBuffer(),
+        new anchor.BN(5).toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    const [refundUserState] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from("user_state"),
+        refundCollection.toBuffer(),
+        user.publicKey.toBuffer(),
+      ],
+      program.programId
+    );
+
+    const batchDistributions: {
+      distribution: PublicKey;
+      mint: PublicKey;
+      vault: PublicKey;
+      userState: PublicKey;
+      userTokenAccount: PublicKey;
+    }[] = [];
+    // Distributions are numbered in the order they were created
+    for (const [mint, userTokenAccount, id] of [
+      [mint1, userTokenAccount1, 1],
+      [mint2, userTokenAccount2, 0],
+    ] as [PublicKey, PublicKey, number][]) {
+      const [distribution] = await PublicKey.findProgramAddress(
+        [
+          Buffer.from("distribution"),
+          refundCollection.toBuffer(),
+          new anchor.BN(id).toArrayLike(Buffer, "le", 8),
+        ],
+        program.programId
+      );
+      const [userState] = await PublicKey.findProgramAddress(
+        [
+          Buffer.from("distribution_user_state"),
+          distribution.toBuffer(),
+          user.publicKey.toBuffer(),
+        ],
+        program.programId
+      );
+      const vault = await getAssociatedTokenAddress(mint, distribution, true);
+      batchDistributions.push({
+        distribution,
+        mint,
+        vault,
+        userState,
+        userTokenAccount,
+      });
+    }
+
+    // Created in id order
+    for (const d of [batchDistributions[1], batchDistributions[0]]) {
+      await program.methods
+        .initDistribution(null, null)
+        .accounts({
+          distribution: d.distribution,
+          collection: refundCollection,
+          mint: d.mint,
+          vault: d.vault,
+          authority: authority.publicKey,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+          eventAuthority,
+          program: program.programId,
+        })
+        .rpc();
+    }
+
+    for (const [index, authorityTokenAccount] of [
+      authorityTokenAccount1,
+      authorityTokenAccount2,
+    ].entries()) {
+      await program.methods
+        .addDistributionTokens(new anchor.BN(200))
+        .accounts({
+          distribution: batchDistributions[index].distribution,
+          vault: batchDistributions[index].vault,
+          mint: batchDistributions[index].mint,
+          authorityTokenAccount,
+          authority: authority.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          eventAuthority,
+          program: program.programId,
+        })
+        .rpc();
+    }
+
+    const userAccount1BeforeClaim = await getAccount(
+      provider.connection,
+      userTokenAccount1
+    );
+    const userAccount2BeforeClaim = await getAccount(
+      provider.connection,
+      userTokenAccount2
+    );
+
+    await program.methods
+      .userClaimFromDistributions()
+      .accounts({
+        collection: refundCollection,
+        collectionUserState: refundUserState,
+        user: user.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        eventAuthority,
+        program: program.programId,
+      })
+      .remainingAccounts(
+        batchDistributions.flatMap((d) =>
+          [
+            { pubkey: d.distribution, isSigner: false, isWritable: true },
+            { pubkey: d.mint, isSigner: false, isWritable: false },
+            { pubkey: d.vault, isSigner: false, isWritable: true },
+            { pubkey: d.userState, isSigner: false, isWritable: true },
+            { pubkey: d.userTokenAccount, isSigner: false, isWritable: true },
+          ]
+        )
+      )
+      .signers([user])
+      .rpc();
+
+    // 60 / 1000 of 200 tokens each
+    const userAccount1AfterClaim = await getAccount(
+      provider.connection,
+      userTokenAccount1
+    );
+    const userAccount2AfterClaim = await getAccount(
+      provider.connection,
+      userTokenAccount2
+    );
+    assert.equal(
+      userAccount1BeforeClaim.amount + BigInt(12),
+      userAccount1AfterClaim.amount
+    );
+    assert.equal(
+      userAccount2BeforeClaim.amount + BigInt(12),
+      userAccount2AfterClaim.amount
+    );
+
+    for (const d of batchDistributions) {
+      const userStateAccount = await program.account.distributionUserState.fetch(
+        d.userState
+      );
+      assert.equal(userStateAccount.receivedAmount.toString(), "12");
+    }
+  });
+
+  it("Credits what arrives for Token-2022 mints with a transfer fee", async () => {
+    // 1% transfer fee
+    const feeMintKeypair = anchor.web3.Keypair.generate();
+    const feeMint = feeMintKeypair.publicKey;
+    const mintLen = getMintLen([ExtensionType.TransferFeeConfig]);
+    await provider.sendAndConfirm(
+      new Transaction().add(
+        SystemProgram.createAccount({
+          fromPubkey: authority.publicKey,
+          newAccountPubkey: feeMint,
+          space: mintLen,
+          lamports: await provider.connection.getMinimumBalanceForRentExemption(
+            mintLen
+          ),
+          programId: TOKEN_2022_PROGRAM_ID,
+        }),
+        createInitializeTransferFeeConfigInstruction(
+          feeMint,
+          authority.publicKey,
+          authority.publicKey,
+          100,
+          BigInt(1000000),
+          TOKEN_2022_PROGRAM_ID
+        ),
+        createInitializeMintInstruction(
+          feeMint,
+          6,
+          authority.publicKey,
+          null,
+          TOKEN_2022_PROGRAM_ID
+        )
+      ),
+      [feeMintKeypair]
+    );
+
+    const userFeeTokenAccount = await createAccount(
+      provider.connection,
+      authority.payer,
+      feeMint,
+      user.publicKey,
+      undefined,
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
+    await mintTo(
+      provider.connection,
+      authority.payer,
+      feeMint,
+      userFeeTokenAccount,
+      authority.publicKey,
+      1000,
+      [],
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
+
+    const feeCounter = new anchor.BN(6);
+    const [feeCollection] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from("collection"),
+        authority.publicKey.toBuffer(),
+        feeMint.toBuffer(),
+        feeCounter.toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    const feeVault = await getAssociatedTokenAddress(
+      feeMint,
+      feeCollection,
+      true,
+      TOKEN_2022_PROGRAM_ID
+    );
+    const [feeReplacementMint] = await PublicKey.findProgramAddress(
+      [Buffer.from("replacement_mint"), feeCollection.toBuffer()],
+      program.programId
+    );
+    const [feeUserState] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from("user_state"),
+        feeCollection.toBuffer(),
+        user.publicKey.toBuffer(),
+      ],
+      program.programId
+    );
+    const userFeeReplacementTokenAccount = await getAssociatedTokenAddress(
+      feeReplacementMint,
+      user.publicKey,
+      false,
+      TOKEN_2022_PROGRAM_ID
+    );
+
+    await program.methods
+      .initCollection(feeCounter, MAX_TOKENS, false, false, null, null, null, new anchor.BN(0), null, null, null)
+      .accounts({
+        collection: feeCollection,
+        mint: feeMint,
+        vault: feeVault,
+        replacementMint: feeReplacementMint,
+        authority: authority.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        tokenProgram: TOKEN_2022_PROGRAM_ID,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        eventAuthority,
+        program: program.programId,
+      })
+      .rpc();
+
+    await program.methods
+      .userCommitToCollection(new anchor.BN(100), null)
+      .accounts({
+        collection: feeCollection,
+        collectionMint: null,
+        userState: feeUserState,
+        mint: feeMint,
+        userTokenAccount: userFeeTokenAccount,
+        vault: feeVault,
+        replacementMint: feeReplacementMint,
+        userReplacementTokenAccount: userFeeReplacementTokenAccount,
+        user: user.publicKey,
+        tokenProgram: TOKEN_2022_PROGRAM_ID,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        eventAuthority,
+        program: program.programId,
+      })
+      .signers([user])
+      .rpc();
+
+    // One token of the 100 is withheld as fee
+    const feeCollectionAccount = await program.account.collection.fetch(feeCollection);
+    const feeUserStateAccount = await program.account.collectionUserState.fetch(
+      feeUserState
+    );
+    assert.equal(feeCollectionAccount.lifetimeTokensCollected.toString(), "99");
+    assert.equal(feeUserStateAccount.depositedAmount.toString(), "99");
+    assert.equal(
+      (
+        await getAccount(
+          provider.connection,
+          userFeeReplacementTokenAccount,
+          undefined,
+          TOKEN_2022_PROGRAM_ID
+        )
+      ).amount,
+      BigInt(99)
+    );
+  });
+
+  it("Sets and updates the replacement mint metadata", async () => {
+    const TOKEN_METADATA_PROGRAM_ID = new PublicKey(
+      "metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bHNkRwBE"
+    );
+    const [metadata] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from("metadata"),
+        TOKEN_METADATA_PROGRAM_ID.toBuffer(),
+        replacementMint.toBuffer(),
+      ],
+      TOKEN_METADATA_PROGRAM_ID
+    );
+
+    for (const name of ["Committed Token", "Renamed Token"]) {
+      await program.methods
+        .setReplacementMintMetadata(name, "cTKN", "https://example.com/ctkn.json")
+        .accounts({
+          collection,
+          replacementMint,
+          metadata,
+          authority: authority.publicKey,
+          tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          eventAuthority,
+          program: program.programId,
+        })
+        .rpc();
+
+      const metadataAccount = await provider.connection.getAccountInfo(metadata);
+      assert.isTrue(metadataAccount.owner.equals(TOKEN_METADATA_PROGRAM_ID));
+      assert.isTrue(metadataAccount.data.includes(Buffer.from(name)));
+    }
+  });
 });